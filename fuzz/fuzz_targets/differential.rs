@@ -0,0 +1,36 @@
+//! A generated program prints the same on the interpreter and on the C
+//! backend, compiled with `$CC` or `cc`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::process::{Command, Stdio};
+use touka::generator::Generator;
+
+fuzz_target!(|data: &[u8]| {
+    let file = Generator::new(data).file();
+
+    let mut interpreted = vec![];
+    touka::interpret(&file, &mut interpreted).expect("a generated program failed on the interpreter");
+
+    let c = touka::compile_to_c(&file).expect("a generated program doesn't compile to C");
+
+    let dir = std::env::temp_dir().join(format!("touka-fuzz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (source, binary) = (dir.join("program.c"), dir.join("program"));
+    std::fs::write(&source, c).unwrap();
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    // The flags of `compile_c` in the tests, the runtime runs the program
+    // on a thread with a bigger stack.
+    let compiled = Command::new(cc).arg("-w").arg("-o").arg(&binary).arg(&source).arg("-lpthread").output().unwrap();
+    assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+
+    let native = Command::new(&binary).stdin(Stdio::null()).output().unwrap();
+    assert!(native.status.success(), "{}", String::from_utf8_lossy(&native.stderr));
+    assert_eq!(
+        String::from_utf8_lossy(&interpreted),
+        String::from_utf8_lossy(&native.stdout),
+        "the interpreter and C diverge"
+    );
+});
@@ -0,0 +1,11 @@
+//! The parser never panics, whatever the text.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = touka::parser::parse_or_report("<fuzz>", text);
+    }
+});
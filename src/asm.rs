@@ -0,0 +1,500 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::ast::BinaryOp;
+use crate::bytecode::{Op, Program};
+use crate::vm::{Kind, Value};
+
+/// The name of the kind in the runtime.
+fn kind(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Str => "KIND_STR",
+        Kind::Int => "KIND_INT",
+        Kind::Bool => "KIND_BOOL",
+        Kind::Tuple => "KIND_TUPLE",
+        Kind::Record => "KIND_RECORD",
+        Kind::List => "KIND_LIST",
+        Kind::Function => "KIND_FUNCTION",
+    }
+}
+
+/// The operand of `.ascii` for the bytes, the assembler has no escapes
+/// beyond the octal ones.
+fn quote(text: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in text {
+        match byte {
+            b'"' | b'\\' => write!(quoted, "\\{}", byte as char),
+            0x20..=0x7e => write!(quoted, "{}", byte as char),
+            _ => write!(quoted, "\\{byte:03o}"),
+        }
+        .unwrap();
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Where a local of the running function is, its kind and then its
+/// payload 8 bytes above. The captured values are kept at `rbp - 8`.
+fn local(slot: usize) -> usize {
+    16 * (slot + 2)
+}
+
+/// The assembly of one function, the paths to the runtime errors are
+/// written after its body so that the checks fall through.
+struct Function<'p> {
+    program: &'p Program,
+    code: usize,
+    body: String,
+    errors: String,
+    failures: usize,
+}
+
+impl Function<'_> {
+    fn label(&self, ip: usize) -> String {
+        format!(".L{}_{ip}", self.code)
+    }
+
+    /// A jump to a new error path of the function, the path is the
+    /// call of a runtime routine that doesn't return.
+    fn fail(&mut self, ip: usize, condition: &str, path: &[String]) {
+        let label = format!(".L{}_{ip}_{}", self.code, self.failures);
+        self.failures += 1;
+        writeln!(self.body, "        {condition} {label}").unwrap();
+        writeln!(self.errors, "{label}:").unwrap();
+        for line in path {
+            writeln!(self.errors, "        {line}").unwrap();
+        }
+    }
+
+    /// An error of a kind the top doesn't have, the kind is at `found`.
+    fn fail_kind(&mut self, ip: usize, condition: &str, at: usize, fault: &str, message: &str, found: &str) {
+        self.fail(
+            ip,
+            condition,
+            &[
+                format!("lea rdi, [rip + .Lat{at}]"),
+                format!("lea rsi, [rip + fault_{fault}]"),
+                format!("lea rdx, [rip + text_{message}]"),
+                format!("mov rcx, {found}"),
+                "call rt_fail_kind".into(),
+            ],
+        );
+    }
+
+    fn push(&mut self, kind: &str, payload: &str) {
+        writeln!(self.body, "        mov qword ptr [r15], {kind}").unwrap();
+        writeln!(self.body, "        mov qword ptr [r15 + 8], {payload}").unwrap();
+        writeln!(self.body, "        add r15, 16").unwrap();
+    }
+
+    /// Copies a value between two places, through rax and rcx.
+    fn copy(&mut self, to: &str, from: &str) {
+        writeln!(self.body, "        mov rax, [{from}]").unwrap();
+        writeln!(self.body, "        mov rcx, [{from} + 8]").unwrap();
+        writeln!(self.body, "        mov [{to}], rax").unwrap();
+        writeln!(self.body, "        mov [{to} + 8], rcx").unwrap();
+    }
+
+    /// Calls a runtime routine with the location in rdi, it's where a
+    /// full heap is reported.
+    fn routine(&mut self, name: &str, at: usize) {
+        writeln!(self.body, "        lea rdi, [rip + .Lat{at}]").unwrap();
+        writeln!(self.body, "        mov [rip + location], rdi").unwrap();
+        writeln!(self.body, "        call {name}").unwrap();
+    }
+
+    fn binary(&mut self, ip: usize, op: BinaryOp, at: usize) {
+        let (slow, done) = (format!("{}_slow", self.label(ip)), format!("{}_done", self.label(ip)));
+        let body = &mut self.body;
+
+        match op {
+            BinaryOp::Add => {
+                writeln!(body, "        cmp qword ptr [r15 - 32], KIND_INT").unwrap();
+                writeln!(body, "        jne {slow}").unwrap();
+                writeln!(body, "        cmp qword ptr [r15 - 16], KIND_INT").unwrap();
+                writeln!(body, "        jne {slow}").unwrap();
+                writeln!(body, "        mov eax, [r15 - 24]").unwrap();
+                writeln!(body, "        add eax, [r15 - 8]").unwrap();
+                writeln!(body, "        sub r15, 16").unwrap();
+                writeln!(body, "        mov [r15 - 8], rax").unwrap();
+                writeln!(body, "        jmp {done}").unwrap();
+                writeln!(body, "{slow}:").unwrap();
+                self.routine("rt_add", at);
+                writeln!(self.body, "{done}:").unwrap();
+            }
+
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                let divide = format!("{}_divide", self.label(ip));
+                let path = [
+                    format!("lea rdi, [rip + .Lat{at}]"),
+                    "lea rsi, [rip + fault_invalid_operands]".into(),
+                    "lea rdx, [rip + text_arithmetic]".into(),
+                    "mov rcx, [r15 - 32]".into(),
+                    "mov r8, [r15 - 16]".into(),
+                    "call rt_fail_kinds".into(),
+                ];
+                writeln!(self.body, "        cmp qword ptr [r15 - 32], KIND_INT").unwrap();
+                self.fail(ip, "jne", &path);
+                writeln!(self.body, "        cmp qword ptr [r15 - 16], KIND_INT").unwrap();
+                self.fail(ip, "jne", &path);
+                writeln!(self.body, "        mov eax, [r15 - 24]").unwrap();
+
+                match op {
+                    BinaryOp::Sub => writeln!(self.body, "        sub eax, [r15 - 8]").unwrap(),
+                    BinaryOp::Mul => writeln!(self.body, "        imul eax, [r15 - 8]").unwrap(),
+                    _ => {
+                        writeln!(self.body, "        mov ecx, [r15 - 8]").unwrap();
+                        writeln!(self.body, "        test ecx, ecx").unwrap();
+                        self.fail(
+                            ip,
+                            "jz",
+                            &[
+                                format!("lea rdi, [rip + .Lat{at}]"),
+                                "lea rsi, [rip + fault_division_by_zero]".into(),
+                                "lea rdx, [rip + text_division_by_zero]".into(),
+                                "call rt_fail_msg".into(),
+                            ],
+                        );
+
+                        // The one division that overflows wraps, like the others.
+                        writeln!(self.body, "        cmp ecx, -1").unwrap();
+                        writeln!(self.body, "        jne {divide}").unwrap();
+                        writeln!(self.body, "        neg eax").unwrap();
+                        if op == BinaryOp::Rem {
+                            writeln!(self.body, "        xor eax, eax").unwrap();
+                        }
+                        writeln!(self.body, "        jmp {done}").unwrap();
+                        writeln!(self.body, "{divide}:").unwrap();
+                        writeln!(self.body, "        cdq").unwrap();
+                        writeln!(self.body, "        idiv ecx").unwrap();
+                        if op == BinaryOp::Rem {
+                            writeln!(self.body, "        mov eax, edx").unwrap();
+                        }
+                        writeln!(self.body, "{done}:").unwrap();
+                    }
+                }
+
+                writeln!(self.body, "        sub r15, 16").unwrap();
+                writeln!(self.body, "        mov [r15 - 8], rax").unwrap();
+            }
+
+            _ => {
+                let (code, set) = match op {
+                    BinaryOp::Eq => (0, "sete"),
+                    BinaryOp::Neq => (1, "setne"),
+                    BinaryOp::Lt => (2, "setl"),
+                    BinaryOp::Gt => (3, "setg"),
+                    BinaryOp::Lte => (4, "setle"),
+                    _ => (5, "setge"),
+                };
+
+                writeln!(body, "        cmp qword ptr [r15 - 32], KIND_INT").unwrap();
+                writeln!(body, "        jne {slow}").unwrap();
+                writeln!(body, "        cmp qword ptr [r15 - 16], KIND_INT").unwrap();
+                writeln!(body, "        jne {slow}").unwrap();
+                writeln!(body, "        xor eax, eax").unwrap();
+                writeln!(body, "        mov ecx, [r15 - 24]").unwrap();
+                writeln!(body, "        cmp ecx, [r15 - 8]").unwrap();
+                writeln!(body, "        {set} al").unwrap();
+                writeln!(body, "        sub r15, 16").unwrap();
+                writeln!(body, "        mov qword ptr [r15 - 16], KIND_BOOL").unwrap();
+                writeln!(body, "        mov [r15 - 8], rax").unwrap();
+                writeln!(body, "        jmp {done}").unwrap();
+                writeln!(body, "{slow}:").unwrap();
+                writeln!(body, "        mov esi, {code}").unwrap();
+                self.routine("rt_compare", at);
+                writeln!(self.body, "{done}:").unwrap();
+            }
+        }
+    }
+
+    fn op(&mut self, ip: usize, op: &Op) {
+        let program = self.program;
+
+        writeln!(self.body, "{}:", self.label(ip)).unwrap();
+        match op {
+            Op::Const(idx) => match &program.constants[*idx] {
+                Value::Int(i) => self.push("KIND_INT", &i.to_string()),
+                Value::Bool(b) => self.push("KIND_BOOL", &(*b as u8).to_string()),
+                Value::Str(_) => {
+                    writeln!(self.body, "        lea rax, [rip + .Lc{idx}]").unwrap();
+                    self.push("KIND_STR", "rax");
+                }
+                value => unreachable!("no constants of kind {}", value.kind().name()),
+            },
+
+            Op::Load(slot) => {
+                self.copy("r15", &format!("rbp - {}", local(*slot)));
+                writeln!(self.body, "        add r15, 16").unwrap();
+            }
+
+            Op::Store(slot) => {
+                writeln!(self.body, "        sub r15, 16").unwrap();
+                self.copy(&format!("rbp - {}", local(*slot)), "r15");
+            }
+
+            Op::Env(idx) => {
+                writeln!(self.body, "        mov rdx, [rbp - 8]").unwrap();
+                self.copy("r15", &format!("rdx + {}", 24 + 16 * idx));
+                writeln!(self.body, "        add r15, 16").unwrap();
+            }
+
+            Op::Current => {
+                writeln!(self.body, "        mov rdx, [rbp - 8]").unwrap();
+                self.push("KIND_FUNCTION", "rdx");
+            }
+
+            Op::Pop => writeln!(self.body, "        sub r15, 16").unwrap(),
+
+            Op::Jump(target) => writeln!(self.body, "        jmp {}", self.label(*target)).unwrap(),
+
+            Op::Branch(target, at) => {
+                writeln!(self.body, "        sub r15, 16").unwrap();
+                writeln!(self.body, "        cmp qword ptr [r15], KIND_BOOL").unwrap();
+                self.fail_kind(ip, "jne", *at, "not_a_boolean", "expected_bool", "[r15]");
+                writeln!(self.body, "        cmp qword ptr [r15 + 8], 0").unwrap();
+                writeln!(self.body, "        je {}", self.label(*target)).unwrap();
+            }
+
+            Op::Truthy(at) => {
+                writeln!(self.body, "        cmp qword ptr [r15 - 16], KIND_BOOL").unwrap();
+                self.fail_kind(ip, "jne", *at, "not_a_boolean", "expected_bool", "[r15 - 16]");
+            }
+
+            Op::Expect(expected, at) => {
+                writeln!(self.body, "        cmp qword ptr [r15 - 16], {}", kind(*expected)).unwrap();
+                self.fail(
+                    ip,
+                    "jne",
+                    &[
+                        format!("lea rdi, [rip + .Lat{at}]"),
+                        format!("mov esi, {}", kind(*expected)),
+                        "mov rdx, [r15 - 16]".into(),
+                        "call rt_fail_expect".into(),
+                    ],
+                );
+            }
+
+            Op::Binary(op, at) => self.binary(ip, *op, *at),
+
+            Op::Closure(code) => {
+                let (arity, captures) = (program.codes[*code].arity, program.codes[*code].captures);
+
+                writeln!(self.body, "        mov edi, {}", 24 + 16 * captures).unwrap();
+                writeln!(self.body, "        call rt_alloc").unwrap();
+                writeln!(self.body, "        lea rcx, [rip + touka_{code}]").unwrap();
+                writeln!(self.body, "        mov [rax], rcx").unwrap();
+                writeln!(self.body, "        mov qword ptr [rax + 8], {arity}").unwrap();
+                writeln!(self.body, "        mov qword ptr [rax + 16], {captures}").unwrap();
+                writeln!(self.body, "        sub r15, {}", 16 * captures).unwrap();
+                for idx in 0..captures {
+                    for offset in [0, 8] {
+                        writeln!(self.body, "        mov rcx, [r15 + {}]", 16 * idx + offset).unwrap();
+                        writeln!(self.body, "        mov [rax + {}], rcx", 24 + 16 * idx + offset).unwrap();
+                    }
+                }
+                self.push("KIND_FUNCTION", "rax");
+            }
+
+            Op::Call(arity, at) => {
+                let function = 16 * (arity + 1);
+
+                // There's room for one more call, like `Deeper` checks.
+                writeln!(self.body, "        lea rax, [rip + .Lat{at}]").unwrap();
+                writeln!(self.body, "        mov [rip + location], rax").unwrap();
+                writeln!(self.body, "        cmp rsp, [rip + stack_end]").unwrap();
+                self.fail(
+                    ip,
+                    "jb",
+                    &[
+                        "mov rdi, rax".into(),
+                        "lea rsi, [rip + fault_stack_overflow]".into(),
+                        "lea rdx, [rip + text_too_deep]".into(),
+                        "call rt_fail_msg".into(),
+                    ],
+                );
+                writeln!(self.body, "        cmp qword ptr [r15 - {function}], KIND_FUNCTION").unwrap();
+                self.fail_kind(
+                    ip,
+                    "jne",
+                    *at,
+                    "not_a_function",
+                    "expected_function",
+                    &format!("[r15 - {function}]"),
+                );
+                writeln!(self.body, "        mov r14, [r15 - {}]", function - 8).unwrap();
+                writeln!(self.body, "        cmp qword ptr [r14 + 8], {arity}").unwrap();
+                self.fail(
+                    ip,
+                    "jne",
+                    &[
+                        format!("lea rdi, [rip + .Lat{at}]"),
+                        "mov rsi, [r14 + 8]".into(),
+                        format!("mov edx, {arity}"),
+                        "call rt_fail_arity".into(),
+                    ],
+                );
+                writeln!(self.body, "        call [r14]").unwrap();
+            }
+
+            Op::Return => {
+                writeln!(self.body, "        leave").unwrap();
+                writeln!(self.body, "        ret").unwrap();
+            }
+
+            Op::Tuple => writeln!(self.body, "        call rt_tuple").unwrap(),
+            Op::First(at) => self.routine("rt_first", *at),
+            Op::Second(at) => self.routine("rt_second", *at),
+            Op::Untuple(at) => self.routine("rt_untuple", *at),
+
+            Op::Record(names) => {
+                writeln!(self.body, "        mov edi, {}", names.len()).unwrap();
+                writeln!(self.body, "        lea rsi, [rip + .Lnames{}_{ip}]", self.code).unwrap();
+                writeln!(self.body, "        call rt_record").unwrap();
+            }
+
+            Op::Field(_, at) => {
+                writeln!(self.body, "        lea rsi, [rip + .Lfield{}_{ip}]", self.code).unwrap();
+                self.routine("rt_field", *at);
+            }
+
+            Op::List(size) => {
+                writeln!(self.body, "        mov edi, {size}").unwrap();
+                writeln!(self.body, "        call rt_list").unwrap();
+            }
+
+            Op::Cons(at) => self.routine("rt_cons", *at),
+            Op::Head(at) => self.routine("rt_head", *at),
+            Op::Tail(at) => self.routine("rt_tail", *at),
+            Op::Len(at) => self.routine("rt_len", *at),
+            Op::Substr(at) => self.routine("rt_substr", *at),
+            Op::ToInt(at) => self.routine("rt_to_int", *at),
+            Op::ToStr(at) => self.routine("rt_to_str", *at),
+            Op::Contains => writeln!(self.body, "        call rt_contains").unwrap(),
+            Op::Print(_) => writeln!(self.body, "        call rt_print").unwrap(),
+            Op::ReadLine(at) => self.routine("rt_read_line", *at),
+            Op::Argv => writeln!(self.body, "        call rt_argv").unwrap(),
+
+            Op::Test(slot, idx, target) => {
+                let (at, target) = (local(*slot), self.label(*target));
+                let body = &mut self.body;
+
+                match &program.constants[*idx] {
+                    Value::Int(i) => {
+                        writeln!(body, "        cmp qword ptr [rbp - {at}], KIND_INT").unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                        writeln!(body, "        cmp dword ptr [rbp - {}], {i}", at - 8).unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                    }
+                    Value::Bool(b) => {
+                        writeln!(body, "        cmp qword ptr [rbp - {at}], KIND_BOOL").unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                        writeln!(body, "        cmp qword ptr [rbp - {}], {}", at - 8, *b as u8).unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                    }
+                    Value::Str(_) => {
+                        writeln!(body, "        cmp qword ptr [rbp - {at}], KIND_STR").unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                        writeln!(body, "        mov rdi, [rbp - {}]", at - 8).unwrap();
+                        writeln!(body, "        lea rsi, [rip + .Lc{idx}]").unwrap();
+                        writeln!(body, "        call rt_strcmp").unwrap();
+                        writeln!(body, "        test eax, eax").unwrap();
+                        writeln!(body, "        jne {target}").unwrap();
+                    }
+                    value => unreachable!("no patterns of kind {}", value.kind().name()),
+                }
+            }
+
+            Op::TestTuple(slot, target) => {
+                writeln!(self.body, "        cmp qword ptr [rbp - {}], KIND_TUPLE", local(*slot)).unwrap();
+                writeln!(self.body, "        jne {}", self.label(*target)).unwrap();
+            }
+
+            Op::NoMatch(at) => {
+                writeln!(self.body, "        lea rsi, [rip + fault_no_match]").unwrap();
+                writeln!(self.body, "        lea rdx, [rip + text_no_match]").unwrap();
+                self.routine("rt_fail_msg", *at);
+            }
+        }
+    }
+}
+
+/// Writes the program as GNU assembler for x86-64 Linux, with the
+/// runtime in front. It's linked without libc:
+///
+/// ```sh
+/// as output.s -o output.o && ld output.o -o output
+/// ```
+pub fn write(program: &Program, output: &mut impl Write) -> io::Result<()> {
+    writeln!(output, "{}", include_str!("yamero.s"))?;
+
+    writeln!(output, "        .section .rodata")?;
+    for (idx, location) in program.locations.iter().enumerate() {
+        writeln!(output, ".Lat{idx}: .asciz {}", quote(location.as_bytes()))?;
+    }
+
+    for (idx, constant) in program.constants.iter().enumerate() {
+        if let Value::Str(s) = constant {
+            writeln!(output, "        .balign 8")?;
+            writeln!(output, ".Lc{idx}: .quad {}", s.len())?;
+            writeln!(output, "        .ascii {}", quote(s))?;
+        }
+    }
+
+    // The names of the fields of each record literal and field access.
+    for (code, function) in program.codes.iter().enumerate() {
+        for (ip, op) in function.ops.iter().enumerate() {
+            if let Op::Field(name, _) = op {
+                writeln!(output, ".Lfield{code}_{ip}: .asciz {}", quote(name.as_bytes()))?;
+            }
+
+            if let Op::Record(names) = op {
+                for (idx, name) in names.iter().enumerate() {
+                    writeln!(output, ".Lname{code}_{ip}_{idx}: .asciz {}", quote(name.as_bytes()))?;
+                }
+
+                writeln!(output, "        .balign 8")?;
+                writeln!(output, ".Lnames{code}_{ip}:")?;
+                for idx in 0..names.len() {
+                    writeln!(output, "        .quad .Lname{code}_{ip}_{idx}")?;
+                }
+            }
+        }
+    }
+
+    writeln!(output, "        .text")?;
+    writeln!(output, "        .set touka_main, touka_{}", program.entry)?;
+
+    for (code, function) in program.codes.iter().enumerate() {
+        let mut asm = Function {
+            program,
+            code,
+            body: String::new(),
+            errors: String::new(),
+            failures: 0,
+        };
+
+        // The arguments are moved from the stack of values to the locals,
+        // and the function itself is dropped.
+        writeln!(output, "\n# {}", function.name)?;
+        writeln!(output, "touka_{code}:")?;
+        writeln!(output, "        push rbp")?;
+        writeln!(output, "        mov rbp, rsp")?;
+        writeln!(output, "        sub rsp, {}", 16 * (function.locals + 1))?;
+        writeln!(output, "        mov [rbp - 8], r14")?;
+        for slot in 0..function.arity {
+            let from = format!("r15 - {}", 16 * (function.arity - slot));
+            asm.copy(&format!("rbp - {}", local(slot)), &from);
+        }
+        writeln!(asm.body, "        sub r15, {}", 16 * (function.arity + 1)).unwrap();
+
+        for (ip, op) in function.ops.iter().enumerate() {
+            asm.op(ip, op);
+        }
+
+        write!(output, "{}{}", asm.body, asm.errors)?;
+    }
+
+    Ok(())
+}
@@ -1,9 +1,16 @@
-use std::{fmt::Debug, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    rc::Rc,
+    sync::Arc,
+};
 
 /// File definition, it contains all the statements,
 /// the module name, and a base location for it as anchor
 /// for the statements.
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct File {
     pub name: String,
     pub expression: Term,
@@ -22,27 +29,243 @@ impl<T: Element> Element for Box<T> {
     }
 }
 
-#[derive(Default, Hash, PartialEq, Eq, Clone, )]
+#[derive(Default, Hash, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(into = "RawLocation", from = "RawLocation")]
 pub struct Location {
     pub start: usize,
     pub end: usize,
-    pub filename: String,
+    pub file: FileId,
 }
 
 impl Location {
     /// Creates a new instance of [`Location`].
-    pub fn new(start: usize, end: usize, filename: &str) -> Self {
+    pub fn new(start: usize, end: usize, file: FileId) -> Self {
+        Self { start, end, file }
+    }
+
+    /// The name of the file this location points to.
+    pub fn filename(&self) -> String {
+        self.file.name()
+    }
+
+    /// The 1-based line and column where the location starts.
+    pub fn line_col(&self) -> (usize, usize) {
+        self.file.line_col(self.start)
+    }
+
+    /// The 1-based line and column where the location ends.
+    pub fn end_line_col(&self) -> (usize, usize) {
+        self.file.line_col(self.end)
+    }
+}
+
+impl Debug for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, column) = self.line_col();
+        let (end_line, end_column) = self.end_line_col();
+
+        write!(
+            f,
+            "{}:{line}:{column}-{end_line}:{end_column}",
+            self.filename()
+        )
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, column) = self.line_col();
+
+        write!(f, "{}:{line}:{column}", self.filename())
+    }
+}
+
+/// The location as written in the JSON AST, the byte offsets are the
+/// source of truth, lines and columns are there for the readers.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawLocation {
+    start: usize,
+    end: usize,
+    filename: String,
+
+    #[serde(default, skip_deserializing)]
+    line: usize,
+    #[serde(default, skip_deserializing)]
+    column: usize,
+    #[serde(default, skip_deserializing)]
+    end_line: usize,
+    #[serde(default, skip_deserializing)]
+    end_column: usize,
+}
+
+impl From<Location> for RawLocation {
+    fn from(value: Location) -> Self {
+        let (line, column) = value.line_col();
+        let (end_line, end_column) = value.end_line_col();
+
         Self {
-            start,
-            end,
-            filename: filename.into(),
+            start: value.start,
+            end: value.end,
+            filename: value.filename(),
+            line,
+            column,
+            end_line,
+            end_column,
         }
     }
 }
 
-impl Debug for Location {
+impl From<RawLocation> for Location {
+    fn from(value: RawLocation) -> Self {
+        let file = READING.with(|reading| match &mut *reading.borrow_mut() {
+            Some(sources) => sources.file(&value.filename),
+            None => FileId::new(&value.filename, ""),
+        });
+
+        Self::new(value.start, value.end, file)
+    }
+}
+
+/// A source file, the locations that point to it keep its text. Two ids
+/// are the same file when they're from the same [`FileId::new`].
+#[derive(Clone, Default)]
+pub struct FileId(Option<Arc<SourceFile>>);
+
+impl PartialEq for FileId {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+}
+
+impl Eq for FileId {}
+
+impl Hash for FileId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ref().map(Arc::as_ptr).hash(state)
+    }
+}
+
+impl Debug for FileId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Location")
+        f.debug_tuple("FileId").field(&self.name()).finish()
+    }
+}
+
+impl FileId {
+    /// Creates a new file. A file with the name of another one is still
+    /// a file of its own, the files are never changed.
+    pub fn new(name: &str, text: &str) -> Self {
+        Self(Some(Arc::new(SourceFile {
+            name: name.to_string(),
+            lines: LineIndex::new(text),
+        })))
+    }
+
+    /// The name of the file.
+    pub fn name(&self) -> String {
+        self.0
+            .as_ref()
+            .map_or_else(|| "<unknown>".to_string(), |file| file.name.clone())
+    }
+
+    /// The file as a source for the diagnostics.
+    pub fn named_source(&self) -> miette::NamedSource {
+        match &self.0 {
+            Some(file) => miette::NamedSource::new(&file.name, file.lines.text.clone()),
+            None => miette::NamedSource::new("<unknown>", String::new()),
+        }
+    }
+
+    /// The 1-based line and column of a byte offset of the file.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        self.0
+            .as_ref()
+            .map_or((1, offset + 1), |file| file.lines.line_col(offset))
+    }
+}
+
+struct SourceFile {
+    name: String,
+    lines: LineIndex,
+}
+
+/// The files of a JSON AST by their names, the JSON has the names only.
+/// A name that isn't in the map is a file without a text, its locations
+/// have the offsets but every one is at the start of the file.
+#[derive(Default, Clone)]
+pub struct SourceMap {
+    files: BTreeMap<String, FileId>,
+}
+
+thread_local! {
+    /// The files of the JSON AST being read, serde has no way to hand
+    /// them to the locations.
+    static READING: RefCell<Option<SourceMap>> = const { RefCell::new(None) };
+}
+
+impl SourceMap {
+    /// Adds a file, replacing the one with its name.
+    pub fn insert(&mut self, file: FileId) {
+        self.files.insert(file.name(), file);
+    }
+
+    /// The file with the given name, a file without a text if there's
+    /// none, that the next ones with the name are too.
+    fn file(&mut self, name: &str) -> FileId {
+        self.files
+            .entry(name.to_string())
+            .or_insert_with(|| FileId::new(name, ""))
+            .clone()
+    }
+
+    /// Reads the locations of a JSON AST into these files.
+    pub(crate) fn reading<T>(&self, read: impl FnOnce() -> T) -> T {
+        let previous = READING.with(|reading| reading.replace(Some(self.clone())));
+        let result = read();
+        READING.with(|reading| reading.replace(previous));
+
+        result
+    }
+}
+
+/// Maps byte offsets of a source text to lines and columns.
+#[derive(Default, Debug, Clone)]
+pub struct LineIndex {
+    text: String,
+
+    /// The byte offset where every line starts.
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    /// Creates a new instance of [`LineIndex`] for the given text.
+    pub fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            text: text.to_string(),
+            line_starts,
+        }
+    }
+
+    /// The 1-based line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self
+            .line_starts
+            .partition_point(|start| *start <= offset)
+            .max(1);
+        let start = self.line_starts.get(line - 1).copied().unwrap_or_default();
+        let column = self
+            .text
+            .get(start..offset.min(self.text.len()))
+            .map_or(offset - start, |prefix| prefix.chars().count());
+
+        (line, column + 1)
     }
 }
 
@@ -58,7 +281,7 @@ pub trait Element {
 }
 
 /// Error node, it does contains an error.
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Error {
     /// The error message.
     pub message: String,
@@ -76,7 +299,7 @@ impl Element for Error {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct If {
     pub condition: Box<Term>,
     pub then: Box<Term>,
@@ -84,16 +307,149 @@ pub struct If {
     pub location: Location,
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Let {
     pub name: crate::parser::Var,
+
+    /// The type annotation, like `let x: Int = 1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ty: Option<Type>,
+    pub value: Box<Term>,
+    pub next: Box<Term>,
+    pub location: Location,
+}
+
+/// A binding that takes a value apart, like `let (a, b) = pair; next`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Destructure {
+    pub pattern: Pattern,
+    pub value: Box<Term>,
+    pub next: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Destructure {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The shape of a value, binding its parts to names.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind")]
+pub enum Pattern {
+    Var(crate::parser::Var),
+    Wildcard(Wildcard),
+    Int(Int),
+    Str(Str),
+    Bool(Bool),
+    Tuple(TuplePattern),
+}
+
+impl Pattern {
+    /// The names bound by the pattern, from left to right.
+    pub fn variables(&self) -> Vec<&crate::parser::Var> {
+        match self {
+            Pattern::Var(name) => vec![name],
+            Pattern::Wildcard(_) | Pattern::Int(_) | Pattern::Str(_) | Pattern::Bool(_) => vec![],
+            Pattern::Tuple(t) => {
+                let mut variables = t.first.variables();
+                variables.extend(t.second.variables());
+                variables
+            }
+        }
+    }
+}
+
+impl Element for Pattern {
+    fn location(&self) -> &Location {
+        match self {
+            Pattern::Var(arg0) => arg0.location(),
+            Pattern::Wildcard(arg0) => &arg0.location,
+            Pattern::Int(arg0) => &arg0.location,
+            Pattern::Str(arg0) => &arg0.location,
+            Pattern::Bool(arg0) => &arg0.location,
+            Pattern::Tuple(arg0) => &arg0.location,
+        }
+    }
+}
+
+/// The pattern that matches anything without binding it, `_`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Wildcard {
+    pub location: Location,
+}
+
+/// A pattern for a [`Tuple`], like `(a, b)`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TuplePattern {
+    pub first: Box<Pattern>,
+    pub second: Box<Pattern>,
+    pub location: Location,
+}
+
+/// Match picks the first arm whose pattern matches the value, like
+/// `match n { 0 => "zero", _ => "many" }`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Match {
     pub value: Box<Term>,
+    pub arms: Vec<Arm>,
+    pub location: Location,
+}
+
+impl Element for Match {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// An arm of a [`Match`], like `(0, _) => "zero"`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub value: Term,
+}
+
+/// The type of a value, like `Int` or `Str`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Type {
+    Int,
+    Str,
+    Bool,
+}
+
+/// An external declaration, it binds a name to a C function like
+/// `external hash: (Str) => Int = "djb2"; next`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct External {
+    pub name: crate::parser::Var,
+
+    /// The C symbol, it's the name itself unless one is given.
+    pub symbol: String,
+    pub parameters: Vec<Type>,
+    pub returns: Type,
+    pub next: Box<Term>,
+    pub location: Location,
+}
+
+/// An import of another file, like `import "math.rinha" as math; next`.
+///
+/// The module is the record of the top-level bindings of the file, the
+/// imports are resolved by the [`crate::loader`] before the code is
+/// generated. The last expression of an imported file takes the place of
+/// the record, it isn't evaluated, so it has to be a value that does
+/// nothing, like `0`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Import {
+    /// The path of the file, relative to the importing one.
+    pub path: String,
+    pub name: crate::parser::Var,
     pub next: Box<Term>,
     pub location: Location,
 }
 
 /// Int is a integer value like `0`, `1`, `2`, etc.
-#[derive(Default, Debug, Clone, )]
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Str {
     pub value: String,
 
@@ -107,7 +463,7 @@ impl Element for Str {
     }
 }
 
-#[derive(Default, Debug, Clone, )]
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Bool {
     pub value: bool,
     pub location: Location,
@@ -120,7 +476,7 @@ impl Element for Bool {
 }
 
 /// Int is a integer value like `0`, `1`, `2`, etc.
-#[derive(Default, Debug, Clone, )]
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Int {
     /// The value of the integer.
     pub value: i32,
@@ -135,7 +491,7 @@ impl Element for Int {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum BinaryOp {
     Add, // Add
     Sub, // Subtract
@@ -152,7 +508,7 @@ pub enum BinaryOp {
     Or,  // Or
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Binary {
     pub lhs: Box<Term>,
     pub op: BinaryOp,
@@ -166,7 +522,7 @@ impl Element for Binary {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Call {
     pub callee: Box<Term>,
     pub arguments: Vec<Term>,
@@ -179,9 +535,13 @@ impl Element for Call {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Function {
     pub parameters: Vec<crate::parser::Var>,
+
+    /// The type annotation of each parameter, like `fn (a: Int) => a`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Option<Type>>,
     pub value: Box<Term>,
     pub location: Location,
 }
@@ -192,7 +552,7 @@ impl Element for Function {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Print {
     pub value: Box<Term>,
     pub location: Location,
@@ -204,7 +564,7 @@ impl Element for Print {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct First {
     pub value: Box<Term>,
     pub location: Location,
@@ -216,7 +576,7 @@ impl Element for First {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Second {
     pub value: Box<Term>,
     pub location: Location,
@@ -228,7 +588,7 @@ impl Element for Second {
     }
 }
 
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Tuple {
     pub first: Box<Term>,
     pub second: Box<Term>,
@@ -241,7 +601,192 @@ impl Element for Tuple {
     }
 }
 
-#[derive(Debug, Clone, )]
+/// List is a sequence of values like `[1, 2, 3]`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct List {
+    pub elements: Vec<Term>,
+    pub location: Location,
+}
+
+impl Element for List {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The first element of a [`List`], like `head([1, 2])`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Head {
+    pub value: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Head {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// A [`List`] without its first element, like `tail([1, 2])`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tail {
+    pub value: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Tail {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The number of elements of a [`List`] or the length of a string, like
+/// `len([1, 2])`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Len {
+    pub value: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Len {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// A [`List`] with another element in front, like `cons(0, [1, 2])`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Cons {
+    pub head: Box<Term>,
+    pub tail: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Cons {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// Part of a string, like `substr("touka", 1, 3)`: the characters
+/// from the index, as many as the length.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Substr {
+    pub value: Box<Term>,
+    pub start: Box<Term>,
+    pub length: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Substr {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The number written in a string, like `to_int("42")`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ToInt {
+    pub value: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for ToInt {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The string of an int, a bool or a string, as it's printed, like
+/// `to_str(42)`. The other values can't be converted, it's a runtime
+/// error.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ToStr {
+    pub value: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for ToStr {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// Whether a string has another in it, like `contains("touka", "ou")`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Contains {
+    pub value: Box<Term>,
+    pub pattern: Box<Term>,
+    pub location: Location,
+}
+
+impl Element for Contains {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// A line of the standard input, without the line break, `read_line()`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReadLine {
+    pub location: Location,
+}
+
+impl Element for ReadLine {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// The command-line arguments of the program as a [`List`] of strings,
+/// `argv()`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Argv {
+    pub location: Location,
+}
+
+impl Element for Argv {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// A field of a [`Record`], like `name: value`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Field {
+    pub name: crate::parser::Var,
+    pub value: Term,
+}
+
+/// Record is a set of named values like `{ x: 1, y: 2 }`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Record {
+    pub fields: Vec<Field>,
+    pub location: Location,
+}
+
+impl Element for Record {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+/// Access to a field of a [`Record`], like `point.x`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FieldAccess {
+    pub value: Box<Term>,
+    pub name: crate::parser::Var,
+    pub location: Location,
+}
+
+impl Element for FieldAccess {
+    fn location(&self) -> &Location {
+        &self.location
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind")]
 pub enum Term {
     Error(Error),
     Int(Int),
@@ -250,12 +795,29 @@ pub enum Term {
     Binary(Binary),
     Function(Function),
     Let(Let),
+    Destructure(Destructure),
+    External(External),
+    Import(Import),
     If(If),
+    Match(Match),
     Print(Print),
     First(First),
     Second(Second),
     Bool(Bool),
     Tuple(Tuple),
+    List(List),
+    Head(Head),
+    Tail(Tail),
+    Len(Len),
+    Cons(Cons),
+    Substr(Substr),
+    ToInt(ToInt),
+    ToStr(ToStr),
+    Contains(Contains),
+    ReadLine(ReadLine),
+    Argv(Argv),
+    Record(Record),
+    FieldAccess(FieldAccess),
     Var(crate::parser::Var),
 }
 
@@ -273,9 +835,26 @@ impl Element for Term {
             Term::First(arg0) => &arg0.location,
             Term::Second(arg0) => &arg0.location,
             Term::Let(arg0) => &arg0.location,
+            Term::Destructure(arg0) => &arg0.location,
+            Term::External(arg0) => &arg0.location,
+            Term::Import(arg0) => &arg0.location,
             Term::If(arg0) => &arg0.location,
+            Term::Match(arg0) => &arg0.location,
             Term::Bool(arg0) => &arg0.location,
             Term::Tuple(arg0) => arg0.location(),
+            Term::List(arg0) => arg0.location(),
+            Term::Head(arg0) => &arg0.location,
+            Term::Tail(arg0) => &arg0.location,
+            Term::Len(arg0) => &arg0.location,
+            Term::Cons(arg0) => &arg0.location,
+            Term::Substr(arg0) => &arg0.location,
+            Term::ToInt(arg0) => &arg0.location,
+            Term::ToStr(arg0) => &arg0.location,
+            Term::Contains(arg0) => &arg0.location,
+            Term::ReadLine(arg0) => &arg0.location,
+            Term::Argv(arg0) => &arg0.location,
+            Term::Record(arg0) => arg0.location(),
+            Term::FieldAccess(arg0) => arg0.location(),
         }
     }
 }
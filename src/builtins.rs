@@ -0,0 +1,189 @@
+//! The builtins are ordinary names. A call of one that isn't bound, like
+//! `len(xs)`, is the builtin, and a binding hides it, like `let len = 3;`
+//! or `fn (head) => head`. The parser reads them as any other call, and
+//! they're resolved right after, in the scopes of the file.
+
+use crate::ast::{
+    Argv, Cons, Contains, First, Head, Len, Location, Pattern, ReadLine, Second, Substr, Tail, Term, ToInt, ToStr,
+};
+use crate::parser::InnerError;
+
+/// The builtins and how many arguments they take.
+const BUILTINS: &[(&str, usize)] = &[
+    ("first", 1),
+    ("second", 1),
+    ("head", 1),
+    ("tail", 1),
+    ("len", 1),
+    ("cons", 2),
+    ("substr", 3),
+    ("to_int", 1),
+    ("to_str", 1),
+    ("contains", 2),
+    ("read_line", 0),
+    ("argv", 0),
+];
+
+#[derive(Default)]
+struct Resolver {
+    /* the names in scope, the innermost last */
+    bound: Vec<String>,
+    errors: Vec<InnerError>,
+}
+
+/// Turns the calls of the builtins into their terms, with the errors of
+/// the ones called with the wrong number of arguments.
+pub fn resolve(term: &mut Term) -> Vec<InnerError> {
+    let mut resolver = Resolver::default();
+    resolver.term(term);
+    resolver.errors
+}
+
+/// The term of a builtin, its arguments are already checked.
+fn builtin(name: &str, arguments: Vec<Term>, location: Location) -> Term {
+    let mut arguments = arguments.into_iter().map(Box::new);
+    let mut next = || arguments.next().unwrap();
+
+    match name {
+        "first" => Term::First(First { value: next(), location }),
+        "second" => Term::Second(Second { value: next(), location }),
+        "head" => Term::Head(Head { value: next(), location }),
+        "tail" => Term::Tail(Tail { value: next(), location }),
+        "len" => Term::Len(Len { value: next(), location }),
+        "cons" => Term::Cons(Cons { head: next(), tail: next(), location }),
+        "substr" => Term::Substr(Substr { value: next(), start: next(), length: next(), location }),
+        "to_int" => Term::ToInt(ToInt { value: next(), location }),
+        "to_str" => Term::ToStr(ToStr { value: next(), location }),
+        "contains" => Term::Contains(Contains { value: next(), pattern: next(), location }),
+        "read_line" => Term::ReadLine(ReadLine { location }),
+        "argv" => Term::Argv(Argv { location }),
+        _ => unreachable!("`{name}` isn't a builtin"),
+    }
+}
+
+/// The names a pattern binds.
+fn variables(pattern: &Pattern) -> Vec<String> {
+    pattern.variables().into_iter().map(|var| var.text.clone()).collect()
+}
+
+impl Resolver {
+    /// Resolves a term with some more names in scope.
+    fn scoped<'a>(&mut self, names: impl IntoIterator<Item = &'a String>, term: &mut Term) {
+        let depth = self.bound.len();
+        self.bound.extend(names.into_iter().cloned());
+        self.term(term);
+        self.bound.truncate(depth);
+    }
+
+    fn term(&mut self, term: &mut Term) {
+        match term {
+            Term::Error(_) | Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) => {}
+            Term::ReadLine(_) | Term::Argv(_) => {}
+
+            Term::Call(call) => {
+                self.term(&mut call.callee);
+                for argument in &mut call.arguments {
+                    self.term(argument);
+                }
+
+                let Term::Var(callee) = &*call.callee else {
+                    return;
+                };
+                let Some(&(name, arity)) = BUILTINS.iter().find(|(name, _)| *name == callee.text) else {
+                    return;
+                };
+                if self.bound.iter().any(|bound| bound == name) {
+                    return;
+                }
+
+                if call.arguments.len() != arity {
+                    self.errors.push(InnerError::BuiltinArguments {
+                        name: name.to_string(),
+                        expected: arity,
+                        found: call.arguments.len(),
+                        err_span: call.location.clone().into(),
+                    });
+                    return;
+                }
+
+                let arguments = std::mem::take(&mut call.arguments);
+                *term = builtin(name, arguments, call.location.clone());
+            }
+
+            Term::Binary(binary) => {
+                self.term(&mut binary.lhs);
+                self.term(&mut binary.rhs);
+            }
+            Term::Function(function) => {
+                let parameters: Vec<_> = function.parameters.iter().map(|var| var.text.clone()).collect();
+                self.scoped(&parameters, &mut function.value);
+            }
+
+            // A function sees its own name, it can call itself.
+            Term::Let(r) => {
+                let name = [r.name.text.clone()];
+                match &*r.value {
+                    Term::Function(_) => self.scoped(&name, &mut r.value),
+                    _ => self.term(&mut r.value),
+                }
+                self.scoped(&name, &mut r.next);
+            }
+            Term::Destructure(d) => {
+                self.term(&mut d.value);
+                self.scoped(&variables(&d.pattern), &mut d.next);
+            }
+            Term::External(external) => self.scoped([&external.name.text], &mut external.next),
+            Term::Import(import) => self.scoped([&import.name.text], &mut import.next),
+
+            Term::If(r) => {
+                self.term(&mut r.condition);
+                self.term(&mut r.then);
+                self.term(&mut r.otherwise);
+            }
+            Term::Match(r) => {
+                self.term(&mut r.value);
+                for arm in &mut r.arms {
+                    self.scoped(&variables(&arm.pattern), &mut arm.value);
+                }
+            }
+
+            Term::Print(r) => self.term(&mut r.value),
+            Term::First(r) => self.term(&mut r.value),
+            Term::Second(r) => self.term(&mut r.value),
+            Term::Head(r) => self.term(&mut r.value),
+            Term::Tail(r) => self.term(&mut r.value),
+            Term::Len(r) => self.term(&mut r.value),
+            Term::ToInt(r) => self.term(&mut r.value),
+            Term::ToStr(r) => self.term(&mut r.value),
+            Term::Cons(r) => {
+                self.term(&mut r.head);
+                self.term(&mut r.tail);
+            }
+            Term::Substr(r) => {
+                self.term(&mut r.value);
+                self.term(&mut r.start);
+                self.term(&mut r.length);
+            }
+            Term::Contains(r) => {
+                self.term(&mut r.value);
+                self.term(&mut r.pattern);
+            }
+
+            Term::Tuple(r) => {
+                self.term(&mut r.first);
+                self.term(&mut r.second);
+            }
+            Term::List(r) => {
+                for element in &mut r.elements {
+                    self.term(element);
+                }
+            }
+            Term::Record(r) => {
+                for field in &mut r.fields {
+                    self.term(&mut field.value);
+                }
+            }
+            Term::FieldAccess(r) => self.term(&mut r.value),
+        }
+    }
+}
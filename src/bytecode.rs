@@ -0,0 +1,784 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{BinaryOp, Element, File as AstRoot, Function, Location, Pattern, Term, Type};
+use crate::gen::{GenError, Known};
+use crate::patterns::MatchWarning;
+use crate::vm::{Kind, Value};
+
+/// An instruction of the stack machine. The `usize` after the operands
+/// of the ones that can fail is the index of their location in
+/// [`Program::locations`].
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Pushes a constant.
+    Const(usize),
+    /// Pushes a local.
+    Load(usize),
+    /// Pops into a local.
+    Store(usize),
+    /// Pushes a captured value.
+    Env(usize),
+    /// Pushes the running function, for recursion.
+    Current,
+    Pop,
+
+    Jump(usize),
+    /// Pops a bool, jumps when it's false.
+    Branch(usize, usize),
+    /// Checks that the top is a bool.
+    Truthy(usize),
+    /// Checks the kind of the top, for the annotations and the builtins.
+    Expect(Kind, usize),
+    Binary(BinaryOp, usize),
+
+    /// Pops the captured values and pushes a function of the code.
+    Closure(usize),
+    /// Pops the arguments and the function, and calls it.
+    Call(usize, usize),
+    Return,
+
+    Tuple,
+    First(usize),
+    Second(usize),
+    /// Pops a tuple, pushes the first and then the second value.
+    Untuple(usize),
+    /// Pops the values of the fields.
+    Record(Rc<[Rc<str>]>),
+    Field(Rc<str>, usize),
+
+    /// Pops the elements.
+    List(usize),
+    Cons(usize),
+    Head(usize),
+    Tail(usize),
+    Len(usize),
+
+    Substr(usize),
+    ToInt(usize),
+    ToStr(usize),
+    Contains,
+
+    /// Prints the top, it stays on the stack.
+    Print(usize),
+    ReadLine(usize),
+    Argv,
+
+    /// Jumps when the local isn't the constant.
+    Test(usize, usize, usize),
+    /// Jumps when the local isn't a tuple.
+    TestTuple(usize, usize),
+    NoMatch(usize),
+}
+
+impl Op {
+    /// The index of the location of the instructions that can fail.
+    pub fn at(&self) -> Option<usize> {
+        match self {
+            Op::Branch(_, at)
+            | Op::Truthy(at)
+            | Op::Expect(_, at)
+            | Op::Binary(_, at)
+            | Op::Call(_, at)
+            | Op::First(at)
+            | Op::Second(at)
+            | Op::Untuple(at)
+            | Op::Field(_, at)
+            | Op::Cons(at)
+            | Op::Head(at)
+            | Op::Tail(at)
+            | Op::Len(at)
+            | Op::Substr(at)
+            | Op::ToInt(at)
+            | Op::ToStr(at)
+            | Op::Print(at)
+            | Op::ReadLine(at)
+            | Op::NoMatch(at) => Some(*at),
+            _ => None,
+        }
+    }
+}
+
+/// The code of a function.
+#[derive(Debug, Default)]
+pub struct Code {
+    pub name: String,
+    pub arity: usize,
+    pub captures: usize,
+    /* the parameters are the first ones */
+    pub locals: usize,
+    pub ops: Vec<Op>,
+}
+
+/// A compiled program, the entry is a function without parameters.
+#[derive(Debug, Default)]
+pub struct Program {
+    pub constants: Vec<Value>,
+    pub codes: Vec<Code>,
+    pub locations: Vec<String>,
+    pub entry: usize,
+}
+
+/// Where a name is found, from the function that uses it.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Local(usize),
+    Env(usize),
+    Current,
+}
+
+/// A function being compiled.
+#[derive(Default)]
+struct Scope {
+    code: Code,
+    /* bound names, the innermost last */
+    names: Vec<(String, usize)>,
+    /* captured names, and where they are in the enclosing function */
+    captures: Vec<(String, Source)>,
+    /* the name the function is bound to */
+    name: Option<String>,
+    /* what's known of the locals, for the annotations */
+    known: HashMap<usize, Known>,
+}
+
+/// Compiles a file to bytecode, closures are converted the same way the
+/// C generator does it: a function captures what it uses from the
+/// enclosing ones when it's created.
+#[derive(Default)]
+pub struct Compiler {
+    program: Program,
+    scopes: Vec<Scope>,
+    warnings: Vec<MatchWarning>,
+    /* the indexes of the locations and the constants, every one is in the program once */
+    locations: HashMap<String, usize>,
+    constants: HashMap<Constant, usize>,
+}
+
+/// A constant of the program by its value, the literals are the only
+/// constants.
+#[derive(PartialEq, Eq, Hash)]
+enum Constant {
+    Int(i32),
+    Str(Rc<[u8]>),
+    Bool(bool),
+}
+
+impl Compiler {
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("no function to compile to")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let ops = &mut self.scope().code.ops;
+        ops.push(op);
+        ops.len() - 1
+    }
+
+    /// The index of the next instruction.
+    fn here(&mut self) -> usize {
+        self.scope().code.ops.len()
+    }
+
+    /// Points the jump at `op` to the next instruction.
+    fn patch(&mut self, op: usize) {
+        let here = self.here();
+        match &mut self.scope().code.ops[op] {
+            Op::Jump(target) | Op::Branch(target, _) | Op::Test(_, _, target) | Op::TestTuple(_, target) => {
+                *target = here
+            }
+            op => unreachable!("{op:?} doesn't jump"),
+        }
+    }
+
+    fn at(&mut self, location: &Location) -> usize {
+        let locations = &mut self.program.locations;
+        *self.locations.entry(location.to_string()).or_insert_with_key(|location| {
+            locations.push(location.clone());
+            locations.len() - 1
+        })
+    }
+
+    fn constant(&mut self, value: Value) -> usize {
+        let key = match &value {
+            Value::Int(i) => Constant::Int(*i),
+            Value::Str(s) => Constant::Str(s.clone()),
+            Value::Bool(b) => Constant::Bool(*b),
+            value => unreachable!("no constants of kind {}", value.kind().name()),
+        };
+
+        let constants = &mut self.program.constants;
+        *self.constants.entry(key).or_insert_with(|| {
+            constants.push(value);
+            constants.len() - 1
+        })
+    }
+
+    fn local(&mut self) -> usize {
+        let code = &mut self.scope().code;
+        code.locals += 1;
+        code.locals - 1
+    }
+
+    fn bind(&mut self, name: &str, slot: usize) {
+        self.scope().names.push((name.to_string(), slot));
+    }
+
+    fn unbind(&mut self, count: usize) {
+        let names = &mut self.scope().names;
+        names.truncate(names.len() - count);
+    }
+
+    /// Resolves a name as seen from the function at `depth`, capturing it
+    /// from the enclosing functions if needed.
+    fn resolve(&mut self, name: &str, depth: usize) -> Option<Source> {
+        let scope = &self.scopes[depth];
+
+        if let Some((_, slot)) = scope.names.iter().rev().find(|(other, _)| other == name) {
+            return Some(Source::Local(*slot));
+        }
+
+        if let Some(idx) = scope.captures.iter().position(|(other, _)| other == name) {
+            return Some(Source::Env(idx));
+        }
+
+        if scope.name.as_deref() == Some(name) {
+            return Some(Source::Current);
+        }
+
+        if depth == 0 {
+            return None;
+        }
+
+        let outer = self.resolve(name, depth - 1)?;
+        let captures = &mut self.scopes[depth].captures;
+        captures.push((name.to_string(), outer));
+
+        Some(Source::Env(captures.len() - 1))
+    }
+
+    fn load(&mut self, source: Source) {
+        match source {
+            Source::Local(slot) => self.emit(Op::Load(slot)),
+            Source::Env(idx) => self.emit(Op::Env(idx)),
+            Source::Current => self.emit(Op::Current),
+        };
+    }
+
+    /// What's known of a term, from the names bound where it is.
+    fn known(&self, term: &Term) -> Option<Known> {
+        let var = |name: &str| {
+            for scope in self.scopes.iter().rev() {
+                if let Some((_, slot)) = scope.names.iter().rev().find(|(other, _)| other == name) {
+                    return scope.known.get(slot).cloned();
+                }
+
+                if scope.name.as_deref() == Some(name) {
+                    return Some(Known::Function);
+                }
+            }
+
+            None
+        };
+
+        crate::gen::known(term, &var)
+    }
+
+    /// Checks the value on the stack against a type, at compile time when
+    /// it's known, otherwise at run time.
+    fn check(&mut self, value: &Term, ty: Type) -> Result<(), GenError> {
+        if !crate::gen::annotate(self.known(value), ty, value.location())? {
+            self.expect(ty, value.location());
+        }
+
+        Ok(())
+    }
+
+    fn expect(&mut self, ty: Type, location: &Location) {
+        let kind = match ty {
+            Type::Int => Kind::Int,
+            Type::Str => Kind::Str,
+            Type::Bool => Kind::Bool,
+        };
+
+        let at = self.at(location);
+        self.emit(Op::Expect(kind, at));
+    }
+
+    fn function(&mut self, f: &Function, name: Option<&str>) -> Result<(), GenError> {
+        self.scopes.push(Scope {
+            code: Code {
+                name: name.unwrap_or("<anonymous>").to_string(),
+                arity: f.parameters.len(),
+                locals: f.parameters.len(),
+                ..Default::default()
+            },
+            name: name.map(str::to_string),
+            ..Default::default()
+        });
+
+        for (idx, p) in f.parameters.iter().enumerate() {
+            self.bind(&p.text, idx);
+
+            if let Some(Some(ty)) = f.annotations.get(idx) {
+                self.emit(Op::Load(idx));
+                self.expect(*ty, &p.location);
+                self.emit(Op::Pop);
+                self.scope().known.insert(idx, Known::Type(*ty));
+            }
+        }
+
+        self.compile(&f.value)?;
+        self.emit(Op::Return);
+
+        let mut scope = self.scopes.pop().expect("no function to compile to");
+        scope.code.captures = scope.captures.len();
+        self.program.codes.push(scope.code);
+
+        for (_, source) in &scope.captures {
+            self.load(*source);
+        }
+        self.emit(Op::Closure(self.program.codes.len() - 1));
+
+        Ok(())
+    }
+
+    /// Binds the names of a `let` pattern to the parts of the value in
+    /// `slot`, returns how many names it bound.
+    fn destructure(&mut self, pattern: &Pattern, slot: usize) -> Result<usize, GenError> {
+        match pattern {
+            Pattern::Var(name) => {
+                self.bind(&name.text, slot);
+                Ok(1)
+            }
+
+            Pattern::Wildcard(_) => Ok(0),
+
+            Pattern::Int(_) | Pattern::Str(_) | Pattern::Bool(_) => Err(GenError::RefutablePattern {
+                source_code: pattern.location().file.named_source(),
+                err_span: pattern.location().clone().into(),
+            }),
+
+            Pattern::Tuple(t) => {
+                let (first, second) = self.untuple(slot, &t.location);
+                Ok(self.destructure(&t.first, first)? + self.destructure(&t.second, second)?)
+            }
+        }
+    }
+
+    /// Puts the parts of the tuple in `slot` in two new locals.
+    fn untuple(&mut self, slot: usize, location: &Location) -> (usize, usize) {
+        let (first, second) = (self.local(), self.local());
+        let at = self.at(location);
+
+        self.emit(Op::Load(slot));
+        self.emit(Op::Untuple(at));
+        self.emit(Op::Store(second));
+        self.emit(Op::Store(first));
+
+        (first, second)
+    }
+
+    /// Tests the value in `slot` against the pattern of an arm, the jumps
+    /// to the next arm are left in `fails`. Returns how many names it bound.
+    fn test(&mut self, pattern: &Pattern, slot: usize, fails: &mut Vec<usize>) -> usize {
+        let constant = match pattern {
+            Pattern::Var(name) => {
+                self.bind(&name.text, slot);
+                return 1;
+            }
+
+            Pattern::Wildcard(_) => return 0,
+
+            Pattern::Tuple(t) => {
+                fails.push(self.emit(Op::TestTuple(slot, 0)));
+                let (first, second) = self.untuple(slot, &t.location);
+                return self.test(&t.first, first, fails) + self.test(&t.second, second, fails);
+            }
+
+            Pattern::Int(i) => Value::Int(i.value),
+            Pattern::Str(s) => Value::Str(s.value.as_bytes().into()),
+            Pattern::Bool(b) => Value::Bool(b.value),
+        };
+
+        let constant = self.constant(constant);
+        fails.push(self.emit(Op::Test(slot, constant, 0)));
+        0
+    }
+
+    /// Compiles a term, leaving its value on the stack.
+    fn compile(&mut self, term: &Term) -> Result<(), GenError> {
+        macro_rules! unary {
+            ($value:expr, $op:expr) => {{
+                let at = self.at(term.location());
+                self.compile($value)?;
+                self.emit($op(at));
+            }};
+        }
+
+        match term {
+            Term::Int(i) => {
+                let constant = self.constant(Value::Int(i.value));
+                self.emit(Op::Const(constant));
+            }
+
+            Term::Str(s) => {
+                let constant = self.constant(Value::Str(s.value.as_bytes().into()));
+                self.emit(Op::Const(constant));
+            }
+
+            Term::Bool(b) => {
+                let constant = self.constant(Value::Bool(b.value));
+                self.emit(Op::Const(constant));
+            }
+
+            Term::Var(v) => match self.resolve(&v.text, self.scopes.len() - 1) {
+                Some(source) => self.load(source),
+                None => {
+                    return Err(GenError::UnboundVariable {
+                        name: v.text.clone(),
+                        source_code: v.location.file.named_source(),
+                        err_span: v.location.clone().into(),
+                    })
+                }
+            },
+
+            Term::If(i) => {
+                let at = self.at(i.condition.location());
+                self.compile(&i.condition)?;
+                let otherwise = self.emit(Op::Branch(0, at));
+                self.compile(&i.then)?;
+                let end = self.emit(Op::Jump(0));
+                self.patch(otherwise);
+                self.compile(&i.otherwise)?;
+                self.patch(end);
+            }
+
+            Term::Binary(b) if matches!(b.op, BinaryOp::And | BinaryOp::Or) => {
+                let (lhs_at, rhs_at) = (self.at(b.lhs.location()), self.at(b.rhs.location()));
+                let short = self.constant(Value::Bool(b.op == BinaryOp::Or));
+
+                // Short-circuit, the right side only runs when needed.
+                self.compile(&b.lhs)?;
+                let rhs = self.emit(Op::Branch(0, lhs_at));
+                if b.op == BinaryOp::And {
+                    self.compile(&b.rhs)?;
+                    self.emit(Op::Truthy(rhs_at));
+                    let end = self.emit(Op::Jump(0));
+                    self.patch(rhs);
+                    self.emit(Op::Const(short));
+                    self.patch(end);
+                } else {
+                    self.emit(Op::Const(short));
+                    let end = self.emit(Op::Jump(0));
+                    self.patch(rhs);
+                    self.compile(&b.rhs)?;
+                    self.emit(Op::Truthy(rhs_at));
+                    self.patch(end);
+                }
+            }
+
+            Term::Binary(b) => {
+                let at = self.at(&b.location);
+                self.compile(&b.lhs)?;
+                self.compile(&b.rhs)?;
+                self.emit(Op::Binary(b.op, at));
+            }
+
+            Term::Let(r) => {
+                match &*r.value {
+                    Term::Function(f) => self.function(f, Some(&r.name.text))?,
+                    value => self.compile(value)?,
+                }
+
+                let known = match r.ty {
+                    Some(ty) => {
+                        self.check(&r.value, ty)?;
+                        Some(Known::Type(ty))
+                    }
+                    None => self.known(&r.value),
+                };
+
+                let slot = self.local();
+                self.emit(Op::Store(slot));
+                if let Some(known) = known {
+                    self.scope().known.insert(slot, known);
+                }
+                self.bind(&r.name.text, slot);
+                self.compile(&r.next)?;
+                self.unbind(1);
+            }
+
+            Term::Destructure(d) => {
+                self.compile(&d.value)?;
+                let slot = self.local();
+                self.emit(Op::Store(slot));
+
+                let bound = self.destructure(&d.pattern, slot)?;
+                self.compile(&d.next)?;
+                self.unbind(bound);
+            }
+
+            Term::Match(m) => {
+                self.warnings.extend(crate::patterns::check(m));
+
+                self.compile(&m.value)?;
+                let slot = self.local();
+                self.emit(Op::Store(slot));
+
+                let mut ends = vec![];
+                for arm in &m.arms {
+                    let mut fails = vec![];
+                    let bound = self.test(&arm.pattern, slot, &mut fails);
+                    self.compile(&arm.value)?;
+                    self.unbind(bound);
+                    ends.push(self.emit(Op::Jump(0)));
+
+                    for fail in fails {
+                        self.patch(fail);
+                    }
+                }
+
+                let at = self.at(&m.location);
+                self.emit(Op::NoMatch(at));
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+
+            Term::Function(f) => self.function(f, None)?,
+
+            Term::Call(c) => {
+                let at = self.at(&c.location);
+                self.compile(&c.callee)?;
+                for argument in &c.arguments {
+                    self.compile(argument)?;
+                }
+                self.emit(Op::Call(c.arguments.len(), at));
+            }
+
+            Term::Print(p) => unary!(&p.value, Op::Print),
+
+            Term::Tuple(t) => {
+                self.compile(&t.first)?;
+                self.compile(&t.second)?;
+                self.emit(Op::Tuple);
+            }
+
+            Term::First(t) => unary!(&t.value, Op::First),
+            Term::Second(t) => unary!(&t.value, Op::Second),
+
+            Term::Record(r) => {
+                for field in &r.fields {
+                    self.compile(&field.value)?;
+                }
+                let names = r.fields.iter().map(|field| field.name.text.as_str().into());
+                self.emit(Op::Record(names.collect()));
+            }
+
+            Term::FieldAccess(access) => {
+                crate::gen::access(self.known(&access.value), access)?;
+
+                let at = self.at(&access.location);
+                self.compile(&access.value)?;
+                self.emit(Op::Field(access.name.text.as_str().into(), at));
+            }
+
+            Term::List(l) => {
+                for element in &l.elements {
+                    self.compile(element)?;
+                }
+                self.emit(Op::List(l.elements.len()));
+            }
+
+            Term::Cons(c) => {
+                let at = self.at(&c.location);
+                self.compile(&c.head)?;
+                self.compile(&c.tail)?;
+                self.emit(Op::Cons(at));
+            }
+
+            Term::Head(h) => unary!(&h.value, Op::Head),
+            Term::Tail(t) => unary!(&t.value, Op::Tail),
+            Term::Len(l) => unary!(&l.value, Op::Len),
+            Term::ToStr(t) => unary!(&t.value, Op::ToStr),
+
+            Term::Substr(sub) => {
+                let at = self.at(&sub.location);
+                self.compile(&sub.value)?;
+                self.check(&sub.value, Type::Str)?;
+                self.compile(&sub.start)?;
+                self.check(&sub.start, Type::Int)?;
+                self.compile(&sub.length)?;
+                self.check(&sub.length, Type::Int)?;
+                self.emit(Op::Substr(at));
+            }
+
+            Term::ToInt(t) => {
+                let at = self.at(&t.location);
+                self.compile(&t.value)?;
+                self.check(&t.value, Type::Str)?;
+                self.emit(Op::ToInt(at));
+            }
+
+            Term::Contains(c) => {
+                self.compile(&c.value)?;
+                self.check(&c.value, Type::Str)?;
+                self.compile(&c.pattern)?;
+                self.check(&c.pattern, Type::Str)?;
+                self.emit(Op::Contains);
+            }
+
+            Term::ReadLine(r) => {
+                let at = self.at(&r.location);
+                self.emit(Op::ReadLine(at));
+            }
+
+            Term::Argv(_) => {
+                self.emit(Op::Argv);
+            }
+
+            Term::External(ext) => {
+                return Err(GenError::UnsupportedExternal {
+                    source_code: ext.location.file.named_source(),
+                    err_span: ext.location.clone().into(),
+                })
+            }
+
+            Term::Import(import) => {
+                return Err(GenError::MisplacedImport {
+                    source_code: import.location.file.named_source(),
+                    err_span: import.location.clone().into(),
+                })
+            }
+
+            Term::Error(e) => {
+                return Err(GenError::InvalidTerm {
+                    message: e.message.clone(),
+                    source_code: e.location.file.named_source(),
+                    err_span: e.location.clone().into(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The warnings found while compiling, they are reported only once.
+    pub fn take_warnings(&mut self) -> Vec<MatchWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn compile_file(&mut self, source: &AstRoot) -> Result<Program, GenError> {
+        self.scopes.push(Scope {
+            code: Code {
+                name: "<main>".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        self.compile(&source.expression)?;
+        self.emit(Op::Return);
+
+        let scope = self.scopes.pop().expect("no function to compile to");
+        self.program.codes.push(scope.code);
+        self.program.entry = self.program.codes.len() - 1;
+
+        Ok(std::mem::take(&mut self.program))
+    }
+}
+
+/// The mnemonic of an operator in the listing.
+fn mnemonic(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Sub => "sub",
+        BinaryOp::Mul => "mul",
+        BinaryOp::Div => "div",
+        BinaryOp::Rem => "rem",
+        BinaryOp::Eq => "eq",
+        BinaryOp::Neq => "neq",
+        BinaryOp::Lt => "lt",
+        BinaryOp::Gt => "gt",
+        BinaryOp::Lte => "lte",
+        BinaryOp::Gte => "gte",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+    }
+}
+
+impl Program {
+    /// A constant as it's written in the listing, the strings quoted.
+    fn constant(&self, idx: usize) -> String {
+        match &self.constants[idx] {
+            Value::Str(s) => format!("{:?}", String::from_utf8_lossy(s)),
+            value => value.to_string(),
+        }
+    }
+
+    /// An instruction in the listing, without its location.
+    fn instruction(&self, op: &Op) -> String {
+        match op {
+            Op::Const(idx) => format!("const {}", self.constant(*idx)),
+            Op::Load(slot) => format!("load {slot}"),
+            Op::Store(slot) => format!("store {slot}"),
+            Op::Env(idx) => format!("env {idx}"),
+            Op::Current => "current".into(),
+            Op::Pop => "pop".into(),
+            Op::Jump(target) => format!("jump {target}"),
+            Op::Branch(target, _) => format!("branch {target}"),
+            Op::Truthy(_) => "truthy".into(),
+            Op::Expect(kind, _) => format!("expect {}", kind.name()),
+            Op::Binary(op, _) => mnemonic(*op).into(),
+            Op::Closure(code) => format!("closure {code}"),
+            Op::Call(arity, _) => format!("call {arity}"),
+            Op::Return => "return".into(),
+            Op::Tuple => "tuple".into(),
+            Op::First(_) => "first".into(),
+            Op::Second(_) => "second".into(),
+            Op::Untuple(_) => "untuple".into(),
+            Op::Record(names) => format!("record {}", names.join(", ")),
+            Op::Field(name, _) => format!("field {name}"),
+            Op::List(size) => format!("list {size}"),
+            Op::Cons(_) => "cons".into(),
+            Op::Head(_) => "head".into(),
+            Op::Tail(_) => "tail".into(),
+            Op::Len(_) => "len".into(),
+            Op::Substr(_) => "substr".into(),
+            Op::ToInt(_) => "to_int".into(),
+            Op::ToStr(_) => "to_str".into(),
+            Op::Contains => "contains".into(),
+            Op::Print(_) => "print".into(),
+            Op::ReadLine(_) => "read_line".into(),
+            Op::Argv => "argv".into(),
+            Op::Test(slot, idx, target) => format!("test {slot} {} else {target}", self.constant(*idx)),
+            Op::TestTuple(slot, target) => format!("test_tuple {slot} else {target}"),
+            Op::NoMatch(_) => "no_match".into(),
+        }
+    }
+}
+
+/// The listing of `--emit bytecode`, every instruction with the location
+/// of the ones that can fail aside.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, code) in self.codes.iter().enumerate() {
+            writeln!(
+                f,
+                "code {idx} {} (arity {}, captures {}, locals {}):",
+                code.name, code.arity, code.captures, code.locals
+            )?;
+
+            for (ip, op) in code.ops.iter().enumerate() {
+                let instruction = self.instruction(op);
+                match op.at() {
+                    Some(at) => writeln!(f, "  {ip:>4}  {instruction:<24}; {}", self.locations[at])?,
+                    None => writeln!(f, "  {ip:>4}  {instruction}")?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,675 +1,1461 @@
-use std::{collections::HashMap, error::Error, fs::File};
+use std::collections::BTreeMap;
 
-use std::io::Write;
+use std::io::{self, Write};
+use std::rc::Rc;
 
-type GenericResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
-use crate::ast::{File as AstRoot, Term};
+use crate::ast::{BinaryOp, Element, External, FieldAccess, File as AstRoot, Function, Location, Pattern, Term, Type};
+
+use miette::{NamedSource, SourceSpan};
+
+use crate::patterns::MatchWarning;
 
 const STR: u8 = 0xca;
 const INT: u8 = 0xfe;
 const MAYBE: u8 = 0xba;
-const UNKNOWN: u8 = 0xbe;
 const FN_MAIN: usize = 0x00;
 
-#[derive(Default)]
-pub struct State {
-    constants: HashMap<usize, (String, String)>,
-    types: HashMap<usize, u8>,
-    print_queue: Vec<usize>,
-    functions: HashMap<usize, usize>,
-    named_functions: HashMap<String, usize>,
-    scoped_variables: HashMap<usize, HashMap<String, usize>>,
-    /* function ID, Queue of Evaluatiions */
-    /* Of course, zero is main. */
-    evaluation_queue: HashMap<usize, Vec<String>>,
-    runtime_queue: HashMap<usize, String>,
-    it: usize,
+/// Compile-time errors of the C generator and the bytecode compiler.
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+pub enum GenError {
+    #[error("cannot find `{name}` in this scope")]
+    #[diagnostic(code(zu::unbound_variable), url(docsrs))]
+    UnboundVariable {
+        name: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("the record has no field `{name}`")]
+    #[diagnostic(code(zu::unknown_field), url(docsrs))]
+    UnknownField {
+        name: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+
+        #[help]
+        help: String,
+    },
+
+    #[error("expected {expected}, found {found}")]
+    #[diagnostic(code(zu::type_mismatch), url(docsrs))]
+    TypeMismatch {
+        expected: String,
+        found: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("`{symbol}` isn't a valid C symbol")]
+    #[diagnostic(code(zu::invalid_symbol), url(docsrs))]
+    InvalidSymbol {
+        symbol: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("a `let` can only take apart tuples")]
+    #[diagnostic(
+        code(zu::refutable_pattern),
+        url(docsrs),
+        help("use a `match` to test the value against a literal")
+    )]
+    RefutablePattern {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("external functions can only be called from the C backend")]
+    #[diagnostic(code(zu::unsupported_external), url(docsrs))]
+    UnsupportedExternal {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("imports are only allowed at the top level of a file")]
+    #[diagnostic(code(zu::misplaced_import), url(docsrs))]
+    MisplacedImport {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("can't generate code for an invalid term: {message}")]
+    #[diagnostic(code(zu::invalid_term), url(docsrs))]
+    InvalidTerm {
+        message: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+}
+
+/// The C type, the `Any` member and the kind of a [`Type`].
+fn c_repr(ty: Type) -> (&'static str, &'static str, &'static str) {
+    match ty {
+        Type::Int => ("int", "i", "i"),
+        Type::Str => ("char *", "s", "s"),
+        Type::Bool => ("int", "b", "b"),
+    }
 }
 
-trait IsPure {
-    fn is_pure(&self) -> bool;
+/// What's known of a value before it runs, the same the C generator finds
+/// in its slots. The bytecode compiler and the JavaScript emitter check
+/// the annotations and the fields of the records against it, it doesn't
+/// change the code they emit.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Known {
+    Type(Type),
+    Function,
+    /// A record with these fields.
+    Record(Rc<[String]>),
 }
 
-impl IsPure for Term {
-    fn is_pure(&self) -> bool {
-        matches!(self, Term::Bool(_) | Term::Int(_) | Term::Str(_))
+impl std::fmt::Display for Known {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Known::Type(ty) => write!(f, "{ty:?}"),
+            Known::Function => write!(f, "function"),
+            Known::Record(_) => write!(f, "record"),
+        }
     }
 }
 
-impl State {
-    fn bag_or_die(self: &mut Self, term: Term, parent: usize) -> Term {
-        match term {
-            Term::Print(p) => {
-                let id = self.inspect(&p.value, parent);
-                self.print_queue.push(id);
-                self.it += 1;
+/// What's known of a term, `var` says it of the names bound around it.
+pub(crate) fn known(term: &Term, var: &dyn Fn(&str) -> Option<Known>) -> Option<Known> {
+    let known = |term| known(term, var);
+
+    match term {
+        Term::Int(_) => Some(Known::Type(Type::Int)),
+        Term::Str(_) => Some(Known::Type(Type::Str)),
+        Term::Bool(_) => Some(Known::Type(Type::Bool)),
+        Term::Var(v) => var(&v.text),
+        Term::Function(_) => Some(Known::Function),
+        Term::Record(r) => Some(Known::Record(r.fields.iter().map(|field| field.name.text.clone()).collect())),
+        Term::Print(p) => known(&p.value),
+
+        Term::If(i) => match &*i.condition {
+            Term::Bool(b) if b.value => known(&i.then),
+            Term::Bool(_) => known(&i.otherwise),
+            _ => None,
+        },
+
+        Term::Binary(b) => {
+            let ty = |term| match known(term) {
+                Some(Known::Type(ty)) => Some(ty),
+                _ => None,
+            };
 
-                *p.value
-            }
+            let ty = match (b.op, ty(&b.lhs), ty(&b.rhs)) {
+                (BinaryOp::Add, Some(Type::Int), Some(Type::Int)) => Type::Int,
+                (BinaryOp::Add, Some(Type::Str), Some(Type::Str | Type::Int))
+                | (BinaryOp::Add, Some(Type::Int), Some(Type::Str)) => Type::Str,
+                (BinaryOp::Add, _, _) => return None,
+                (BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, _, _) => Type::Int,
+                _ => Type::Bool,
+            };
 
-            _ => {
-                self.inspect(&term, parent);
+            Some(Known::Type(ty))
+        }
 
-                term
-            }
+        Term::Let(r) => {
+            let value = match r.ty {
+                Some(ty) => Some(Known::Type(ty)),
+                None => known(&r.value),
+            };
+
+            self::known(&r.next, &|name| match name == r.name.text {
+                true => value.clone(),
+                false => var(name),
+            })
+        }
+
+        Term::Destructure(d) => {
+            let names = d.pattern.variables();
+            self::known(&d.next, &|name| match names.iter().any(|bound| bound.text == name) {
+                true => None,
+                false => var(name),
+            })
+        }
+
+        Term::External(ext) => self::known(&ext.next, &|name| match name == ext.name.text {
+            true => Some(Known::Function),
+            false => var(name),
+        }),
+
+        Term::Len(_) | Term::ToInt(_) => Some(Known::Type(Type::Int)),
+        Term::Substr(_) | Term::ToStr(_) | Term::ReadLine(_) => Some(Known::Type(Type::Str)),
+        Term::Contains(_) => Some(Known::Type(Type::Bool)),
+        _ => None,
+    }
+}
+
+/// Fails when a value is known not to have the type of its annotation,
+/// returns whether it's known to have it, so there's nothing to check
+/// when it runs.
+pub(crate) fn annotate(known: Option<Known>, ty: Type, location: &Location) -> Result<bool, GenError> {
+    match known {
+        Some(found) if found == Known::Type(ty) => Ok(true),
+
+        Some(found) => Err(GenError::TypeMismatch {
+            expected: format!("{ty:?}"),
+            found: found.to_string(),
+            source_code: location.file.named_source(),
+            err_span: location.clone().into(),
+        }),
+
+        None => Ok(false),
+    }
+}
+
+/// Fails when a record is known not to have the field that's accessed.
+pub(crate) fn access(known: Option<Known>, access: &FieldAccess) -> Result<(), GenError> {
+    match known {
+        Some(Known::Record(fields)) if !fields.contains(&access.name.text) => Err(unknown_field(access, &fields)),
+        _ => Ok(()),
+    }
+}
+
+fn unknown_field(access: &FieldAccess, fields: &[String]) -> GenError {
+    GenError::UnknownField {
+        name: access.name.text.clone(),
+        source_code: access.name.location.file.named_source(),
+        err_span: access.name.location.clone().into(),
+        help: format!("the fields are: {}", fields.join(", ")),
+    }
+}
+
+/// A test of the decision tree of a match.
+#[derive(PartialEq)]
+enum Test {
+    Tuple,
+    Int(i32),
+    Str(String),
+    Bool(bool),
+}
+
+/// A row of the decision tree of a match: the tests left, on the slots
+/// of the value and its parts, to take the arm.
+#[derive(Clone)]
+struct Row<'a> {
+    tests: Vec<(usize, &'a Pattern)>,
+    arm: usize,
+}
+
+impl<'a> Row<'a> {
+    /// The tests of a pattern, names and wildcards test nothing.
+    fn tests(slot: usize, pattern: &'a Pattern) -> Vec<(usize, &'a Pattern)> {
+        match pattern {
+            Pattern::Var(_) | Pattern::Wildcard(_) => vec![],
+            pattern => vec![(slot, pattern)],
         }
     }
 
-    fn inspect(self: &mut Self, term: &Term, parent: usize) -> usize {
+    /// The row once `slot` passed the test, the tests on the parts of a
+    /// tuple take its place. It's gone if it expected something else.
+    fn select(&self, slot: usize, test: &Test, parts: Option<(usize, usize)>) -> Option<Row<'a>> {
+        let Some(idx) = self.tests.iter().position(|(other, _)| *other == slot) else {
+            return Some(self.clone());
+        };
+
+        let replacement = match (self.tests[idx].1, test, parts) {
+            (Pattern::Tuple(t), Test::Tuple, Some((first, second))) => {
+                let mut tests = Row::tests(first, &t.first);
+                tests.extend(Row::tests(second, &t.second));
+                tests
+            }
+
+            (Pattern::Int(i), Test::Int(value), _) if i.value == *value => vec![],
+            (Pattern::Str(s), Test::Str(value), _) if s.value == *value => vec![],
+            (Pattern::Bool(b), Test::Bool(value), _) if b.value == *value => vec![],
+            _ => return None,
+        };
+
+        let mut row = self.clone();
+        row.tests.splice(idx..=idx, replacement);
+        Some(row)
+    }
+}
+
+/// The lowering of a match, every leaf of its decision tree jumps to the
+/// code of an arm, labelled after the result slot.
+struct Decision {
+    label: usize,
+    at: String,
+    line: String,
+    function: usize,
+    /* slot ID, slots of the parts of the tuple it holds */
+    parts: BTreeMap<usize, (usize, usize)>,
+}
+
+/// The C generator state.
+///
+/// Every value of the program lives in a slot, a `v_N` holding the
+/// value and a `t_N` holding its kind. Literals are the only slots
+/// written as globals, everything else is a local of the function
+/// that computes it, so a recursive call never clobbers its caller.
+#[derive(Default)]
+pub struct State {
+    /* Global slots: the literals of the program. */
+    constants: BTreeMap<usize, String>,
+    types: BTreeMap<usize, u8>,
+
+    /* function ID, arity */
+    functions: BTreeMap<usize, usize>,
+    /* function ID, declaration of the C function it wraps */
+    externals: BTreeMap<usize, String>,
+    /* function ID, the function it was declared in */
+    parents: BTreeMap<usize, usize>,
+    /* function ID, slots of the enclosing functions it captures */
+    captures: BTreeMap<usize, Vec<usize>>,
+    /* slot ID, function ID that it is known to hold */
+    known_functions: BTreeMap<usize, usize>,
+    /* slot ID, fields of the record that it is known to hold */
+    records: BTreeMap<usize, Vec<String>>,
+    /* slot ID, type that it is known to hold */
+    static_types: BTreeMap<usize, Type>,
+
+    /* function ID, local slots */
+    locals: BTreeMap<usize, Vec<usize>>,
+    scoped_variables: BTreeMap<usize, BTreeMap<String, usize>>,
+    /* function ID, Queue of Evaluatiions */
+    /* Of course, zero is main. */
+    prologue: BTreeMap<usize, Vec<String>>,
+    evaluation_queue: BTreeMap<usize, Vec<String>>,
+    it: usize,
+
+    warnings: Vec<MatchWarning>,
+}
+
+impl State {
+    /// The location of a term as a C string literal, used by the runtime
+    /// to report errors.
+    fn at(&self, location: &Location) -> String {
+        format!("{:?}", location.to_string())
+    }
+
+    /// The `#line` directive pointing the C compiler to a term.
+    fn line(&self, location: &Location) -> String {
+        let (line, _) = location.line_col();
+        format!("#line {line} {:?}", location.filename())
+    }
+
+    fn fresh(&mut self) -> usize {
         self.it += 1;
+        self.it
+    }
+
+    fn local(&mut self, function: usize) -> usize {
+        let id = self.fresh();
+        self.locals.entry(function).or_default().push(id);
+        id
+    }
 
-        // let mut inspect! = |term| self.inspect!(term, parent);
-        // YOU DONT KNOW US TOO WELL, AT ALL.
+    /// Resolves a name as seen from `function`, capturing it from the
+    /// enclosing functions if needed.
+    fn resolve(&mut self, name: &str, function: usize) -> Option<usize> {
+        let scoped = self
+            .scoped_variables
+            .get(&function)
+            .and_then(|scope| scope.get(name));
 
-        macro_rules! inspect {
-            ($t:expr) => {
-                self.inspect($t, parent)
-            };
+        if let Some(slot) = scoped {
+            return Some(*slot);
         }
 
-        macro_rules! int {
-            ($to:expr, $value:expr) => {{
-                self.constants
-                    .insert($to, ("int".to_string(), format!("{}", $value)));
-                self.types.insert($to, INT);
-            }};
+        if function == FN_MAIN {
+            return None;
         }
 
-        macro_rules! maybe {
-            ($to:expr, $value:expr) => {{
-                self.constants
-                    .insert($to, ("char".to_string(), format!("{}", $value)));
-                self.types.insert($to, MAYBE);
-            }};
+        let outer = self.resolve(name, self.parents[&function])?;
+
+        // Globals are visible everywhere.
+        if self.constants.contains_key(&outer) {
+            return Some(outer);
         }
 
-        macro_rules! loveint {
-            ($it:expr, $binary:ident, $nm: expr, $op:tt) => {
-                match (self.bag_or_die(*$binary.lhs.clone(), parent), self.bag_or_die(*$binary.rhs.clone(), parent)) {
-                    (Term::Int(x), Term::Int(z)) => {
-                        int!($it, x.value $op z.value);
-                    }
+        let slot = self.local(function);
+        let captures = self.captures.entry(function).or_default();
+        let idx = captures.len();
+        captures.push(outer);
 
-                    (Term::Var(v), Term::Var(v2)) => vitao!(v, v2, $nm),
-                    (Term::Var(v), Term::Int(i)) | (Term::Int(i), Term::Var(v)) => vitao!(known i, v, $nm),
+        self.prologue
+            .entry(function)
+            .or_default()
+            .push(format!("v_{slot} = env->v[{idx}]; t_{slot} = env->t[{idx}];"));
 
+        self.scoped_variables
+            .entry(function)
+            .or_default()
+            .insert(name.to_string(), slot);
 
-                    what => panic!("{} => Just ints. found {what:?}", $nm),
-                }
-            };
+        if let Some(fid) = self.known_functions.get(&outer).copied() {
+            self.known_functions.insert(slot, fid);
         }
 
-        macro_rules! ohyeahcomp {
-            ($it:expr, $binary:ident, $nm: expr, $op:tt) => {
-                match (self.bag_or_die(*$binary.lhs.clone(), parent), self.bag_or_die(*$binary.rhs.clone(), parent)) {
-                    (Term::Int(x), Term::Int(z)) => {
-                        maybe!($it, x.value $op z.value);
-                    }
+        if let Some(fields) = self.records.get(&outer).cloned() {
+            self.records.insert(slot, fields);
+        }
 
-                    (Term::Var(v), Term::Var(v2)) => robertocomparacoes!(v, v2, $nm),
-                    (Term::Var(v), Term::Int(i)) | (Term::Int(i), Term::Var(v)) => robertocomparacoes!(known i, v, $nm),
+        if let Some(ty) = self.static_types.get(&outer).copied() {
+            self.static_types.insert(slot, ty);
+        }
 
-                    (Term::Var(v), Term::Str(i)) | (Term::Str(i), Term::Var(v)) => robertocomparacoes!(knownAsString i, v, $nm),
+        Some(slot)
+    }
 
-                    _ => panic!(concat!($nm, "=> Just ints or strings. With rules of course.")),
-                }
-            };
-        }
+    fn bind(&mut self, function: usize, name: &str, slot: usize) -> Option<usize> {
+        self.scoped_variables
+            .entry(function)
+            .or_default()
+            .insert(name.to_string(), slot)
+    }
 
-        macro_rules! phonk {
-            ($it:expr, $value:expr) => {{
-                self.constants
-                    .insert($it, ("char*".to_string(), format!("{:?}", $value)));
-                self.types.insert($it, STR);
-            }};
-        }
+    /// Leaves the scope of a binding, bringing back the one it shadowed.
+    fn unbind(&mut self, function: usize, name: &str, previous: Option<usize>) {
+        let scope = self.scoped_variables.entry(function).or_default();
+        match previous {
+            Some(previous) => scope.insert(name.to_string(), previous),
+            None => scope.remove(name),
+        };
+    }
 
-        macro_rules! lazy {
-            () => {{
-                self.constants
-                    .insert(self.it, ("void*".to_string(), "0".to_string()));
-                self.types.insert(self.it, UNKNOWN);
+    /// Binds the names of a pattern to the parts of the value in `slot`,
+    /// keeping the bindings they shadow in `shadowed`.
+    fn destructure(
+        &mut self,
+        pattern: &Pattern,
+        slot: usize,
+        function: usize,
+        shadowed: &mut Vec<(String, Option<usize>)>,
+    ) -> Result<(), GenError> {
+        match pattern {
+            Pattern::Var(name) => {
+                let previous = self.bind(function, &name.text, slot);
+                shadowed.push((name.text.clone(), previous));
+            }
 
-                self.it
-            }};
+            Pattern::Wildcard(_) => {}
 
-            (int) => {{
-                self.constants
-                    .insert(self.it, ("int*".to_string(), "0".to_string()));
-                self.types.insert(self.it, INT);
+            Pattern::Int(_) | Pattern::Str(_) | Pattern::Bool(_) => {
+                return Err(GenError::RefutablePattern {
+                    source_code: pattern.location().file.named_source(),
+                    err_span: pattern.location().clone().into(),
+                })
+            }
 
-                self.it
-            }};
+            Pattern::Tuple(t) => {
+                let at = self.at(&t.location);
+                let line = self.line(&t.location);
+                let first = self.local(function);
+                let second = self.local(function);
 
-            (boolean) => {{
-                self.constants
-                    .insert(self.it, ("char*".to_string(), "0".to_string()));
-                self.types.insert(self.it, MAYBE);
+                self.evaluation_queue.entry(function).or_default().extend([
+                    line,
+                    format!("Untuple(&v_{first}, &t_{first}, &v_{second}, &t_{second}, &v_{slot}, t_{slot}, {at});"),
+                ]);
 
-                self.it
-            }};
+                self.destructure(&t.first, first, function, shadowed)?;
+                self.destructure(&t.second, second, function, shadowed)?;
+            }
         }
 
-        macro_rules! push {
-            ($parent:expr => $($t:tt)*) => {{
-                self.evaluation_queue.entry($parent).or_default().push(format!($($t)*));
-                // dbg!(self.evaluation_queue.entry(parent).or_default());
-            }};
+        Ok(())
+    }
 
-            ($($t:tt)*) => {{
-                self.evaluation_queue.entry(parent).or_default().push(format!($($t)*));
-                // dbg!(self.evaluation_queue.entry(parent).or_default());
-            }};
+    /// The slots of the parts of the tuple in `slot`, the same ones for
+    /// every branch of the decision tree.
+    fn parts(&mut self, slot: usize, decision: &mut Decision) -> (usize, usize) {
+        if let Some(parts) = decision.parts.get(&slot) {
+            return *parts;
         }
 
-        macro_rules! vitc {
-            ($a:tt + $b:tt) => {{
-                let rr = self.it;
-                self.it += 1;
-                int!(rr, $a.value);
+        let parts = (self.local(decision.function), self.local(decision.function));
+        decision.parts.insert(slot, parts);
+        parts
+    }
 
-                let var = getvar!($b.text.as_str());
-                let result = lazy!();
+    /// Lowers the rows of a match to nested tests, the first row whose
+    /// tests are all done is the arm that is taken. Every branch ends on
+    /// a jump to an arm, or on the error when no arm matches.
+    fn decide(&mut self, rows: Vec<Row>, decision: &mut Decision) {
+        let (label, at) = (decision.label, decision.at.clone());
 
-                push!("v_{result} = calloc(1, sizeof(char));");
-                push!("S(&v_{result},&t_{result},&v_{var},&v_{rr},t_{var},i);",);
+        macro_rules! push {
+            ($($t:tt)*) => {{
+                let line = decision.line.clone();
+                let queue = self.evaluation_queue.entry(decision.function).or_default();
+                queue.push(line);
+                queue.push(format!($($t)*));
             }};
         }
 
-        macro_rules! vits {
-            ($a:tt + $b:tt) => {{
-                let rr = self.it;
-                phonk!(rr, $a.value);
-                self.it += 1;
+        let Some(first) = rows.first() else {
+            push!("panic({at}, NoMatch, \"no arm matches the value\");");
+            return;
+        };
+
+        let Some(&(slot, _)) = first.tests.first() else {
+            push!("goto m_{label}_{};", first.arm);
+            return;
+        };
+
+        let mut tests: Vec<Test> = vec![];
+        for row in &rows {
+            for (_, pattern) in row.tests.iter().filter(|(other, _)| *other == slot) {
+                let test = match pattern {
+                    Pattern::Int(i) => Test::Int(i.value),
+                    Pattern::Str(s) => Test::Str(s.value.clone()),
+                    Pattern::Bool(b) => Test::Bool(b.value),
+                    _ => Test::Tuple,
+                };
 
-                let var = getvar!(&$b.text);
-                let result = lazy!();
+                if !tests.contains(&test) {
+                    tests.push(test);
+                }
+            }
+        }
 
-                push!("v_{result} = calloc(1024, sizeof(char));");
-                push!("S(&v_{result},&t_{result}, (PSTR)&v_{var},(PSTR)&v_{rr},t_{var},s);",);
-            }};
+        // The integers are tested together, by a `switch`.
+        if tests.iter().any(|test| matches!(test, Test::Int(_))) {
+            push!("if (t_{slot} == i) switch (v_{slot}.i) {{");
         }
 
-        macro_rules! vitd {
-            ($a:tt + $b:tt) => {{
-                self.it += 1;
+        for test in tests.iter().filter(|test| matches!(test, Test::Int(_))) {
+            let Test::Int(value) = test else { continue };
+            push!("case {value}: {{");
+            self.decide(rows.iter().filter_map(|row| row.select(slot, test, None)).collect(), decision);
+            push!("}}");
+        }
 
-                let vara = getvar!(&$a.text);
+        if tests.iter().any(|test| matches!(test, Test::Int(_))) {
+            push!("}}");
+        }
 
-                let var = getvar!(&$b.text);
+        for test in tests.iter().filter(|test| !matches!(test, Test::Int(_))) {
+            let parts = match test {
+                Test::Tuple => {
+                    let (first, second) = self.parts(slot, decision);
+                    push!("if (t_{slot} == kTuple) {{");
+                    push!("Untuple(&v_{first}, &t_{first}, &v_{second}, &t_{second}, &v_{slot}, t_{slot}, {at});");
+                    Some((first, second))
+                }
 
-                let result = lazy!(int);
+                Test::Str(value) => {
+                    push!("if (t_{slot} == s && 0 == strcmp(v_{slot}.s, {value:?})) {{");
+                    None
+                }
 
-                push!("v_{result} = calloc(1024, sizeof(char));");
-                push!("S(&v_{result},&t_{result}, &v_{var},&v_{vara},t_{var},t_{vara});");
-            }};
+                Test::Bool(value) => {
+                    push!("if (t_{slot} == b && v_{slot}.b == {}) {{", *value as u8);
+                    None
+                }
+
+                Test::Int(_) => continue,
+            };
+
+            self.decide(rows.iter().filter_map(|row| row.select(slot, test, parts)).collect(), decision);
+            push!("}}");
         }
 
-        macro_rules! vitao {
-            (known $a:tt, $b:tt, $nm:expr) => {{
-                let rr = self.it;
-                self.it += 1;
-                int!(rr, $a.value);
+        // The value failed every test, only the rows that don't test it
+        // are left.
+        let rest = rows
+            .into_iter()
+            .filter(|row| row.tests.iter().all(|(other, _)| *other != slot))
+            .collect();
 
-                let var = getvar!($b.text.as_str());
+        self.decide(rest, decision);
+    }
 
-                let result = lazy!(int);
+    /// Binds the names of the pattern of an arm to the slots the decision
+    /// tree put its parts in.
+    fn bind_arm(
+        &mut self,
+        pattern: &Pattern,
+        slot: usize,
+        decision: &mut Decision,
+        shadowed: &mut Vec<(String, Option<usize>)>,
+    ) {
+        match pattern {
+            Pattern::Var(name) => {
+                let previous = self.bind(decision.function, &name.text, slot);
+                shadowed.push((name.text.clone(), previous));
+            }
 
-                push!("v_{result} = calloc(1, sizeof(char));");
-                push!(
-                    "MathEvaluateA((int*)&v_{result},&v_{var},&v_{rr},t_{var},i, {});",
-                    $nm
-                );
-            }};
+            Pattern::Tuple(t) => {
+                let (first, second) = self.parts(slot, decision);
+                self.bind_arm(&t.first, first, decision, shadowed);
+                self.bind_arm(&t.second, second, decision, shadowed);
+            }
 
-            ($a:tt, $b:tt, $nm:expr) => {{
-                self.it += 1;
-                let result = lazy!(int);
+            _ => {}
+        }
+    }
 
-                let vara = getvar!(&$a.text);
-                let var = getvar!(&$b.text);
+    /// Wraps a C function into a function value, checking the kinds of
+    /// the arguments and converting them to C values.
+    fn external(&mut self, ext: &External, parent: usize) -> Result<usize, GenError> {
+        let is_symbol = ext.symbol.chars().enumerate().all(|(idx, c)| {
+            c == '_' || c.is_ascii_alphabetic() || (idx > 0 && c.is_ascii_digit())
+        });
+
+        if ext.symbol.is_empty() || !is_symbol {
+            return Err(GenError::InvalidSymbol {
+                symbol: ext.symbol.clone(),
+                source_code: ext.location.file.named_source(),
+                err_span: ext.location.clone().into(),
+            });
+        }
 
-                push!("v_{result} = calloc(1, sizeof(int));");
-                push!(
-                    "MathEvaluateA((int*)&v_{result}, &v_{var},&v_{vara},t_{var},t_{vara}, {});",
-                    $nm
-                );
-            }};
+        let fid = self.fresh();
+        let location = self.at(&ext.location);
+        let line = self.line(&ext.location);
+        self.functions.insert(fid, ext.parameters.len());
+        self.parents.insert(fid, parent);
+
+        let parameters = match ext.parameters.len() {
+            0 => "void".to_string(),
+            _ => ext.parameters.iter().map(|ty| c_repr(*ty).0).collect::<Vec<_>>().join(", "),
+        };
+
+        self.externals.insert(
+            fid,
+            format!(
+                "extern {} x_{fid}({parameters}) __asm__({:?});",
+                c_repr(ext.returns).0,
+                ext.symbol
+            ),
+        );
+
+        let queue = self.evaluation_queue.entry(fid).or_default();
+        let mut arguments = vec![];
+        queue.push(line.clone());
+
+        for (idx, ty) in ext.parameters.iter().enumerate() {
+            let (_, member, kind) = c_repr(*ty);
+            queue.push(format!("Expect(a_{}, {kind}, InvalidArgument, {location});", idx * 2 + 1));
+            arguments.push(format!("a_{}.{member}", idx * 2));
         }
 
-        macro_rules! robertocomparacoes {
-            (known $a:tt, $b:tt, $nm:expr) => {{
-                let rr = self.it;
-                self.it += 1;
-                int!(rr, $a.value);
+        let (_, member, kind) = c_repr(ext.returns);
+        let call = format!("x_{fid}({})", arguments.join(", "));
+        queue.push(match ext.returns {
+            Type::Bool => format!("Any r; r.b = {call} != 0; *tr = b; return r;"),
+            Type::Str => format!("Any r; r.s = NonNull({call}, {location}); *tr = s; return r;"),
+            _ => format!("Any r; r.{member} = {call}; *tr = {kind}; return r;"),
+        });
+
+        let result = self.local(parent);
+        let queue = self.evaluation_queue.entry(parent).or_default();
+        queue.push(line);
+        queue.push(format!(
+            "MakeClosure(&v_{result}, &t_{result}, (void*)f_{fid}, {}, 0);",
+            ext.parameters.len()
+        ));
+
+        self.known_functions.insert(result, fid);
+        Ok(result)
+    }
 
-                let var = getvar!(&$b.text);
-                let result = lazy!(boolean);
+    /// The type of a slot, when it's known at compile time.
+    fn found(&self, slot: usize) -> Option<String> {
+        if let Some(ty) = self.static_types.get(&slot) {
+            Some(format!("{ty:?}"))
+        } else if self.known_functions.contains_key(&slot) {
+            Some("function".to_string())
+        } else if self.records.contains_key(&slot) {
+            Some("record".to_string())
+        } else {
+            None
+        }
+    }
 
-                push!("v_{result} = calloc(1, sizeof(char));");
-                push!(
-                    "BinaryEvaluateA((char*)&v_{result},&v_{var},&v_{rr},t_{var},i, {});",
-                    $nm
-                );
+    /// Checks a slot against a type annotation, at compile time when its
+    /// type is known, otherwise at run time. The returned slot is known to
+    /// hold the type.
+    ///
+    /// An annotation adds the check only, the slot is still a tagged value
+    /// like any other. Knowing its type is what lets the operations on it
+    /// skip the helpers, the same as for a slot whose type is inferred.
+    fn annotate(
+        &mut self,
+        slot: usize,
+        ty: Type,
+        location: &Location,
+        function: usize,
+    ) -> Result<usize, GenError> {
+        match self.found(slot) {
+            Some(found) if found == format!("{ty:?}") => Ok(slot),
+
+            Some(found) => Err(GenError::TypeMismatch {
+                expected: format!("{ty:?}"),
+                found,
+                source_code: location.file.named_source(),
+                err_span: location.clone().into(),
+            }),
+
+            None => {
+                let (_, _, kind) = c_repr(ty);
+                let at = self.at(location);
+                let line = self.line(location);
+                let result = self.local(function);
+
+                self.evaluation_queue.entry(function).or_default().extend([
+                    line,
+                    format!("Expect(t_{slot}, {kind}, TypeMismatch, {at});"),
+                    format!("v_{result} = v_{slot}; t_{result} = t_{slot};"),
+                ]);
+
+                self.static_types.insert(result, ty);
+                Ok(result)
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        f: &Function,
+        name: Option<&str>,
+        parent: usize,
+    ) -> Result<usize, GenError> {
+        let fid = self.fresh();
+        self.functions.insert(fid, f.parameters.len());
+        self.parents.insert(fid, parent);
+
+        macro_rules! prologue {
+            ($($t:tt)*) => {{
+                self.prologue.entry(fid).or_default().push(format!($($t)*));
             }};
+        }
 
-            (knownAsString $a:tt, $b:tt, $nm:expr) => {{
-                let rr = self.it;
-                self.it += 1;
-                phonk!(rr, $a.value);
+        if let Some(name) = name {
+            let me = self.local(fid);
+            self.known_functions.insert(me, fid);
+            self.scoped_variables
+                .entry(fid)
+                .or_default()
+                .insert(name.to_string(), me);
 
-                let var = getvar!($b.text.as_str());
-                let result = lazy!(boolean);
+            prologue!("v_{me}.fn = env; t_{me} = kFunction;");
+        }
 
-                push!("v_{result} = calloc(1, sizeof(char));");
-                push!(
-                    "BinaryEvaluateA((char*)&v_{result},&v_{var},&v_{rr},t_{var},i, {});",
-                    $nm
-                );
-            }};
+        for (idx, p) in f.parameters.iter().enumerate() {
+            let id = self.local(fid);
+            self.scoped_variables
+                .entry(fid)
+                .or_default()
+                .insert(p.text.clone(), id);
+
+            prologue!("v_{id} = a_{}; t_{id} = a_{};", idx * 2, idx * 2 + 1);
+
+            if let Some(Some(ty)) = f.annotations.get(idx) {
+                let (_, _, kind) = c_repr(*ty);
+                let at = self.at(&p.location);
+                prologue!("Expect(t_{id}, {kind}, TypeMismatch, {at});");
+                self.static_types.insert(id, *ty);
+            }
+        }
 
-            ($a:tt, $b:tt, $nm:expr) => {{
-                self.it += 1;
-                let result = lazy!(boolean);
+        let value = self.inspect(&f.value, fid)?;
+        let line = self.line(&f.location);
+        self.evaluation_queue
+            .entry(fid)
+            .or_default()
+            .extend([line.clone(), format!("*tr = t_{value}; return v_{value};")]);
+
+        let result = self.local(parent);
+        let captures = self.captures.get(&fid).cloned().unwrap_or_default();
+        let queue = self.evaluation_queue.entry(parent).or_default();
+
+        queue.push(line);
+        queue.push(format!(
+            "MakeClosure(&v_{result}, &t_{result}, (void*)f_{fid}, {}, {});",
+            f.parameters.len(),
+            captures.len()
+        ));
+
+        for (idx, outer) in captures.iter().enumerate() {
+            queue.push(format!(
+                "v_{result}.fn->v[{idx}] = v_{outer}; v_{result}.fn->t[{idx}] = t_{outer};"
+            ));
+        }
 
-                let vara = getvar!($a.text.as_str());
-                let var = getvar!($b.text.as_str());
+        self.known_functions.insert(result, fid);
+        Ok(result)
+    }
 
-                push!("v_{result} = calloc(1, sizeof(char));");
-                push!(
-                    "BinaryEvaluateA((char*)&v_{result}, &v_{var},&v_{vara},t_{var},t_{vara}, {});",
-                    $nm
-                );
+    fn inspect(&mut self, term: &Term, parent: usize) -> Result<usize, GenError> {
+        macro_rules! inspect {
+            ($t:expr) => {
+                self.inspect($t, parent)?
+            };
+        }
+
+        macro_rules! constant {
+            ($kind:expr, $ty:expr, $value:expr) => {{
+                let id = self.fresh();
+                self.constants.insert(id, $value);
+                self.types.insert(id, $kind);
+                self.static_types.insert(id, $ty);
+                id
             }};
         }
 
-        macro_rules! putvar {
-            ($name:expr, $value:expr) => {{
-                self.scoped_variables
-                    .entry(parent)
-                    .or_default()
-                    .insert($name, $value)
+        macro_rules! int {
+            ($value:expr) => {
+                constant!(INT, Type::Int, format!("{{.i = {}}}", $value))
+            };
+        }
+
+        macro_rules! maybe {
+            ($value:expr) => {
+                constant!(MAYBE, Type::Bool, format!("{{.b = {}}}", $value as u8))
+            };
+        }
+
+        macro_rules! phonk {
+            ($value:expr) => {
+                constant!(STR, Type::Str, format!("{{.s = {:?}}}", $value))
+            };
+        }
+
+        macro_rules! lazy {
+            () => {
+                self.local(parent)
+            };
+        }
+
+        let line = self.line(term.location());
+
+        macro_rules! push {
+            ($($t:tt)*) => {{
+                let queue = self.evaluation_queue.entry(parent).or_default();
+                queue.push(line.clone());
+                queue.push(format!($($t)*));
             }};
         }
 
         macro_rules! getvar {
             ($name:expr) => {{
-                let scoped = self
-                    .scoped_variables
-                    .entry(parent)
-                    .or_default()
-                    .get($name)
-                    .map(|x| *x);
-
-                let main = self
-                    .scoped_variables
-                    .entry(FN_MAIN)
-                    .or_default()
-                    .get($name)
-                    .map(|x| *x);
-
-                scoped
-                    .or(main)
-                    .expect(&format!("expected variable: <#var {parent}/{}>", $name))
+                match self.resolve(&$name.text, parent) {
+                    Some(slot) => slot,
+                    None => {
+                        return Err(GenError::UnboundVariable {
+                            name: $name.text.clone(),
+                            source_code: $name.location.file.named_source(),
+                            err_span: $name.location.clone().into(),
+                        })
+                    }
+                }
             }};
         }
 
-        match term {
-            Term::Str(s) => {
-                phonk!(self.it, s.value);
-            }
+        let it = match term {
+            Term::Str(s) => phonk!(s.value),
+            Term::Int(i) => int!(i.value),
+            Term::Bool(b) => maybe!(b.value),
 
-            Term::Int(i) => {
-                int!(self.it, i.value);
-            }
+            Term::If(comp) => match &*comp.condition {
+                Term::Bool(b) if b.value => inspect!(&comp.then),
+                Term::Bool(_) => inspect!(&comp.otherwise),
 
-            Term::If(comp) => match self.bag_or_die(*comp.condition.clone(), parent) {
-                Term::Bool(b) => {
-                    if b.value {
-                        inspect!(&comp.then)
-                    } else {
-                        inspect!(&comp.otherwise)
-                    };
+                condition => {
+                    let location = self.at(condition.location());
+                    let condition = inspect!(condition);
+                    let result = lazy!();
 
-                    // panic!("{res}");
-                }
-                t @ Term::Binary(_) => {
-                    let res = inspect!(&t);
-                    if self.constants.get(&res).unwrap().1 == "true" {
-                        inspect!(&comp.then)
-                    } else {
-                        inspect!(&comp.otherwise)
-                    };
+                    match self.static_types.get(&condition) {
+                        Some(Type::Bool) => push!("if (v_{condition}.b) {{"),
+                        _ => push!("if (Truthy(v_{condition}, t_{condition}, {location})) {{"),
+                    }
+                    let then = inspect!(&comp.then);
+                    push!("v_{result} = v_{then}; t_{result} = t_{then};");
+                    push!("}} else {{");
+                    let otherwise = inspect!(&comp.otherwise);
+                    push!("v_{result} = v_{otherwise}; t_{result} = t_{otherwise};");
+                    push!("}}");
+
+                    result
                 }
-                what => panic!("If => Just boolean or binary. found {what:?}"),
             },
 
-            Term::Binary(binary) => match binary.op {
-                crate::ast::BinaryOp::Add => match (
-                    self.bag_or_die(*binary.lhs.clone(), parent),
-                    self.bag_or_die(*binary.rhs.clone(), parent),
-                ) {
-                    (Term::Int(x), Term::Int(z)) => int!(self.it, x.value + z.value),
-                    (Term::Str(s), Term::Str(s2)) => phonk!(self.it, s.value + &s2.value),
-                    (Term::Var(v), Term::Var(v2)) => vitd!(v + v2),
-                    (Term::Var(v), Term::Int(i)) | (Term::Int(i), Term::Var(v)) => vitc!(i + v),
-                    (Term::Str(s), Term::Var(v)) | (Term::Var(v), Term::Str(s)) => vits!(s + v),
-
-                    what => panic!("Add => Just ints and strings. found {what:?}"),
-                },
-
-                crate::ast::BinaryOp::Div => loveint!(self.it, binary, "Div", /),
-                crate::ast::BinaryOp::Sub => loveint!(self.it, binary, "Sub", -),
-                crate::ast::BinaryOp::Rem => loveint!(self.it, binary, "Rem", %),
-                crate::ast::BinaryOp::Mul => loveint!(self.it, binary, "Mul", *),
-
-                crate::ast::BinaryOp::Lt => ohyeahcomp!(self.it, binary, "Lt", <),
-                crate::ast::BinaryOp::Gt => ohyeahcomp!(self.it, binary, "Gt", >),
-                crate::ast::BinaryOp::Lte => ohyeahcomp!(self.it, binary, "Lte", >=),
-                crate::ast::BinaryOp::Gte => ohyeahcomp!(self.it, binary, "Gte", <=),
-
-                crate::ast::BinaryOp::Eq => match (
-                    self.bag_or_die(*binary.lhs.clone(), parent),
-                    self.bag_or_die(*binary.rhs.clone(), parent),
-                ) {
-                    (Term::Int(x), Term::Int(z)) => {
-                        maybe!(self.it, x.value == z.value);
-                    }
-
-                    (Term::Str(s), Term::Str(s2)) => {
-                        maybe!(self.it, s.value == s2.value);
-                        // self.runtime_queue
-                        //     .insert(self.it, format!("!strcmp({:?}, {:?})", s.value, s2.value));
-                    }
+            Term::Binary(binary) => match (&binary.op, &*binary.lhs, &*binary.rhs) {
+                (BinaryOp::Add, Term::Int(x), Term::Int(z)) => int!(x.value.wrapping_add(z.value)),
+                (BinaryOp::Add, Term::Str(x), Term::Str(z)) => phonk!(x.value.clone() + &z.value),
+                (BinaryOp::Sub, Term::Int(x), Term::Int(z)) => int!(x.value.wrapping_sub(z.value)),
+                (BinaryOp::Mul, Term::Int(x), Term::Int(z)) => int!(x.value.wrapping_mul(z.value)),
 
-                    (Term::Bool(b), Term::Bool(b2)) => {
-                        maybe!(self.it, b.value == b2.value);
-                    }
+                (BinaryOp::Div, Term::Int(x), Term::Int(z)) if x.value.checked_div(z.value).is_some() => {
+                    int!(x.value / z.value)
+                }
 
-                    _ => ohyeahcomp!(self.it, binary, "Eq", ==),
-                },
+                (BinaryOp::Rem, Term::Int(x), Term::Int(z)) if x.value.checked_rem(z.value).is_some() => {
+                    int!(x.value % z.value)
+                }
 
-                crate::ast::BinaryOp::Neq => match (
-                    self.bag_or_die(*binary.lhs.clone(), parent),
-                    self.bag_or_die(*binary.rhs.clone(), parent),
-                ) {
-                    (Term::Int(x), Term::Int(z)) => {
-                        maybe!(self.it, x.value != z.value);
-                    }
+                (BinaryOp::Eq, Term::Int(x), Term::Int(z)) => maybe!(x.value == z.value),
+                (BinaryOp::Neq, Term::Int(x), Term::Int(z)) => maybe!(x.value != z.value),
+                (BinaryOp::Lt, Term::Int(x), Term::Int(z)) => maybe!(x.value < z.value),
+                (BinaryOp::Gt, Term::Int(x), Term::Int(z)) => maybe!(x.value > z.value),
+                (BinaryOp::Lte, Term::Int(x), Term::Int(z)) => maybe!(x.value <= z.value),
+                (BinaryOp::Gte, Term::Int(x), Term::Int(z)) => maybe!(x.value >= z.value),
+                (BinaryOp::Eq, Term::Str(x), Term::Str(z)) => maybe!(x.value == z.value),
+                (BinaryOp::Neq, Term::Str(x), Term::Str(z)) => maybe!(x.value != z.value),
+                (BinaryOp::Lt, Term::Str(x), Term::Str(z)) => maybe!(x.value < z.value),
+                (BinaryOp::Gt, Term::Str(x), Term::Str(z)) => maybe!(x.value > z.value),
+                (BinaryOp::Lte, Term::Str(x), Term::Str(z)) => maybe!(x.value <= z.value),
+                (BinaryOp::Gte, Term::Str(x), Term::Str(z)) => maybe!(x.value >= z.value),
+                (BinaryOp::Eq, Term::Bool(x), Term::Bool(z)) => maybe!(x.value == z.value),
+                (BinaryOp::Neq, Term::Bool(x), Term::Bool(z)) => maybe!(x.value != z.value),
+                (BinaryOp::And, Term::Bool(x), Term::Bool(z)) => maybe!(x.value && z.value),
+                (BinaryOp::Or, Term::Bool(x), Term::Bool(z)) => maybe!(x.value || z.value),
+
+                (op @ (BinaryOp::And | BinaryOp::Or), lhs, rhs) => {
+                    let (lhs_at, rhs_at) = (self.at(lhs.location()), self.at(rhs.location()));
+                    let lhs = inspect!(lhs);
+                    let result = lazy!();
 
-                    (Term::Str(s), Term::Str(s2)) => {
-                        maybe!(self.it, s.value != s2.value);
-                        // self.runtime_queue
-                        //     .insert(self.it, format!("!!strcmp({:?}, {:?})", s.value, s2.value));
+                    // Short-circuit, the right side only runs when needed.
+                    push!("v_{result}.b = Truthy(v_{lhs}, t_{lhs}, {lhs_at}); t_{result} = b;");
+                    match op {
+                        BinaryOp::And => push!("if (v_{result}.b) {{"),
+                        _ => push!("if (!v_{result}.b) {{"),
                     }
+                    let rhs = inspect!(rhs);
+                    push!("v_{result}.b = Truthy(v_{rhs}, t_{rhs}, {rhs_at});");
+                    push!("}}");
 
-                    (Term::Bool(b), Term::Bool(b2)) => {
-                        maybe!(self.it, b.value != b2.value);
-                    }
+                    self.static_types.insert(result, Type::Bool);
+                    result
+                }
 
-                    _ => ohyeahcomp!(self.it, binary, "Neq", ==),
-                },
+                (op, lhs, rhs) => {
+                    let location = self.at(&binary.location);
+                    let lhs = inspect!(lhs);
+                    let rhs = inspect!(rhs);
+                    let result = lazy!();
 
-                crate::ast::BinaryOp::And => match (*binary.lhs.clone(), *binary.rhs.clone()) {
-                    (Term::Bool(b), Term::Bool(b2)) => {
-                        maybe!(self.it, b.value && b2.value);
-                    }
+                    let known = (
+                        self.static_types.get(&lhs).copied(),
+                        self.static_types.get(&rhs).copied(),
+                    );
+
+                    let (nm, c) = match op {
+                        BinaryOp::Add => ("Add", "+"),
+                        BinaryOp::Sub => ("Sub", "-"),
+                        BinaryOp::Mul => ("Mul", "*"),
+                        BinaryOp::Div => ("Div", "/"),
+                        BinaryOp::Rem => ("Rem", "%"),
+                        BinaryOp::Eq => ("Eq", "=="),
+                        BinaryOp::Neq => ("Neq", "!="),
+                        BinaryOp::Lt => ("Lt", "<"),
+                        BinaryOp::Gt => ("Gt", ">"),
+                        BinaryOp::Lte => ("Lte", "<="),
+                        BinaryOp::Gte => ("Gte", ">="),
+                        BinaryOp::And | BinaryOp::Or => unreachable!(),
+                    };
 
-                    _ => panic!("Just bools are allowed."),
-                },
+                    let ty = match (op, known) {
+                        // Both sides are known to be ints, so the operation
+                        // works right on the C values, wrapping around.
+                        (BinaryOp::Div | BinaryOp::Rem, (Some(Type::Int), Some(Type::Int))) => {
+                            push!("v_{result}.i = Wrap{nm}(v_{lhs}.i, NonZero(v_{rhs}.i, {location})); t_{result} = i;");
+                            Some(Type::Int)
+                        }
+
+                        (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul, (Some(Type::Int), Some(Type::Int))) => {
+                            push!("v_{result}.i = Wrap{nm}(v_{lhs}.i, v_{rhs}.i); t_{result} = i;");
+                            Some(Type::Int)
+                        }
+
+                        (_, (Some(Type::Int), Some(Type::Int))) => {
+                            push!("v_{result}.b = v_{lhs}.i {c} v_{rhs}.i; t_{result} = b;");
+                            Some(Type::Bool)
+                        }
+
+                        (
+                            BinaryOp::Eq | BinaryOp::Neq | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Lte | BinaryOp::Gte,
+                            (Some(Type::Str), Some(Type::Str)),
+                        ) => {
+                            push!("v_{result}.b = strcmp(v_{lhs}.s, v_{rhs}.s) {c} 0; t_{result} = b;");
+                            Some(Type::Bool)
+                        }
+
+                        (BinaryOp::Add, known) => {
+                            push!("S(&v_{result}, &t_{result}, &v_{lhs}, &v_{rhs}, t_{lhs}, t_{rhs}, {location});");
+
+                            match known {
+                                (Some(Type::Str), Some(Type::Str | Type::Int))
+                                | (Some(Type::Int), Some(Type::Str)) => Some(Type::Str),
+                                _ => None,
+                            }
+                        }
+
+                        (BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, _) => {
+                            push!(
+                                "MathEvaluateA(&v_{result}, &t_{result}, &v_{lhs}, &v_{rhs}, t_{lhs}, t_{rhs}, {nm}, {location});"
+                            );
+                            Some(Type::Int)
+                        }
+
+                        _ => {
+                            push!(
+                                "BinaryEvaluateA(&v_{result}, &t_{result}, &v_{lhs}, &v_{rhs}, t_{lhs}, t_{rhs}, {nm}, {location});"
+                            );
+                            Some(Type::Bool)
+                        }
+                    };
 
-                crate::ast::BinaryOp::Or => match (*binary.lhs.clone(), *binary.rhs.clone()) {
-                    (Term::Bool(b), Term::Bool(b2)) => {
-                        maybe!(self.it, b.value || b2.value);
+                    if let Some(ty) = ty {
+                        self.static_types.insert(result, ty);
                     }
 
-                    _ => panic!("Just bools are allowed."),
-                },
+                    result
+                }
             },
 
             Term::Let(r) => {
-                match &*r.value {
-                    Term::Str(s) => {
-                        phonk!(self.it, s.value.clone());
-                    }
+                let value = match &*r.value {
+                    Term::Function(f) => self.function(f, Some(&r.name.text), parent)?,
+                    value => inspect!(value),
+                };
 
-                    Term::Int(i) => {
-                        int!(self.it, i.value);
-                    }
+                let value = match r.ty {
+                    Some(ty) => self.annotate(value, ty, r.value.location(), parent)?,
+                    None => value,
+                };
 
-                    f @ Term::Function(_) => {
-                        self.named_functions
-                            .insert(r.name.text.clone(), self.it + 1);
-                        inspect!(&f);
-                    }
+                let previous = self.bind(parent, &r.name.text, value);
+                let next = inspect!(&r.next);
+                self.unbind(parent, &r.name.text, previous);
 
-                    s => {
-                        inspect!(&s);
-                    }
-                };
+                next
+            }
+
+            Term::Destructure(d) => {
+                let value = inspect!(&d.value);
+
+                let mut shadowed = vec![];
+                self.destructure(&d.pattern, value, parent, &mut shadowed)?;
+                let next = inspect!(&d.next);
+                for (name, previous) in shadowed.into_iter().rev() {
+                    self.unbind(parent, &name, previous);
+                }
 
-                self.scoped_variables
-                    .entry(parent)
-                    .or_default()
-                    .insert(r.name.text.clone(), self.it);
-                inspect!(&r.next);
+                next
+            }
+
+            Term::External(ext) => {
+                let value = self.external(ext, parent)?;
+
+                let previous = self.bind(parent, &ext.name.text, value);
+                let next = inspect!(&ext.next);
+                self.unbind(parent, &ext.name.text, previous);
+
+                next
             }
 
             Term::Tuple(t) => {
                 let first = inspect!(&t.first);
                 let second = inspect!(&t.second);
-                self.it += 1;
-                let rr = self.it;
-
-                self.constants.insert(
-                    rr,
-                    (
-                        "Tuple".to_string(),
-                        format!("{{0}}"), // format!("{{v_{first},v_{second},t_{first},t_{second}}}"),
+                let result = lazy!();
+
+                push!("MakeTuple(&v_{result}, &t_{result}, v_{first}, t_{first}, v_{second}, t_{second});");
+                result
+            }
+
+            Term::Record(r) => {
+                let mut fields = vec![];
+                for field in &r.fields {
+                    fields.push((field.name.text.clone(), inspect!(&field.value)));
+                }
+
+                let result = lazy!();
+                let names = match fields.len() {
+                    0 => "NULL".to_string(),
+                    _ => format!(
+                        "(const char *[]){{{}}}",
+                        fields
+                            .iter()
+                            .map(|(name, _)| format!("{name:?}"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
                     ),
-                );
+                };
 
-                push!("v_{rr}.a = &v_{first};");
-                push!("v_{rr}.b = &v_{second};");
-                push!("v_{rr}.ta = t_{first};");
-                push!("v_{rr}.tb = t_{second};");
+                push!("MakeRecord(&v_{result}, &t_{result}, {}, {names});", fields.len());
+                for (idx, (_, value)) in fields.iter().enumerate() {
+                    push!("v_{result}.record->v[{idx}] = v_{value}; v_{result}.record->t[{idx}] = t_{value};");
+                }
 
-                self.types.insert(rr, 0x10);
+                self.records
+                    .insert(result, fields.into_iter().map(|(name, _)| name).collect());
+                result
             }
 
-            Term::First(t) => match self.bag_or_die(*t.value.clone(), parent) {
-                Term::Tuple(t) => {
-                    inspect!(&t.first);
+            Term::FieldAccess(access) => {
+                let location = self.at(&access.location);
+                let value = inspect!(&access.value);
+                let result = lazy!();
+
+                // The shape of the record is known, so the field is too.
+                match self.records.get(&value) {
+                    Some(fields) => match fields.iter().position(|name| *name == access.name.text) {
+                        Some(idx) => push!(
+                            "v_{result} = v_{value}.record->v[{idx}]; t_{result} = v_{value}.record->t[{idx}];"
+                        ),
+                        None => return Err(unknown_field(access, fields)),
+                    },
+
+                    None => push!(
+                        "FieldA(&v_{result}, &t_{result}, &v_{value}, t_{value}, {:?}, {location});",
+                        access.name.text
+                    ),
                 }
 
-                Term::Var(v) => {
-                    let result = lazy!();
+                result
+            }
 
-                    let var = getvar!(v.text.as_str());
+            Term::First(t) => {
+                let location = self.at(&t.location);
+                let value = inspect!(&t.value);
+                let result = lazy!();
 
-                    push!("TupleIdxA(&v_{result}, &t_{result}, &v_{var}, t_{var}, 0);");
-                }
+                push!("TupleIdxA(&v_{result}, &t_{result}, &v_{value}, t_{value}, 0, {location});");
+                result
+            }
 
-                e => {
-                    inspect!(&e);
+            Term::Second(t) => {
+                let location = self.at(&t.location);
+                let value = inspect!(&t.value);
+                let result = lazy!();
+
+                push!("TupleIdxA(&v_{result}, &t_{result}, &v_{value}, t_{value}, 1, {location});");
+                result
+            }
+
+            Term::List(l) => {
+                let location = self.at(&l.location);
+                let mut elements = vec![];
+                for element in &l.elements {
+                    elements.push(inspect!(element));
                 }
-            },
+                let result = lazy!();
 
-            Term::Second(t) => match self.bag_or_die(*t.value.clone(), parent) {
-                Term::Tuple(t) => {
-                    inspect!(&t.second);
+                // Built from the end, the last element goes in first.
+                push!("v_{result}.list = NULL; t_{result} = kList;");
+                for element in elements.iter().rev() {
+                    push!("Cons(&v_{result}, &t_{result}, v_{element}, t_{element}, &v_{result}, t_{result}, {location});");
                 }
 
-                Term::Var(v) => {
-                    let result = lazy!();
+                result
+            }
 
-                    let var = getvar!(v.text.as_str());
+            Term::Cons(c) => {
+                let location = self.at(&c.location);
+                let head = inspect!(&c.head);
+                let tail = inspect!(&c.tail);
+                let result = lazy!();
 
-                    push!("TupleIdxA(&v_{result}, &t_{result}, &v_{var}, t_{var}, 1);");
-                }
+                push!("Cons(&v_{result}, &t_{result}, v_{head}, t_{head}, &v_{tail}, t_{tail}, {location});");
+                result
+            }
 
-                e => {
-                    inspect!(&e);
-                }
-            },
+            Term::Head(h) => {
+                let location = self.at(&h.location);
+                let value = inspect!(&h.value);
+                let result = lazy!();
 
-            Term::Var(v) => {
-                return getvar!(&v.text);
+                push!("HeadA(&v_{result}, &t_{result}, &v_{value}, t_{value}, {location});");
+                result
             }
 
-            Term::Print(p) => {
-                let it = inspect!(&p.value);
+            Term::Tail(t) => {
+                let location = self.at(&t.location);
+                let value = inspect!(&t.value);
+                let result = lazy!();
+
+                push!("TailA(&v_{result}, &t_{result}, &v_{value}, t_{value}, {location});");
+                result
+            }
+
+            Term::Len(l) => {
+                let location = self.at(&l.location);
+                let value = inspect!(&l.value);
+                let result = lazy!();
 
-                self.print_queue.push(it);
+                push!("LenA(&v_{result}, &t_{result}, &v_{value}, t_{value}, {location});");
+                self.static_types.insert(result, Type::Int);
+                result
             }
 
-            Term::Function(f) => {
-                self.functions.insert(self.it, f.parameters.len());
-                let fid = self.it;
-                let mut ids = vec![];
+            Term::Substr(sub) => {
+                let location = self.at(&sub.location);
+                let value = inspect!(&sub.value);
+                let value = self.annotate(value, Type::Str, sub.value.location(), parent)?;
+                let start = inspect!(&sub.start);
+                let start = self.annotate(start, Type::Int, sub.start.location(), parent)?;
+                let length = inspect!(&sub.length);
+                let length = self.annotate(length, Type::Int, sub.length.location(), parent)?;
+                let result = lazy!();
 
-                for p in f.parameters.iter() {
-                    self.it += 1;
-                    let id = lazy!();
-                    ids.push(id);
-                    putvar!(p.text.clone(), id);
-                }
+                push!("Substr(&v_{result}, &t_{result}, &v_{value}, v_{start}.i, v_{length}.i, {location});");
+                self.static_types.insert(result, Type::Str);
+                result
+            }
 
-                let mut idx: usize = 0;
+            Term::ToInt(t) => {
+                let location = self.at(&t.location);
+                let value = inspect!(&t.value);
+                let value = self.annotate(value, Type::Str, t.value.location(), parent)?;
+                let result = lazy!();
 
-                while idx != ids.len() * 2 {
-                    let id = ids[idx / 2];
+                push!("ToInt(&v_{result}, &t_{result}, &v_{value}, {location});");
+                self.static_types.insert(result, Type::Int);
+                result
+            }
+
+            Term::ToStr(t) => {
+                let location = self.at(&t.location);
+                let value = inspect!(&t.value);
+                let result = lazy!();
 
-                    push!(fid => "(void*)v_{id} = *(void**)a_{idx};");
-                    push!(fid => "t_{id} = *(Kind*)a_{};", idx+1);
-                    idx += 2;
+                push!("ToStr(&v_{result}, &t_{result}, &v_{value}, t_{value}, {location});");
+                self.static_types.insert(result, Type::Str);
+                result
+            }
+
+            Term::Contains(c) => {
+                let value = inspect!(&c.value);
+                let value = self.annotate(value, Type::Str, c.value.location(), parent)?;
+                let pattern = inspect!(&c.pattern);
+                let pattern = self.annotate(pattern, Type::Str, c.pattern.location(), parent)?;
+                let result = lazy!();
+
+                push!("v_{result}.b = NULL != strstr(v_{value}.s, v_{pattern}.s); t_{result} = b;");
+                self.static_types.insert(result, Type::Bool);
+                result
+            }
+
+            Term::ReadLine(r) => {
+                let location = self.at(&r.location);
+                let result = lazy!();
+
+                push!("ReadLine(&v_{result}, &t_{result}, {location});");
+                self.static_types.insert(result, Type::Str);
+                result
+            }
+
+            Term::Argv(_) => {
+                let result = lazy!();
+
+                push!("ArgvA(&v_{result}, &t_{result});");
+                result
+            }
+
+            Term::Match(m) => {
+                self.warnings.extend(crate::patterns::check(m));
+
+                let value = inspect!(&m.value);
+                let result = lazy!();
+                let mut decision = Decision {
+                    label: result,
+                    at: self.at(&m.location),
+                    line: line.clone(),
+                    function: parent,
+                    parts: BTreeMap::new(),
+                };
+
+                let rows = m.arms.iter().enumerate().map(|(arm, a)| Row {
+                    tests: Row::tests(value, &a.pattern),
+                    arm,
+                });
+                self.decide(rows.collect(), &mut decision);
+
+                // The code of every arm comes once, after the tree.
+                for (idx, arm) in m.arms.iter().enumerate() {
+                    push!("m_{result}_{idx}:;");
+                    let mut shadowed = vec![];
+                    self.bind_arm(&arm.pattern, value, &mut decision, &mut shadowed);
+                    let value = inspect!(&arm.value);
+                    for (name, previous) in shadowed.into_iter().rev() {
+                        self.unbind(parent, &name, previous);
+                    }
+                    push!("v_{result} = v_{value}; t_{result} = t_{value}; goto m_{result}_end;");
                 }
+                push!("m_{result}_end:;");
 
-                self.inspect(&f.value, fid);
-                push!(fid => "*(Kind*)tr = t_{};", self.it);
-                push!(fid => "return (void*)v_{};", self.it);
+                result
             }
 
+            Term::Var(v) => getvar!(v),
+
+            Term::Print(p) => {
+                let location = self.at(&p.location);
+                let value = inspect!(&p.value);
+
+                push!("p(&v_{value}, t_{value}, {location});");
+                value
+            }
+
+            Term::Function(f) => self.function(f, None, parent)?,
+
             Term::Call(c) => {
-                let f;
-
-                match &*c.callee {
-                    Term::Var(v) => {
-                        f = *self
-                            .named_functions
-                            .get(&v.text)
-                            .expect(&format!("unable to find function named: {}", v.text));
+                let location = self.at(&c.location);
+                let callee = inspect!(&c.callee);
+                let mut arguments = vec![];
+                for argument in &c.arguments {
+                    arguments.push(inspect!(argument));
+                }
+                let result = lazy!();
+
+                let blyat = arguments
+                    .iter()
+                    .map(|x| format!(", v_{x}, t_{x}"))
+                    .collect::<String>();
+
+                match self.known_functions.get(&callee) {
+                    Some(f) if self.functions[f] == arguments.len() => {
+                        push!("Deeper({location}); v_{result} = f_{f}(&t_{result}, v_{callee}.fn{blyat});");
                     }
 
-                    s => {
-                        f = inspect!(s);
+                    _ => {
+                        let signature = ", Any, Kind".repeat(arguments.len());
+                        push!(
+                            "v_{result} = ((Any (*)(Kind *, Closure *{signature}))Callable(v_{callee}, t_{callee}, {}, {location})->code)(&t_{result}, v_{callee}.fn{blyat});",
+                            arguments.len()
+                        );
                     }
                 }
 
-                let result = lazy!();
-                let blyat = c
-                    .arguments
-                    .iter()
-                    .map(|x| inspect!(x))
-                    .map(|x| format!("&v_{x},&t_{x}"))
-                    .collect::<Vec<_>>()
-                    .join(",");
-
-                let blyat = if blyat.len() > 0 {
-                    format!(",{blyat}")
-                } else {
-                    format!("")
-                };
+                result
+            }
 
-                // self.it += 1;
-                push!("(void* (*)(void*, ...)) v_{result} = (f_{f}) (&t_{result} {blyat});");
-                return result;
+            Term::Import(import) => {
+                return Err(GenError::MisplacedImport {
+                    source_code: import.location.file.named_source(),
+                    err_span: import.location.clone().into(),
+                })
             }
 
-            e => eprintln!("ToukaGen: unimplemented {e:?}!"),
-        }
-        return self.it;
-    }
+            Term::Error(e) => {
+                return Err(GenError::InvalidTerm {
+                    message: e.message.clone(),
+                    source_code: e.location.file.named_source(),
+                    err_span: e.location.clone().into(),
+                })
+            }
+        };
 
-    pub fn write(self: Self) -> GenericResult<()> {
-        let mut output = File::create("output.c")?;
+        Ok(it)
+    }
 
-        writeln!(output, "{}", include_str!("yamero.c"))?;
+    fn write_locals(&self, output: &mut impl Write, function: usize) -> io::Result<()> {
+        for id in self.locals.get(&function).into_iter().flatten() {
+            writeln!(output, "Any v_{id}; Kind t_{id};")?;
+        }
 
-        for (j, (k, v)) in self.constants {
-            writeln!(output, "{} v_{} = {};", k, j, v)?
+        for item in self.prologue.get(&function).into_iter().flatten() {
+            writeln!(output, "{item}")?;
         }
 
-        for (j, k) in self.types {
-            writeln!(output, "Kind t_{j} = {k};")?;
+        for item in self.evaluation_queue.get(&function).into_iter().flatten() {
+            writeln!(output, "{item}")?;
         }
 
-        for (j, k) in self.named_functions {
-            writeln!(output, "/* fn: {j} */")?;
-            let f_args = *self.functions.get(&k).unwrap();
-            let args = (0..f_args * 2)
-                .map(|x| format!("void *a_{x}"))
-                .collect::<Vec<_>>()
-                .join(", ");
-            let args = if args.len() > 0 {
-                format!(",{args}")
-            } else {
-                format!("")
-            };
-            writeln!(output, "fnDecl(f_{k} {args}){{")?;
+        Ok(())
+    }
 
-            if let Some(eq) = self.evaluation_queue.get(&k) {
-                for item in eq {
-                    writeln!(output, "{item}")?;
-                }
-            }
+    /// Writes the translation unit to `output`, the runtime and then the
+    /// program, and returns it.
+    pub fn write(&self, mut output: impl Write) -> io::Result<String> {
+        let mut unit = vec![];
+        self.write_unit(&mut unit)?;
+        output.write_all(&unit)?;
 
-            writeln!(output, "}}")?;
+        Ok(String::from_utf8(unit).expect("the translation unit is UTF-8"))
+    }
+
+    fn write_unit(&self, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "{}", include_str!("yamero.c"))?;
+
+        for (j, v) in &self.constants {
+            writeln!(output, "Any v_{j} = {v};")?;
         }
 
-        writeln!(output, "int main(void) {{")?;
+        for (j, k) in &self.types {
+            writeln!(output, "Kind t_{j} = {k};")?;
+        }
 
-        for (id, expr) in self.runtime_queue {
-            writeln!(output, "v_{id} = {expr};")?;
+        let signature = |arity: usize| {
+            (0..arity * 2)
+                .map(|x| match x % 2 {
+                    0 => format!(", Any a_{x}"),
+                    _ => format!(", Kind a_{x}"),
+                })
+                .collect::<String>()
+        };
+
+        for declaration in self.externals.values() {
+            writeln!(output, "{declaration}")?;
         }
 
-        if let Some(eq) = self.evaluation_queue.get(&FN_MAIN) {
-            for item in eq {
-                writeln!(output, "{item}")?;
-            }
+        for (k, arity) in &self.functions {
+            writeln!(output, "fnDecl(f_{k}{});", signature(*arity))?;
         }
 
-        for item in self.print_queue {
-            writeln!(output, "p((void*)&v_{item}, t_{item});")?;
+        for (k, arity) in &self.functions {
+            writeln!(output, "/* fn: {k} */")?;
+            writeln!(output, "fnDecl(f_{k}{}) {{", signature(*arity))?;
+            self.write_locals(output, *k)?;
+            writeln!(output, "}}")?;
         }
 
-        writeln!(output, "return 0;}}")?;
+        writeln!(output, "void Program(void) {{")?;
+        self.write_locals(output, FN_MAIN)?;
+        writeln!(output, "}}")?;
+        writeln!(output, "int main(int argc, char **argv) {{")?;
+        writeln!(output, "Init(argc, argv);")?;
+        writeln!(output, "return Run(Program);}}")?;
 
         Ok(())
     }
 
-    pub fn generate(self: &mut Self, source: AstRoot) -> GenericResult<()> {
-        self.inspect(&source.expression, FN_MAIN);
-        // match source.expression {
-        //     crate::ast::Term::Error(_) => todo!(),
-        //     crate::ast::Term::Int(_) => todo!(),
-        //     crate::ast::Term::Str(_) => todo!(),
-        //     crate::ast::Term::Call(_) => todo!(),
-        //     crate::ast::Term::Binary(_) => todo!(),
-        //     crate::ast::Term::Function(_) => todo!(),
-        //     t @ crate::ast::Term::Let(_) => {
-        //         inspect!(&t);
-        //     }
-        //     crate::ast::Term::If(_) => todo!(),
-        //     crate::ast::Term::Print(what) => {
-
-        //     }
-        //     crate::ast::Term::First(_) => todo!(),
-        //     crate::ast::Term::Second(_) => todo!(),
-        //     crate::ast::Term::Bool(_) => todo!(),
-        //     crate::ast::Term::Tuple(_) => todo!(),
-        //     crate::ast::Term::Var(_) => todo!(),
-        // }
+    /// The warnings found while generating the code, they are reported
+    /// only once.
+    pub fn take_warnings(&mut self) -> Vec<MatchWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn generate(&mut self, source: AstRoot) -> Result<(), GenError> {
+        self.inspect(&source.expression, FN_MAIN)?;
 
         Ok(())
     }
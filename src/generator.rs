@@ -0,0 +1,349 @@
+use crate::ast::{
+    Binary, BinaryOp, Bool, Call, File, FileId, First, Function, If, Int, Let, Location, Print, Second, Str, Term, Tuple,
+};
+use crate::parser::Var;
+
+/// How deep the terms go, the ones deeper are literals and names.
+const DEPTH: usize = 6;
+
+/// The type of a generated term.
+#[derive(Debug, Clone, PartialEq)]
+enum Ty {
+    Int,
+    Bool,
+    Str,
+    Tuple(Box<Ty>, Box<Ty>),
+    Function(Vec<Ty>, Box<Ty>),
+}
+
+/// Generates random programs that are well typed: they never fail at run
+/// time, and they always finish, there's no recursion.
+///
+/// The choices come from bytes, the input of a fuzzer or the ones of
+/// [`entropy`], and when they run out every choice is the first one, so
+/// the program is over soon.
+pub struct Generator<'a> {
+    bytes: &'a [u8],
+    /* bound names and their types, the innermost last */
+    names: Vec<(String, Ty)>,
+    fresh: usize,
+    location: Location,
+}
+
+/// Random bytes for the [`Generator`], from a seed, for the runs without a
+/// fuzzer.
+pub fn entropy(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed;
+
+    // SplitMix64.
+    let mut next = move || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    (0..(len + 7) / 8).flat_map(|_| next().to_le_bytes()).take(len).collect()
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            names: vec![],
+            fresh: 0,
+            location: Location::new(0, 0, FileId::new("<generated>", "")),
+        }
+    }
+
+    /// A number below `n`, zero once the bytes run out.
+    fn choose(&mut self, n: usize) -> usize {
+        match self.bytes.split_first() {
+            Some((byte, rest)) => {
+                self.bytes = rest;
+                *byte as usize % n
+            }
+            None => 0,
+        }
+    }
+
+    fn var(&self, text: &str) -> Var {
+        Var {
+            text: text.to_string(),
+            location: self.location.clone(),
+        }
+    }
+
+    /// One of the names.
+    fn pick(&mut self, names: &[String]) -> Term {
+        let idx = self.choose(names.len());
+        Term::Var(self.var(&names[idx]))
+    }
+
+    fn bind(&mut self, ty: Ty) -> String {
+        self.fresh += 1;
+        let name = format!("v{}", self.fresh);
+        self.names.push((name.clone(), ty));
+        name
+    }
+
+    fn ty(&mut self, depth: usize) -> Ty {
+        match self.choose(if depth < DEPTH { 8 } else { 3 }) {
+            0 | 3 => Ty::Int,
+            1 | 4 => Ty::Bool,
+            2 | 5 => Ty::Str,
+            6 => Ty::Tuple(self.ty(depth + 1).into(), self.ty(depth + 1).into()),
+            _ => {
+                let parameters = (0..self.choose(3)).map(|_| self.ty(depth + 1)).collect();
+                Ty::Function(parameters, self.ty(depth + 1).into())
+            }
+        }
+    }
+
+    fn int(&mut self) -> Term {
+        let value = match self.choose(4) {
+            0 => self.choose(10) as i32,
+            1 => self.choose(256) as i32 * 1000,
+            2 => i32::MAX - self.choose(4) as i32,
+            _ => i32::from_le_bytes([self.choose(256), self.choose(256), self.choose(256), self.choose(128)].map(|b| b as u8)),
+        };
+
+        Term::Int(Int {
+            value,
+            location: self.location.clone(),
+        })
+    }
+
+    fn binary(&mut self, lhs: Term, op: BinaryOp, rhs: Term) -> Term {
+        Term::Binary(Binary {
+            lhs: lhs.into(),
+            op,
+            rhs: rhs.into(),
+            location: self.location.clone(),
+        })
+    }
+
+    /// A literal of a type, or a function that makes one.
+    fn literal(&mut self, ty: &Ty, depth: usize) -> Term {
+        match ty {
+            Ty::Int => self.int(),
+
+            Ty::Bool => Term::Bool(Bool {
+                value: self.choose(2) == 1,
+                location: self.location.clone(),
+            }),
+
+            Ty::Str => {
+                let len = self.choose(6);
+                let value = (0..len).map(|_| b"abc xyz01"[self.choose(9)] as char).collect();
+                Term::Str(Str {
+                    value,
+                    location: self.location.clone(),
+                })
+            }
+
+            Ty::Tuple(first, second) => Term::Tuple(Tuple {
+                first: self.term(first, depth + 1).into(),
+                second: self.term(second, depth + 1).into(),
+                location: self.location.clone(),
+            }),
+
+            Ty::Function(parameters, result) => {
+                let bound = self.names.len();
+                let parameters = parameters.iter().map(|ty| self.bind(ty.clone())).collect::<Vec<_>>();
+                let value = self.term(result, depth + 1);
+                self.names.truncate(bound);
+
+                Term::Function(Function {
+                    parameters: parameters.iter().map(|name| self.var(name)).collect(),
+                    annotations: vec![],
+                    value: value.into(),
+                    location: self.location.clone(),
+                })
+            }
+        }
+    }
+
+    /// A term of a type.
+    fn term(&mut self, ty: &Ty, depth: usize) -> Term {
+        let names: Vec<_> = self.names.iter().filter(|(_, other)| other == ty).map(|(name, _)| name.clone()).collect();
+
+        if depth >= DEPTH {
+            return match names.is_empty() {
+                true => self.literal(ty, depth),
+                false => self.pick(&names),
+            };
+        }
+
+        match self.choose(12) {
+            0 => self.literal(ty, depth),
+            1 if !names.is_empty() => self.pick(&names),
+
+            2 => {
+                let condition = self.term(&Ty::Bool, depth + 1);
+                Term::If(If {
+                    condition: condition.into(),
+                    then: self.term(ty, depth + 1).into(),
+                    otherwise: self.term(ty, depth + 1).into(),
+                    location: self.location.clone(),
+                })
+            }
+
+            3 => {
+                let value_ty = self.ty(depth + 1);
+                let value = self.term(&value_ty, depth + 1);
+                let name = self.bind(value_ty);
+                let next = self.term(ty, depth + 1);
+                self.names.pop();
+
+                Term::Let(Let {
+                    name: self.var(&name),
+                    ty: None,
+                    value: value.into(),
+                    next: next.into(),
+                    location: self.location.clone(),
+                })
+            }
+
+            4 => Term::Print(Print {
+                value: self.term(ty, depth + 1).into(),
+                location: self.location.clone(),
+            }),
+
+            5 => {
+                let parameters: Vec<_> = (0..self.choose(3)).map(|_| self.ty(depth + 1)).collect();
+                let callee = self.term(&Ty::Function(parameters.clone(), ty.clone().into()), depth + 1);
+                let arguments = parameters.iter().map(|parameter| self.term(parameter, depth + 1)).collect();
+
+                Term::Call(Call {
+                    callee: callee.into(),
+                    arguments,
+                    location: self.location.clone(),
+                })
+            }
+
+            6 => {
+                let other = self.ty(depth + 1);
+                let value = self.term(&Ty::Tuple(ty.clone().into(), other.into()), depth + 1);
+                Term::First(First {
+                    value: value.into(),
+                    location: self.location.clone(),
+                })
+            }
+
+            7 => {
+                let other = self.ty(depth + 1);
+                let value = self.term(&Ty::Tuple(other.into(), ty.clone().into()), depth + 1);
+                Term::Second(Second {
+                    value: value.into(),
+                    location: self.location.clone(),
+                })
+            }
+
+            _ => self.operation(ty, depth),
+        }
+    }
+
+    /// An operator that makes a value of the type.
+    fn operation(&mut self, ty: &Ty, depth: usize) -> Term {
+        match ty {
+            Ty::Int => {
+                let lhs = self.term(&Ty::Int, depth + 1);
+                let (op, rhs) = match self.choose(5) {
+                    0 => (BinaryOp::Add, self.term(&Ty::Int, depth + 1)),
+                    1 => (BinaryOp::Sub, self.term(&Ty::Int, depth + 1)),
+                    2 => (BinaryOp::Mul, self.term(&Ty::Int, depth + 1)),
+
+                    // The divisors are never zero.
+                    op => {
+                        let divisor = Term::Int(Int {
+                            value: self.choose(9) as i32 + 1,
+                            location: self.location.clone(),
+                        });
+                        (if op == 3 { BinaryOp::Div } else { BinaryOp::Rem }, divisor)
+                    }
+                };
+
+                self.binary(lhs, op, rhs)
+            }
+
+            Ty::Bool => {
+                let (operands, ops): (Ty, &[BinaryOp]) = match self.choose(3) {
+                    0 => (Ty::Bool, &[BinaryOp::And, BinaryOp::Or, BinaryOp::Eq, BinaryOp::Neq]),
+                    1 => (Ty::Str, &[BinaryOp::Eq, BinaryOp::Neq, BinaryOp::Lt, BinaryOp::Gte]),
+                    _ => (
+                        Ty::Int,
+                        &[BinaryOp::Eq, BinaryOp::Neq, BinaryOp::Lt, BinaryOp::Gt, BinaryOp::Lte, BinaryOp::Gte],
+                    ),
+                };
+
+                let op = ops[self.choose(ops.len())];
+                let lhs = self.term(&operands, depth + 1);
+                let rhs = self.term(&operands, depth + 1);
+                self.binary(lhs, op, rhs)
+            }
+
+            Ty::Str => {
+                let (lhs, rhs) = match self.choose(3) {
+                    0 => (Ty::Str, Ty::Str),
+                    1 => (Ty::Str, Ty::Int),
+                    _ => (Ty::Int, Ty::Str),
+                };
+
+                let lhs = self.term(&lhs, depth + 1);
+                let rhs = self.term(&rhs, depth + 1);
+                self.binary(lhs, BinaryOp::Add, rhs)
+            }
+
+            ty => self.literal(ty, depth),
+        }
+    }
+
+    /// A program: some bindings, each printed, and a value printed at the
+    /// end.
+    pub fn file(mut self) -> File {
+        let mut bindings = vec![];
+        for _ in 0..self.choose(6) + 1 {
+            let ty = self.ty(0);
+            let value = self.term(&ty, 0);
+            bindings.push((self.bind(ty), value));
+        }
+
+        let ty = self.ty(0);
+        let last = self.term(&ty, 0);
+        let mut expression = Term::Print(Print {
+            value: last.into(),
+            location: self.location.clone(),
+        });
+
+        for (name, value) in bindings.into_iter().rev() {
+            let print = Term::Print(Print {
+                value: Term::Var(self.var(&name)).into(),
+                location: self.location.clone(),
+            });
+
+            let sequence = Term::Let(Let {
+                name: self.var("_"),
+                ty: None,
+                value: print.into(),
+                next: expression.into(),
+                location: self.location.clone(),
+            });
+
+            expression = Term::Let(Let {
+                name: self.var(&name),
+                ty: None,
+                value: value.into(),
+                next: sequence.into(),
+                location: self.location.clone(),
+            });
+        }
+
+        File {
+            name: "<generated>".to_string(),
+            expression,
+            location: self.location,
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::ast::{BinaryOp, Element, File as AstRoot, Function, Location, Pattern, Term, Type};
+use crate::gen::{GenError, Known};
+use crate::patterns::MatchWarning;
+
+/// How the script represents the program.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    /// The ints are BigInts, so they don't wrap around like the C ones.
+    pub bigint: bool,
+}
+
+/// Translates a file to a script that runs by itself on node and in the
+/// browsers, the runtime is `yamero.js`.
+///
+/// Each name gets its own JavaScript binding, so shadowing works the way
+/// it does in the bytecode and the names can't clash with the keywords.
+#[derive(Default)]
+pub struct Emitter {
+    options: Options,
+    /* bound names and their bindings, the innermost last */
+    names: Vec<(String, String)>,
+    /* what's known of the bindings, for the annotations */
+    known: HashMap<String, Known>,
+    bindings: usize,
+    locations: Vec<String>,
+    indent: usize,
+    warnings: Vec<MatchWarning>,
+}
+
+impl Emitter {
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Binds a name to a new binding, and returns it.
+    fn bind(&mut self, name: &str) -> String {
+        self.bindings += 1;
+        let text: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '$' })
+            .collect();
+
+        let binding = format!("{text}${}", self.bindings);
+        self.names.push((name.to_string(), binding.clone()));
+        binding
+    }
+
+    /// A temporary binding, for the values being taken apart.
+    fn temporary(&mut self) -> String {
+        self.bindings += 1;
+        format!("$t{}", self.bindings)
+    }
+
+    fn line(&self, out: &mut String, text: &str) {
+        writeln!(out, "{}{text}", "  ".repeat(self.indent)).unwrap();
+    }
+
+    fn at(&mut self, location: &Location) -> String {
+        self.locations.push(location.to_string());
+        format!("$at{}", self.locations.len() - 1)
+    }
+
+    fn string(s: &str) -> String {
+        serde_json::to_string(s).expect("strings are always JSON")
+    }
+
+    /// A string of the program, a character for each byte like the
+    /// runtime has them.
+    fn bytes(s: &str) -> String {
+        Self::string(&s.bytes().map(char::from).collect::<String>())
+    }
+
+    fn int(&self, value: i32) -> String {
+        let suffix = if self.options.bigint { "n" } else { "" };
+        match value < 0 {
+            true => format!("({value}{suffix})"),
+            false => format!("{value}{suffix}"),
+        }
+    }
+
+    /// What's known of a term, from the names bound where it is.
+    fn known(&self, term: &Term) -> Option<Known> {
+        let var = |name: &str| {
+            let (_, binding) = self.names.iter().rev().find(|(other, _)| other == name)?;
+            self.known.get(binding).cloned()
+        };
+
+        crate::gen::known(term, &var)
+    }
+
+    /// Checks a value against a type, at compile time when it's known,
+    /// otherwise at run time.
+    fn check(&mut self, value: String, term: &Term, ty: Type) -> Result<String, GenError> {
+        match crate::gen::annotate(self.known(term), ty, term.location())? {
+            true => Ok(value),
+            false => Ok(self.expect(&value, ty, term.location())),
+        }
+    }
+
+    fn expect(&mut self, value: &str, ty: Type, location: &Location) -> String {
+        let kind = match ty {
+            Type::Int => "int",
+            Type::Str => "string",
+            Type::Bool => "bool",
+        };
+
+        format!("$expect({value}, \"{kind}\", {})", self.at(location))
+    }
+
+    fn function(&mut self, f: &Function) -> Result<String, GenError> {
+        let bound = self.names.len();
+        let mut checks = vec![];
+
+        let mut parameters = vec![];
+        for (idx, p) in f.parameters.iter().enumerate() {
+            let binding = self.bind(&p.text);
+            if let Some(Some(ty)) = f.annotations.get(idx) {
+                checks.push(format!("{};", self.expect(&binding, *ty, &p.location)));
+                self.known.insert(binding.clone(), Known::Type(*ty));
+            }
+            parameters.push(binding);
+        }
+
+        let parameters = parameters.join(", ");
+        let function = match &*f.value {
+            Term::Let(_) | Term::Destructure(_) => None,
+            _ if !checks.is_empty() => None,
+            value => Some(format!("(({parameters}) => {})", self.expr(value)?)),
+        };
+
+        let function = match function {
+            Some(function) => function,
+            None => {
+                let mut body = String::new();
+                self.indent += 1;
+                for check in &checks {
+                    self.line(&mut body, check);
+                }
+                self.block(&f.value, &mut body)?;
+                self.indent -= 1;
+
+                format!("(({parameters}) => {{\n{body}{}}})", "  ".repeat(self.indent))
+            }
+        };
+
+        self.names.truncate(bound);
+        Ok(function)
+    }
+
+    /// Binds the names of a `let` pattern to the parts of `value`.
+    fn destructure(&mut self, pattern: &Pattern, value: &str, out: &mut String) -> Result<(), GenError> {
+        match pattern {
+            Pattern::Var(name) => {
+                let binding = self.bind(&name.text);
+                self.line(out, &format!("const {binding} = {value};"));
+            }
+
+            Pattern::Wildcard(_) => {}
+
+            Pattern::Int(_) | Pattern::Str(_) | Pattern::Bool(_) => {
+                return Err(GenError::RefutablePattern {
+                    source_code: pattern.location().file.named_source(),
+                    err_span: pattern.location().clone().into(),
+                })
+            }
+
+            Pattern::Tuple(t) => {
+                let at = self.at(&t.location);
+                self.line(out, &format!("$tuple({value}, {at});"));
+                self.destructure(&t.first, &format!("{value}[0]"), out)?;
+                self.destructure(&t.second, &format!("{value}[1]"), out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The conditions for `value` to match the pattern of an arm, and the
+    /// names it binds.
+    fn test(&mut self, pattern: &Pattern, value: &str, conditions: &mut Vec<String>, out: &mut String) {
+        match pattern {
+            Pattern::Var(name) => {
+                let binding = self.bind(&name.text);
+                self.line(out, &format!("const {binding} = {value};"));
+            }
+
+            Pattern::Wildcard(_) => {}
+
+            Pattern::Tuple(t) => {
+                conditions.push(format!("Array.isArray({value})"));
+                self.test(&t.first, &format!("{value}[0]"), conditions, out);
+                self.test(&t.second, &format!("{value}[1]"), conditions, out);
+            }
+
+            Pattern::Int(i) => conditions.push(format!("{value} === {}", self.int(i.value))),
+            Pattern::Str(s) => conditions.push(format!("{value} === {}", Self::bytes(&s.value))),
+            Pattern::Bool(b) => conditions.push(format!("{value} === {}", b.value)),
+        }
+    }
+
+    /// Writes the statements of a term, returning its value.
+    fn block(&mut self, term: &Term, out: &mut String) -> Result<(), GenError> {
+        let bound = self.names.len();
+        let mut term = term;
+
+        loop {
+            match term {
+                Term::Let(r) => {
+                    let found = self.known(&r.value);
+                    let value = match &*r.value {
+                        // The function sees itself, for the recursion.
+                        Term::Function(f) => {
+                            let binding = self.bind(&r.name.text);
+                            self.known.insert(binding.clone(), Known::Function);
+                            (binding, self.function(f)?)
+                        }
+                        value => {
+                            let value = self.expr(value)?;
+                            (self.bind(&r.name.text), value)
+                        }
+                    };
+
+                    let (binding, mut value) = value;
+                    if let Some(ty) = r.ty {
+                        if !crate::gen::annotate(found.clone(), ty, r.value.location())? {
+                            value = self.expect(&value, ty, r.value.location());
+                        }
+                    }
+
+                    if let Some(known) = r.ty.map(Known::Type).or(found) {
+                        self.known.insert(binding.clone(), known);
+                    }
+
+                    // The sequences, `a; b` is a `let _ = a; b`.
+                    match r.name.text.as_str() {
+                        "_" => self.line(out, &format!("{value};")),
+                        _ => self.line(out, &format!("const {binding} = {value};")),
+                    }
+                    term = &r.next;
+                }
+
+                Term::Destructure(d) => {
+                    let value = self.expr(&d.value)?;
+                    let temporary = self.temporary();
+                    self.line(out, &format!("const {temporary} = {value};"));
+
+                    self.destructure(&d.pattern, &temporary, out)?;
+                    term = &d.next;
+                }
+
+                term => {
+                    let value = self.expr(term)?;
+                    self.line(out, &format!("return {value};"));
+                    break;
+                }
+            }
+        }
+
+        self.names.truncate(bound);
+        Ok(())
+    }
+
+    /// A block as an expression, a function called right away with the
+    /// parameters `f` returns.
+    fn scoped(
+        &mut self,
+        arguments: &str,
+        f: impl FnOnce(&mut Self, &mut String) -> Result<String, GenError>,
+    ) -> Result<String, GenError> {
+        let mut body = String::new();
+        self.indent += 1;
+        let parameters = f(self, &mut body)?;
+        self.indent -= 1;
+
+        Ok(format!("(({parameters}) => {{\n{body}{}}})({arguments})", "  ".repeat(self.indent)))
+    }
+
+    /// The expression of a term.
+    fn expr(&mut self, term: &Term) -> Result<String, GenError> {
+        macro_rules! unary {
+            ($value:expr, $format:literal) => {{
+                let value = self.expr($value)?;
+                let at = self.at(term.location());
+                format!($format, value = value, at = at)
+            }};
+        }
+
+        let expr = match term {
+            Term::Int(i) => self.int(i.value),
+            Term::Str(s) => Self::bytes(&s.value),
+            Term::Bool(b) => b.value.to_string(),
+
+            Term::Var(v) => match self.names.iter().rev().find(|(name, _)| *name == v.text) {
+                Some((_, binding)) => binding.clone(),
+                None => {
+                    return Err(GenError::UnboundVariable {
+                        name: v.text.clone(),
+                        source_code: v.location.file.named_source(),
+                        err_span: v.location.clone().into(),
+                    })
+                }
+            },
+
+            Term::If(i) => {
+                let condition = self.expr(&i.condition)?;
+                let at = self.at(i.condition.location());
+                let then = self.expr(&i.then)?;
+                let otherwise = self.expr(&i.otherwise)?;
+                format!("($bool({condition}, {at}) ? {then} : {otherwise})")
+            }
+
+            Term::Binary(b) => {
+                let (lhs, rhs) = (self.expr(&b.lhs)?, self.expr(&b.rhs)?);
+                let at = self.at(&b.location);
+
+                let op = match b.op {
+                    BinaryOp::And | BinaryOp::Or => {
+                        let (lhs_at, rhs_at) = (self.at(b.lhs.location()), self.at(b.rhs.location()));
+                        let op = if b.op == BinaryOp::And { "&&" } else { "||" };
+                        return Ok(format!("($bool({lhs}, {lhs_at}) {op} $bool({rhs}, {rhs_at}))"));
+                    }
+
+                    BinaryOp::Add => return Ok(format!("$add({lhs}, {rhs}, {at})")),
+                    BinaryOp::Sub => ("$arithmetic", "-"),
+                    BinaryOp::Mul => ("$arithmetic", "*"),
+                    BinaryOp::Div => ("$arithmetic", "/"),
+                    BinaryOp::Rem => ("$arithmetic", "%"),
+                    BinaryOp::Eq => ("$compare", "=="),
+                    BinaryOp::Neq => ("$compare", "!="),
+                    BinaryOp::Lt => ("$compare", "<"),
+                    BinaryOp::Gt => ("$compare", ">"),
+                    BinaryOp::Lte => ("$compare", "<="),
+                    BinaryOp::Gte => ("$compare", ">="),
+                };
+
+                format!("{}(\"{}\", {lhs}, {rhs}, {at})", op.0, op.1)
+            }
+
+            Term::Let(_) | Term::Destructure(_) => self.scoped("", |this, body| {
+                this.block(term, body)?;
+                Ok(String::new())
+            })?,
+
+            Term::Match(m) => {
+                self.warnings.extend(crate::patterns::check(m));
+
+                let value = self.expr(&m.value)?;
+                let temporary = self.temporary();
+
+                self.scoped(&value, |this, body| {
+                    for arm in &m.arms {
+                        let bound = this.names.len();
+                        let (mut conditions, mut bindings) = (vec![], String::new());
+
+                        this.indent += 1;
+                        this.test(&arm.pattern, &temporary, &mut conditions, &mut bindings);
+                        this.block(&arm.value, &mut bindings)?;
+                        this.indent -= 1;
+                        this.names.truncate(bound);
+
+                        match conditions.is_empty() {
+                            true => this.line(body, "{"),
+                            false => this.line(body, &format!("if ({}) {{", conditions.join(" && "))),
+                        }
+                        body.push_str(&bindings);
+                        this.line(body, "}");
+                    }
+
+                    let at = this.at(&m.location);
+                    this.line(body, &format!("return $noMatch({at});"));
+                    Ok(temporary.clone())
+                })?
+            }
+
+            Term::Function(f) => self.function(f)?,
+
+            Term::Call(c) => {
+                let callee = self.expr(&c.callee)?;
+                let mut operands = vec![callee, self.at(&c.location)];
+                for argument in &c.arguments {
+                    operands.push(self.expr(argument)?);
+                }
+                format!("$call({})", operands.join(", "))
+            }
+
+            Term::Print(p) => format!("$print({})", self.expr(&p.value)?),
+
+            Term::Tuple(t) => {
+                let (first, second) = (self.expr(&t.first)?, self.expr(&t.second)?);
+                format!("Object.freeze([{first}, {second}])")
+            }
+
+            Term::First(t) => unary!(&t.value, "$tuple({value}, {at})[0]"),
+            Term::Second(t) => unary!(&t.value, "$tuple({value}, {at})[1]"),
+
+            Term::Record(r) => {
+                let mut fields = vec![];
+                for field in &r.fields {
+                    fields.push(format!("{}: {}", Self::string(&field.name.text), self.expr(&field.value)?));
+                }
+                format!("Object.freeze({{{}}})", fields.join(", "))
+            }
+
+            Term::FieldAccess(access) => {
+                crate::gen::access(self.known(&access.value), access)?;
+
+                let value = self.expr(&access.value)?;
+                let name = Self::string(&access.name.text);
+                format!("$field({value}, {name}, {})", self.at(&access.location))
+            }
+
+            Term::List(l) => {
+                let mut elements = vec![];
+                for element in &l.elements {
+                    elements.push(self.expr(element)?);
+                }
+                format!("$list([{}])", elements.join(", "))
+            }
+
+            Term::Cons(c) => {
+                let (head, tail) = (self.expr(&c.head)?, self.expr(&c.tail)?);
+                format!("$cons({head}, {tail}, {})", self.at(&c.location))
+            }
+
+            Term::Head(h) => unary!(&h.value, "$cell({value}, {at}).head"),
+            Term::Tail(t) => unary!(&t.value, "$cell({value}, {at}).tail"),
+            Term::Len(l) => unary!(&l.value, "$len({value}, {at})"),
+            Term::ToStr(t) => unary!(&t.value, "$toStr({value}, {at})"),
+
+            Term::Substr(sub) => {
+                let value = self.expr(&sub.value)?;
+                let value = self.check(value, &sub.value, Type::Str)?;
+                let start = self.expr(&sub.start)?;
+                let start = self.check(start, &sub.start, Type::Int)?;
+                let length = self.expr(&sub.length)?;
+                let length = self.check(length, &sub.length, Type::Int)?;
+                format!("$substr({value}, {start}, {length}, {})", self.at(&sub.location))
+            }
+
+            Term::ToInt(t) => {
+                let value = self.expr(&t.value)?;
+                let value = self.check(value, &t.value, Type::Str)?;
+                format!("$toInt({value}, {})", self.at(&t.location))
+            }
+
+            Term::Contains(c) => {
+                let (value, pattern) = (self.expr(&c.value)?, self.expr(&c.pattern)?);
+                let value = self.check(value, &c.value, Type::Str)?;
+                let pattern = self.check(pattern, &c.pattern, Type::Str)?;
+                format!("{value}.includes({pattern})")
+            }
+
+            Term::ReadLine(r) => format!("$readLine({})", self.at(&r.location)),
+            Term::Argv(_) => "$argv()".to_string(),
+
+            Term::External(ext) => {
+                return Err(GenError::UnsupportedExternal {
+                    source_code: ext.location.file.named_source(),
+                    err_span: ext.location.clone().into(),
+                })
+            }
+
+            Term::Import(import) => {
+                return Err(GenError::MisplacedImport {
+                    source_code: import.location.file.named_source(),
+                    err_span: import.location.clone().into(),
+                })
+            }
+
+            Term::Error(e) => {
+                return Err(GenError::InvalidTerm {
+                    message: e.message.clone(),
+                    source_code: e.location.file.named_source(),
+                    err_span: e.location.clone().into(),
+                })
+            }
+        };
+
+        Ok(expr)
+    }
+
+    /// The warnings found while emitting, they are reported only once.
+    pub fn take_warnings(&mut self) -> Vec<MatchWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// The script of a file, with the runtime in it.
+    pub fn emit_file(&mut self, source: &AstRoot) -> Result<String, GenError> {
+        let mut script = String::new();
+        writeln!(script, "\"use strict\";\n").unwrap();
+        writeln!(script, "const $big = {};\n", self.options.bigint).unwrap();
+        writeln!(script, "{}", include_str!("yamero.js")).unwrap();
+
+        self.indent = 1;
+        let mut main = String::new();
+        self.block(&source.expression, &mut main)?;
+
+        for (idx, location) in self.locations.iter().enumerate() {
+            writeln!(script, "const $at{idx} = {};", Self::string(location)).unwrap();
+        }
+        writeln!(script).unwrap();
+        write!(script, "$main(() => {{\n{main}}});\n").unwrap();
+
+        Ok(script)
+    }
+}
@@ -0,0 +1,162 @@
+//! The Rinha compiler as a library.
+//!
+//! A program is parsed to an [`ast::File`], and then compiled to C or
+//! JavaScript, or run right away on the bytecode virtual machine:
+//!
+//! ```
+//! let file = touka::parse("print(1 + 2)").unwrap();
+//!
+//! let mut out = vec![];
+//! touka::interpret(&file, &mut out).unwrap();
+//! assert_eq!(out, b"3\n");
+//! ```
+//!
+//! The imports are resolved by [`loader::link`], a parsed file that has
+//! them doesn't compile until then.
+
+use std::io::{BufRead, Write};
+
+use lalrpop_util::lalrpop_mod;
+use miette::Diagnostic;
+
+pub mod asm;
+pub mod ast;
+pub mod builtins;
+pub mod bytecode;
+pub mod gen;
+pub mod generator;
+pub mod js;
+pub mod loader;
+pub mod parser;
+pub mod patterns;
+pub mod pretty;
+pub mod vm;
+pub mod wat;
+
+// The lalrpop module, it does generate the parser and lexer
+// for the language.
+lalrpop_mod! {
+    #[allow(warnings)]
+    /// The parsing module
+    pub rinha
+}
+
+pub use js::Options as JsOptions;
+pub use parser::ParseError;
+
+/// The errors that stopped a program from compiling, reported with
+/// miette like the command line does.
+#[derive(thiserror::Error, Debug)]
+#[error("could not compile the program")]
+pub struct Diagnostics {
+    pub errors: Vec<Box<dyn Diagnostic + Send + Sync>>,
+}
+
+impl Diagnostic for Diagnostics {
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(self.errors.iter().map(|error| &**error as &dyn Diagnostic)))
+    }
+}
+
+macro_rules! diagnostics_from {
+    ($($error:ty),*) => {$(
+        impl From<$error> for Diagnostics {
+            fn from(error: $error) -> Self {
+                Self {
+                    errors: vec![Box::new(error)],
+                }
+            }
+        }
+    )*};
+}
+
+diagnostics_from!(ParseError, loader::LoadError, gen::GenError);
+
+/// Why [`interpret`] stopped.
+#[derive(thiserror::Error, Debug)]
+pub enum InterpretError {
+    #[error(transparent)]
+    Compile(#[from] Diagnostics),
+
+    #[error(transparent)]
+    Runtime(#[from] vm::RuntimeError),
+}
+
+/// How [`interpret_with`] runs a program.
+#[derive(Default)]
+pub struct RunOptions {
+    /// What `argv()` returns.
+    pub args: Vec<String>,
+
+    /// Where `read_line()` reads from, the standard input if it's `None`.
+    pub input: Option<Box<dyn BufRead>>,
+}
+
+impl std::fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("args", &self.args)
+            .field("input", &self.input.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
+
+/// Parses a program, its locations are in the `<input>` file. Every
+/// program parsed is a file of its own, even with the same name.
+pub fn parse(source: &str) -> Result<ast::File, ParseError> {
+    parse_named("<input>", source)
+}
+
+/// Parses a program, like [`parse`], with the name of its file in the
+/// locations and the errors.
+pub fn parse_named(name: &str, source: &str) -> Result<ast::File, ParseError> {
+    parser::parse_or_report(name, parser::strip_bom(source))
+}
+
+/// Formats a program, keeping its comments, like `touka fmt` does.
+pub fn format(source: &str) -> Result<String, ParseError> {
+    let source = parser::strip_bom(source);
+    let file = parser::parse_or_report("<input>", source)?;
+
+    Ok(pretty::format(&file, source))
+}
+
+/// The warnings of a program, the arms of its matches that don't cover
+/// every value or are never reached.
+pub fn warnings(file: &ast::File) -> Vec<patterns::MatchWarning> {
+    let mut compiler = bytecode::Compiler::default();
+    let _ = compiler.compile_file(file);
+    compiler.take_warnings()
+}
+
+/// The C translation unit of a program, with the runtime in it.
+pub fn compile_to_c(file: &ast::File) -> Result<String, Diagnostics> {
+    let mut state = gen::State::default();
+    state.generate(file.clone())?;
+
+    Ok(state.write(std::io::sink()).expect("writing to a sink doesn't fail"))
+}
+
+/// The JavaScript script of a program, with the runtime in it.
+pub fn compile_to_js(file: &ast::File, options: JsOptions) -> Result<String, Diagnostics> {
+    Ok(js::Emitter::new(options).emit_file(file)?)
+}
+
+/// Runs a program on the virtual machine, writing what it prints to
+/// `out`.
+pub fn interpret(file: &ast::File, out: impl Write) -> Result<(), InterpretError> {
+    interpret_with(file, out, RunOptions::default())
+}
+
+/// Runs a program on the virtual machine, like [`interpret`], with the
+/// arguments and the input in `options`.
+pub fn interpret_with(file: &ast::File, out: impl Write, options: RunOptions) -> Result<(), InterpretError> {
+    let program = bytecode::Compiler::default().compile_file(file).map_err(Diagnostics::from)?;
+
+    let mut vm = vm::Vm::new(&program, out).args(options.args);
+    if let Some(input) = options.input {
+        vm = vm.input(input);
+    }
+    vm.run()?;
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use miette::{NamedSource, SourceSpan};
+
+use crate::ast::{Element, Field, File, Import, Let, Record, Term};
+use crate::parser::{parse_or_report, strip_bom, ParseError, Var};
+
+/// The error type for the loader, it points to the import that couldn't
+/// be resolved in the file that has it.
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+pub enum LoadError {
+    #[error("can't read `{path}`: {error}")]
+    #[diagnostic(code(zu::unreadable_import), url(docsrs))]
+    Unreadable {
+        path: String,
+        error: std::io::Error,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "imported here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("import cycle: {chain}")]
+    #[diagnostic(
+        code(zu::import_cycle),
+        url(docsrs),
+        help("maybe move the shared bindings to another file")
+    )]
+    Cycle {
+        /// The files of the cycle, in import order.
+        chain: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "imported here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("the last expression of an imported file is never evaluated")]
+    #[diagnostic(
+        code(zu::imported_tail),
+        url(docsrs),
+        help("bind what it does instead, like `let _ = print(\"loaded\");`")
+    )]
+    UnusedTail {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "this is dropped"]
+        err_span: SourceSpan,
+    },
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parse(#[from] ParseError),
+}
+
+/// Resolves the imports of a program.
+///
+/// Every file is parsed once and turned into a module: its top-level
+/// bindings with a record of them in place of the last expression. The
+/// modules are bound before the program, in the order they depend on each
+/// other, with names that can't be written in the source, and every import
+/// is a binding to one of them.
+#[derive(Default)]
+struct Loader {
+    /* canonical path, name of the module */
+    modules: HashMap<PathBuf, String>,
+    /* modules in dependency order */
+    linked: Vec<(String, Term)>,
+    /* files being loaded, for cycle detection, and their shown paths */
+    stack: Vec<(PathBuf, String)>,
+}
+
+/// Path of the file, made absolute so the same file is always found by
+/// the same path.
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Path of the file as it's shown in the errors and the locations: the
+/// path of the program joined with the imports up to it, without the
+/// `..` between them.
+fn shown(path: &Path) -> String {
+    let mut shown = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(shown.components().next_back(), Some(Component::Normal(_))) => {
+                shown.pop();
+            }
+            component => shown.push(component),
+        }
+    }
+
+    shown.display().to_string()
+}
+
+/// Whether an expression does nothing but build a value, so dropping it
+/// changes nothing.
+fn inert(term: &Term) -> bool {
+    match term {
+        Term::Int(_) | Term::Str(_) | Term::Bool(_) | Term::Var(_) | Term::Function(_) => true,
+        Term::Tuple(tuple) => inert(&tuple.first) && inert(&tuple.second),
+        Term::Record(record) => record.fields.iter().all(|field| inert(&field.value)),
+        _ => false,
+    }
+}
+
+/// Replaces the last expression of a file by the record of its
+/// top-level bindings. The expression is dropped without being evaluated,
+/// so it's an error unless it does nothing, what it does belongs in a
+/// binding like `let _ = print("loaded");`.
+fn exports(term: Term, names: &mut Vec<Var>) -> Result<Term, LoadError> {
+    match term {
+        Term::Let(mut r) => {
+            names.push(r.name.clone());
+            r.next = exports(*r.next, names)?.into();
+            Ok(Term::Let(r))
+        }
+
+        Term::Destructure(mut d) => {
+            names.extend(d.pattern.variables().into_iter().cloned());
+            d.next = exports(*d.next, names)?.into();
+            Ok(Term::Destructure(d))
+        }
+
+        Term::External(mut ext) => {
+            names.push(ext.name.clone());
+            ext.next = exports(*ext.next, names)?.into();
+            Ok(Term::External(ext))
+        }
+
+        Term::Import(mut import) => {
+            names.push(import.name.clone());
+            import.next = exports(*import.next, names)?.into();
+            Ok(Term::Import(import))
+        }
+
+        tail if !inert(&tail) => Err(LoadError::UnusedTail {
+            source_code: tail.location().file.named_source(),
+            err_span: tail.location().clone().into(),
+        }),
+
+        tail => {
+            let mut fields: Vec<Field> = vec![];
+
+            for name in names.iter().rev().filter(|name| name.text != "_") {
+                if fields.iter().all(|field| field.name.text != name.text) {
+                    fields.push(Field {
+                        name: name.clone(),
+                        value: Term::Var(name.clone()),
+                    });
+                }
+            }
+
+            fields.reverse();
+
+            Ok(Term::Record(Record {
+                fields,
+                location: tail.location().clone(),
+            }))
+        }
+    }
+}
+
+impl Loader {
+    /// Resolves the imports of the top-level bindings of a file.
+    fn resolve(&mut self, term: Term, dir: &Path) -> Result<Term, LoadError> {
+        match term {
+            Term::Let(mut r) => {
+                r.next = self.resolve(*r.next, dir)?.into();
+                Ok(Term::Let(r))
+            }
+
+            Term::Destructure(mut d) => {
+                d.next = self.resolve(*d.next, dir)?.into();
+                Ok(Term::Destructure(d))
+            }
+
+            Term::External(mut ext) => {
+                ext.next = self.resolve(*ext.next, dir)?.into();
+                Ok(Term::External(ext))
+            }
+
+            Term::Import(import) => {
+                let module = self.module(&import, dir)?;
+                let next = self.resolve(*import.next, dir)?;
+
+                Ok(Term::Let(Let {
+                    name: import.name,
+                    ty: None,
+                    value: Term::Var(Var {
+                        text: module,
+                        location: import.location.clone(),
+                    })
+                    .into(),
+                    next: next.into(),
+                    location: import.location,
+                }))
+            }
+
+            term => Ok(term),
+        }
+    }
+
+    /// Loads the module of an import, returning its name.
+    fn module(&mut self, import: &Import, dir: &Path) -> Result<String, LoadError> {
+        let (path, name) = (canonical(&dir.join(&import.path)), shown(&dir.join(&import.path)));
+
+        if let Some(position) = self.stack.iter().position(|(file, _)| *file == path) {
+            let chain = self.stack[position..]
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .chain([name.as_str()])
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err(LoadError::Cycle {
+                chain,
+                source_code: import.location.file.named_source(),
+                err_span: import.location.clone().into(),
+            });
+        }
+
+        if let Some(name) = self.modules.get(&path) {
+            return Ok(name.clone());
+        }
+
+        let text = std::fs::read_to_string(&path).map_err(|error| LoadError::Unreadable {
+            path: import.path.clone(),
+            error,
+            source_code: import.location.file.named_source(),
+            err_span: import.location.clone().into(),
+        })?;
+
+        let file = parse_or_report(&name, strip_bom(&text))?;
+
+        // The paths of its imports are relative to it, as written.
+        let joined = dir.join(&import.path);
+        let dir = joined.parent().unwrap_or(Path::new("."));
+
+        self.stack.push((path.clone(), name));
+        let expression = self.resolve(file.expression, dir)?;
+        self.stack.pop();
+
+        let name = format!("#module{}", self.modules.len());
+        let module = exports(expression, &mut vec![])?;
+        self.modules.insert(path, name.clone());
+        self.linked.push((name.clone(), module));
+
+        Ok(name)
+    }
+}
+
+/// Resolves the imports of a file read from `path`, binding the modules
+/// it depends on before its expression.
+pub fn link(file: File, path: &Path) -> Result<File, LoadError> {
+    let mut loader = Loader::default();
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    loader.stack.push((canonical(path), shown(path)));
+    let expression = loader.resolve(file.expression, dir)?;
+
+    let expression = loader
+        .linked
+        .into_iter()
+        .rev()
+        .fold(expression, |next, (name, value)| {
+            let location = value.location().clone();
+
+            Term::Let(Let {
+                name: Var {
+                    text: name,
+                    location: location.clone(),
+                },
+                ty: None,
+                value: value.into(),
+                next: next.into(),
+                location,
+            })
+        });
+
+    Ok(File { expression, ..file })
+}
@@ -1,49 +1,223 @@
-use gen::State;
-use lalrpop_util::lalrpop_mod;
 use miette::IntoDiagnostic;
 use std::env::args;
+use std::io::Write;
+use std::path::Path;
+use touka::ast::{FileId, SourceMap};
+use touka::gen::State;
+use touka::{asm, bytecode, js, loader, vm, wat};
 
-pub mod ast;
-mod gen;
-pub mod parser;
+/// What the compiler should write out, `-o` says where.
+enum Emit {
+    /// The C translation unit, to `output.c`.
+    C,
 
-// The lalrpop module, it does generate the parser and lexer
-// for the language.
-lalrpop_mod! {
-    #[allow(warnings)]
-    /// The parsing module
-    pub rinha
+    /// The JSON representation of the AST, to the standard output.
+    Ast,
+
+    /// The disassembly of the bytecode, to the standard output.
+    Bytecode,
+
+    /// The WebAssembly text module, to the standard output.
+    Wat,
+
+    /// The JavaScript script, to the standard output.
+    Js,
 }
 
-/// Strips Byte-Order-Mark (BOM) generated by some programs
-fn strip_bom(s: &str) -> &str {
-    if s.as_bytes().get(0..3) == Some(&[0xEF, 0xBB, 0xBF]) {
-        &s[3..]
-    } else {
-        s
+/// How the program is run.
+enum Backend {
+    /// Through the C translation unit, `output.c`.
+    C,
+
+    /// On the bytecode virtual machine, right away.
+    Vm,
+
+    /// Through the x86-64 assembly, `output.s`, for `as` and `ld`.
+    Asm,
+}
+
+/// Opens where the output goes: the path given to `-o`, or `default`
+/// without it. The standard output is `-`.
+fn open(output: Option<&str>, default: &str) -> miette::Result<Box<dyn Write>> {
+    match output.unwrap_or(default) {
+        "-" => Ok(Box::new(std::io::stdout().lock())),
+        path => Ok(Box::new(std::fs::File::create(path).into_diagnostic()?)),
+    }
+}
+
+/// `touka fmt [--check] files...`, it formats the files in place, or
+/// fails with the ones that aren't formatted.
+fn fmt(args: impl Iterator<Item = String>) -> miette::Result<()> {
+    let mut check = false;
+    let mut files = vec![];
+
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => files.push(arg),
+        }
+    }
+
+    let mut unformatted = vec![];
+    for path in files {
+        let text = std::fs::read_to_string(&path).into_diagnostic()?;
+        let text = touka::parser::strip_bom(&text);
+        let file = touka::parser::parse_or_report(&path, text)?;
+
+        let formatted = touka::pretty::format(&file, text);
+        if formatted == text {
+            continue;
+        }
+
+        match check {
+            true => unformatted.push(path),
+            false => std::fs::write(&path, formatted).into_diagnostic()?,
+        }
     }
+
+    if !unformatted.is_empty() {
+        miette::bail!("these files aren't formatted: {}", unformatted.join(", "));
+    }
+
+    Ok(())
 }
 
 fn app() -> miette::Result<()> {
-    let mut args = args().skip(1);
-    let src = args.next().expect("I need a filename блыат");
+    let mut args = args().skip(1).peekable();
+    if args.next_if(|arg| arg == "fmt").is_some() {
+        return fmt(args);
+    }
+
+    let mut src = None;
+    let mut output = None;
+    let mut emit = None;
+    let mut backend = Backend::C;
+    let mut js = js::Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--emit" => {
+                emit = match args.next().as_deref() {
+                    Some("c") => Some(Emit::C),
+                    Some("ast") => Some(Emit::Ast),
+                    Some("bytecode") => Some(Emit::Bytecode),
+                    Some("wat") => Some(Emit::Wat),
+                    Some("js") => Some(Emit::Js),
+                    other => miette::bail!("unknown emit target: {other:?}"),
+                }
+            }
+
+            "--backend" => {
+                backend = match args.next().as_deref() {
+                    Some("c") => Backend::C,
+                    Some("vm") => Backend::Vm,
+                    Some("asm") => Backend::Asm,
+                    other => miette::bail!("unknown backend: {other:?}"),
+                }
+            }
+
+            "--bigint" => js.bigint = true,
+
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path),
+                None => miette::bail!("`{arg}` needs a path"),
+            },
+
+            // The rest are the arguments of the program.
+            "--" => break,
+
+            _ => src = Some(arg),
+        }
+    }
+
+    let src = src.expect("I need a filename блыат");
+    let output = output.as_deref();
     let file_contents = std::fs::read_to_string(&src).into_diagnostic()?;
-    let file = crate::parser::parse_or_report(&src, strip_bom(&file_contents))?;
-    let mut state = State::default();
 
-    if let Err(e) = state.generate(file) {
-        miette::bail!(e);
+    // The JSON AST points to its original source, that is used for the
+    // line and columns when it's around.
+    let file = if src.ends_with(".json") {
+        let name = touka::parser::json_name(&file_contents).into_diagnostic()?;
+        let mut sources = SourceMap::default();
+        if let Ok(text) = std::fs::read_to_string(&name) {
+            sources.insert(FileId::new(&name, &text));
+        }
+
+        touka::parser::parse_json(&file_contents, &sources).into_diagnostic()?
+    } else {
+        let text = touka::parser::strip_bom(&file_contents);
+
+        touka::parser::parse_or_report(&src, text)?
+    };
+
+    if let Some(Emit::Ast) = emit {
+        let json = serde_json::to_string_pretty(&file).into_diagnostic()?;
+        writeln!(open(output, "-")?, "{json}").into_diagnostic()?;
+        return Ok(());
     }
 
-    if let Err(e) = state.write() {
-        miette::bail!(e);
+    // Imports are relative to the file that has them.
+    let file = loader::link(file, Path::new(&src))?;
+
+    if let Some(Emit::Js) = emit {
+        let mut emitter = js::Emitter::new(js);
+        let script = emitter.emit_file(&file)?;
+
+        for warning in emitter.take_warnings() {
+            eprintln!("{:?}", miette::Report::new(warning));
+        }
+
+        write!(open(output, "-")?, "{script}").into_diagnostic()?;
+        return Ok(());
     }
 
+    if let (Some(Emit::Bytecode | Emit::Wat), _) | (None, Backend::Vm | Backend::Asm) = (&emit, &backend) {
+        let mut compiler = bytecode::Compiler::default();
+        let program = compiler.compile_file(&file)?;
+
+        for warning in compiler.take_warnings() {
+            eprintln!("{:?}", miette::Report::new(warning));
+        }
+
+        if let Some(Emit::Bytecode) = emit {
+            write!(open(output, "-")?, "{program}").into_diagnostic()?;
+            return Ok(());
+        }
+
+        if let Some(Emit::Wat) = emit {
+            wat::write(&program, &mut open(output, "-")?).into_diagnostic()?;
+            return Ok(());
+        }
+
+        if let Backend::Asm = backend {
+            asm::write(&program, &mut open(output, "output.s")?).into_diagnostic()?;
+            return Ok(());
+        }
+
+        let mut vm = vm::Vm::new(&program, std::io::stdout().lock()).args(args.collect());
+        if let Err(e) = vm.run() {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
+    let mut state = State::default();
+    state.generate(file)?;
+
+    for warning in state.take_warnings() {
+        eprintln!("{:?}", miette::Report::new(warning));
+    }
+
+    state.write(open(output, "output.c")?).into_diagnostic()?;
+
     Ok(())
 }
 
 fn main() {
     if let Err(e) = app() {
         eprintln!("{e:?}");
+        std::process::exit(1);
     }
 }
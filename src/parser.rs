@@ -1,4 +1,4 @@
-use crate::ast::{Element, Location};
+use crate::ast::{Element, FileId, Location, SourceMap};
 
 use miette::{NamedSource, SourceSpan};
 
@@ -8,7 +8,7 @@ pub use crate::rinha::*;
 ///
 /// It's useful to know the location of the name in the source code
 /// and the name itself to be resolved later.
-#[derive(Debug, Clone, )]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Var {
     pub text: String,
     pub location: Location,
@@ -76,17 +76,50 @@ pub enum InnerError {
         err_span: SourceSpan,
     },
 
-    #[error("record index isn't supported yet")]
+    #[error("field is defined more than once")]
     #[diagnostic(
-        code(zu::unsupported_record_index),
+        code(zu::duplicate_field),
         url(docsrs),
-        help("maybe open a PR")
+        help("maybe remove one of the definitions")
     )]
-    UnsupportedRecordIndex {
-        /// The expression that is a record index. It's the span of the
-        /// expression that will be pointed in the error message.
-        ///
-        /// It's not supported yet, but it will be implemented in the future.
+    DuplicateField {
+        /// The name of the repeated field. It's the span of the
+        /// field that will be pointed in the error message.
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("integer literal is too large")]
+    #[diagnostic(
+        code(zu::integer_too_large),
+        url(docsrs),
+        help("the integers are 32 bits, up to 2147483647")
+    )]
+    IntegerTooLarge {
+        /// The literal that doesn't fit in an integer.
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("`{name}` takes {}, not {found}", arguments(*.expected))]
+    #[diagnostic(code(zu::builtin_arguments), url(docsrs))]
+    BuiltinArguments {
+        /// The name of the builtin.
+        name: String,
+        expected: usize,
+        found: usize,
+
+        /// The call of the builtin.
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    /// A keyword where a name was expected, like `let match = 1`.
+    #[error("`{word}` is a reserved word")]
+    #[diagnostic(code(zu::reserved_word), url(docsrs), help("maybe use another name"))]
+    ReservedWord {
+        word: String,
+
         #[label = "here"]
         err_span: SourceSpan,
     },
@@ -146,6 +179,23 @@ pub enum InnerError {
     },
 }
 
+/// A number of arguments, like `1 argument`.
+fn arguments(count: usize) -> String {
+    match count {
+        1 => "1 argument".to_string(),
+        _ => format!("{count} arguments"),
+    }
+}
+
+/// Strips Byte-Order-Mark (BOM) generated by some programs
+pub fn strip_bom(s: &str) -> &str {
+    if s.as_bytes().get(0..3) == Some(&[0xEF, 0xBB, 0xBF]) {
+        &s[3..]
+    } else {
+        s
+    }
+}
+
 /// Format an expected token message, it's useful for helpful error messages.
 ///
 /// It's useful to know what the parser expected to parse.
@@ -165,6 +215,74 @@ fn fmt_expected(expected: &[String]) -> String {
     f
 }
 
+impl InnerError {
+    /// The span pointed by the error.
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            InnerError::FunctionBodyMissing { err_span }
+            | InnerError::ExpectedStatement { err_span }
+            | InnerError::DuplicateField { err_span }
+            | InnerError::IntegerTooLarge { err_span }
+            | InnerError::BuiltinArguments { err_span, .. }
+            | InnerError::ReservedWord { err_span, .. }
+            | InnerError::InvalidToken { err_span }
+            | InnerError::UnrecoginzedToken { err_span, .. }
+            | InnerError::ExpectedToken { err_span, .. }
+            | InnerError::ExtraToken { err_span, .. } => *err_span,
+        }
+    }
+}
+
+/// The words of the syntax, they can't be names. The builtins can, see
+/// [`crate::builtins`].
+const KEYWORDS: &[&str] = &[
+    "let", "fn", "if", "else", "match", "import", "as", "external", "print", "true", "false", "Int", "Str", "Bool",
+];
+
+impl<T: ToString> From<lalrpop_util::ParseError<usize, T, InnerError>> for InnerError {
+    fn from(error: lalrpop_util::ParseError<usize, T, InnerError>) -> Self {
+        use lalrpop_util::ParseError::*;
+
+        match error {
+            InvalidToken { location } => InnerError::InvalidToken {
+                err_span: SourceSpan::from(location..location),
+            },
+            UnrecognizedEof { location, expected } => InnerError::ExpectedToken {
+                err_span: SourceSpan::from(location..location),
+                help: fmt_expected(&expected),
+            },
+            // A keyword where only a name could go. Where a keyword could
+            // go too, it's a term that's misplaced, not a name.
+            UnrecognizedToken { token, expected }
+                if KEYWORDS.contains(&token.1.to_string().as_str())
+                    && expected.iter().any(|expected| expected.contains("[a-zA-Z]"))
+                    && !expected.iter().any(|expected| KEYWORDS.contains(&expected.trim_matches('"'))) =>
+            {
+                InnerError::ReservedWord {
+                    word: token.1.to_string(),
+                    err_span: SourceSpan::from(token.0..token.2),
+                }
+            }
+            UnrecognizedToken { token, expected } => InnerError::UnrecoginzedToken {
+                err_span: SourceSpan::from(token.0..token.2),
+                help: fmt_expected(&expected),
+            },
+            ExtraToken { ref token } => InnerError::ExtraToken {
+                err_span: SourceSpan::from(token.0..token.2),
+                token: token.1.to_string(),
+            },
+            User { error } => error,
+        }
+    }
+}
+
+/// The parser, it's made once: making one compiles the regular
+/// expressions of the lexer, and that's slower than parsing most files.
+fn parser() -> &'static crate::rinha::FileParser {
+    static PARSER: std::sync::OnceLock<crate::rinha::FileParser> = std::sync::OnceLock::new();
+    PARSER.get_or_init(crate::rinha::FileParser::new)
+}
+
 /// Parses or report the error. It takes a filename to report errors and locations
 /// pointing to the file in the error message.
 ///
@@ -172,54 +290,51 @@ fn fmt_expected(expected: &[String]) -> String {
 /// lot of sub-errors.
 pub fn parse_or_report(filename: &str, text: &str) -> Result<crate::ast::File, ParseError> {
     let mut errors = vec![];
-    let ast = match crate::rinha::FileParser::new().parse(&mut errors, filename, text) {
-        Ok(ast) => ast,
-        Err(error) => {
-            // Build up the list with at least one recovery error.
-            errors.push(lalrpop_util::ErrorRecovery {
-                dropped_tokens: vec![],
-                error,
-            });
-
-            return Err(ParseError {
-                related: vec![],
-                source_code: NamedSource::new(filename, text.to_string()),
-            });
-        }
-    };
+    let file = FileId::new(filename, text);
+    let mut result = parser().parse(&mut errors, &file, text);
 
-    // If there's no error, so return normally the AST as nothing
-    // had happened.
-    if errors.is_empty() {
-        return Ok(ast);
+    let mut related = errors
+        .into_iter()
+        .map(|recovery| InnerError::from(recovery.error))
+        .collect::<Vec<_>>();
+
+    if let Ok(ast) = &mut result {
+        related.extend(crate::builtins::resolve(&mut ast.expression));
+    }
+
+    match result {
+        // If there's no error, so return normally the AST as nothing
+        // had happened.
+        Ok(ast) if related.is_empty() => return Ok(ast),
+        Ok(_) => {}
+
+        // The fatal error is reported along the recovered ones.
+        Err(error) => related.push(InnerError::from(error)),
     }
 
+    related.sort_by_key(|error| (error.span().offset(), error.span().len()));
+    related.dedup_by(|a, b| a.span() == b.span() && a.to_string() == b.to_string());
+
     Err(ParseError {
-        related: errors
-            .into_iter()
-            .map(|recovery| {
-                use lalrpop_util::ParseError::*;
-
-                match recovery.error {
-                    InvalidToken { location } => InnerError::InvalidToken {
-                        err_span: SourceSpan::from(location..location),
-                    },
-                    UnrecognizedEof { location, expected } => InnerError::ExpectedToken {
-                        err_span: SourceSpan::from(location..location),
-                        help: fmt_expected(&expected),
-                    },
-                    UnrecognizedToken { token, expected } => InnerError::UnrecoginzedToken {
-                        err_span: SourceSpan::from(token.0..token.2),
-                        help: fmt_expected(&expected),
-                    },
-                    ExtraToken { ref token } => InnerError::ExtraToken {
-                        err_span: SourceSpan::from(token.0..token.2),
-                        token: token.1.to_string(),
-                    },
-                    User { error } => error,
-                }
-            })
-            .collect(),
+        related,
         source_code: NamedSource::new(filename, text.to_string()),
     })
 }
+
+/// Reads a file from the JSON representation of the AST, the one
+/// written by `--emit ast`. Its locations point to the files of the
+/// source map with their names.
+pub fn parse_json(text: &str, sources: &SourceMap) -> Result<crate::ast::File, serde_json::Error> {
+    sources.reading(|| serde_json::from_str(text))
+}
+
+/// The name of the file of a JSON AST, the source it was parsed from,
+/// without reading the rest of the AST.
+pub fn json_name(text: &str) -> Result<String, serde_json::Error> {
+    #[derive(serde::Deserialize)]
+    struct Named {
+        name: String,
+    }
+
+    serde_json::from_str::<Named>(text).map(|named| named.name)
+}
@@ -0,0 +1,162 @@
+use miette::{NamedSource, SourceSpan};
+
+use crate::ast::{Element, Match, Pattern};
+
+/// The warnings of a [`Match`], they don't stop the compilation: a value
+/// that no arm matches is an error at run time.
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+pub enum MatchWarning {
+    #[error("the arms don't cover every value")]
+    #[diagnostic(
+        code(zu::non_exhaustive_match),
+        url(docsrs),
+        severity(Warning),
+        help("add a `_ => ...` arm for the other values")
+    )]
+    NonExhaustive {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "here"]
+        err_span: SourceSpan,
+    },
+
+    #[error("this arm is never reached")]
+    #[diagnostic(code(zu::unreachable_arm), url(docsrs), severity(Warning))]
+    Unreachable {
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label = "the arms before it match everything it does"]
+        err_span: SourceSpan,
+    },
+}
+
+/// A pattern without the names, as seen by the checks. The values of
+/// a column are assumed to be of a single type, so `true` and `false`
+/// together cover it, and so does a tuple of wildcards.
+#[derive(Clone, PartialEq)]
+enum Shape {
+    Any,
+    Int(i32),
+    Str(String),
+    Bool(bool),
+    Tuple(Box<Shape>, Box<Shape>),
+}
+
+impl From<&Pattern> for Shape {
+    fn from(pattern: &Pattern) -> Self {
+        match pattern {
+            Pattern::Var(_) | Pattern::Wildcard(_) => Shape::Any,
+            Pattern::Int(i) => Shape::Int(i.value),
+            Pattern::Str(s) => Shape::Str(s.value.clone()),
+            Pattern::Bool(b) => Shape::Bool(b.value),
+            Pattern::Tuple(t) => Shape::Tuple(
+                Shape::from(&*t.first).into(),
+                Shape::from(&*t.second).into(),
+            ),
+        }
+    }
+}
+
+/// The row for the values of `constructor`, the parts of a tuple become
+/// columns. Rows that can't match the constructor are gone.
+fn specialize(row: &[Shape], constructor: &Shape) -> Option<Vec<Shape>> {
+    let (head, rest) = row.split_first()?;
+
+    let mut columns = match (head, constructor) {
+        (Shape::Any, Shape::Tuple(..)) => vec![Shape::Any, Shape::Any],
+        (Shape::Any, _) => vec![],
+        (Shape::Tuple(first, second), Shape::Tuple(..)) => vec![*first.clone(), *second.clone()],
+        (head, constructor) if head == constructor => vec![],
+        _ => return None,
+    };
+
+    columns.extend_from_slice(rest);
+    Some(columns)
+}
+
+/// Whether the constructors are every value of their type.
+fn complete(constructors: &[Shape]) -> bool {
+    let tuples = constructors.iter().all(|c| matches!(c, Shape::Tuple(..)));
+    let bools = constructors.contains(&Shape::Bool(true)) && constructors.contains(&Shape::Bool(false));
+
+    !constructors.is_empty() && (tuples || bools && constructors.len() == 2)
+}
+
+/// Whether `row` matches a value that no row of the matrix does.
+fn useful(matrix: &[Vec<Shape>], row: &[Shape]) -> bool {
+    let Some(head) = row.first() else {
+        return matrix.is_empty();
+    };
+
+    let constructors = match head {
+        Shape::Any => {
+            let mut constructors: Vec<Shape> = vec![];
+            for shape in matrix.iter().filter_map(|row| row.first()) {
+                let shape = match shape {
+                    Shape::Any => continue,
+                    Shape::Tuple(..) => Shape::Tuple(Shape::Any.into(), Shape::Any.into()),
+                    shape => shape.clone(),
+                };
+
+                if !constructors.contains(&shape) {
+                    constructors.push(shape);
+                }
+            }
+
+            if !complete(&constructors) {
+                let rest: Vec<_> = matrix
+                    .iter()
+                    .filter(|row| row.first() == Some(&Shape::Any))
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+
+                return useful(&rest, &row[1..]);
+            }
+
+            constructors
+        }
+
+        constructor => vec![constructor.clone()],
+    };
+
+    constructors.iter().any(|constructor| {
+        let matrix: Vec<_> = matrix
+            .iter()
+            .filter_map(|row| specialize(row, constructor))
+            .collect();
+
+        specialize(row, constructor).is_some_and(|row| useful(&matrix, &row))
+    })
+}
+
+/// Checks that every arm of the match can be reached, and that some arm
+/// matches every value.
+pub fn check(m: &Match) -> Vec<MatchWarning> {
+    let mut warnings = vec![];
+    let mut matrix = vec![];
+
+    for arm in &m.arms {
+        let row = vec![Shape::from(&arm.pattern)];
+        let location = arm.pattern.location();
+
+        if !useful(&matrix, &row) {
+            warnings.push(MatchWarning::Unreachable {
+                source_code: location.file.named_source(),
+                err_span: location.clone().into(),
+            });
+        }
+
+        matrix.push(row);
+    }
+
+    if useful(&matrix, &[Shape::Any]) {
+        warnings.push(MatchWarning::NonExhaustive {
+            source_code: m.location.file.named_source(),
+            err_span: m.location.clone().into(),
+        });
+    }
+
+    warnings
+}
@@ -0,0 +1,543 @@
+//! The pretty-printer, it writes a [`File`] back as Rinha text, for
+//! `touka fmt`.
+//!
+//! The comments aren't in the AST, they're read from the source again
+//! and written before the statement they come before. The ones at the end
+//! of a line stay there, and the ones in the middle of an expression are
+//! written where they were in it.
+
+use std::ops::Range;
+
+use crate::ast::{BinaryOp, Element, File, Pattern, Term, Type};
+
+/// How many spaces a block is indented.
+const INDENT: usize = 2;
+
+/// How tight a term binds, as in the grammar: each one can be written
+/// where the ones before it can, without parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    /// A sequence of bindings and the term after them.
+    Term,
+    Expr,
+    Logical,
+    Arithmetic,
+    Factor,
+    Apply,
+}
+
+/// The comments of a source, `//` and `/* */`, and not the ones in
+/// strings.
+pub fn comments(source: &str) -> Vec<Range<usize>> {
+    let bytes = source.as_bytes();
+    let mut comments = vec![];
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match &bytes[idx..] {
+            [b'"', ..] => {
+                idx += 1;
+                while idx < bytes.len() && bytes[idx] != b'"' {
+                    idx += if bytes[idx] == b'\\' { 2 } else { 1 };
+                }
+                idx += 1;
+            }
+
+            [b'/', b'/', ..] => {
+                let end = source[idx..].find(['\n', '\r']).map_or(bytes.len(), |len| idx + len);
+                comments.push(idx..end);
+                idx = end;
+            }
+
+            [b'/', b'*', ..] => {
+                let end = source[idx + 2..].find("*/").map_or(bytes.len(), |len| idx + len + 4);
+                comments.push(idx..end);
+                idx = end;
+            }
+
+            _ => idx += 1,
+        }
+    }
+
+    comments
+}
+
+/// Formats a file, with the comments of its source. A file without one,
+/// like a generated or a JSON one, takes an empty source.
+pub fn format(file: &File, source: &str) -> String {
+    let mut printer = Printer::new(source);
+    printer.statements(&file.expression, usize::MAX);
+    printer.flush(usize::MAX);
+
+    let mut output = printer.output.trim_start().to_string();
+    output.push('\n');
+    output
+}
+
+/// Writes the terms, and the comments before them.
+struct Printer<'a> {
+    source: &'a str,
+    comments: Vec<Range<usize>>,
+
+    /* the first comment that isn't written yet */
+    next: usize,
+    output: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            comments: comments(source),
+            next: 0,
+            output: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    /// Starts a line for what is at `start` in the source, after a blank
+    /// one when there was one there.
+    fn line(&mut self, start: usize) {
+        let before = self.source.get(..start).unwrap_or_default();
+        let gap = &before[before.trim_end().len()..];
+
+        let opened = self.output.ends_with('{') || self.output.is_empty();
+        if gap.matches('\n').count() >= 2 && !opened {
+            self.output.push('\n');
+        }
+
+        self.output.push('\n');
+        self.output.push_str(&" ".repeat(self.indent * INDENT));
+    }
+
+    /// Writes the comments that start before `end`, each in its line.
+    fn flush(&mut self, end: usize) {
+        while let Some(comment) = self.comments.get(self.next).filter(|comment| comment.start < end).cloned() {
+            self.next += 1;
+            self.line(comment.start);
+            self.write(self.source[comment].trim_end());
+        }
+    }
+
+    /// Writes the comments before `end` that are in the same line of the
+    /// code before them, the line of what was just written.
+    fn trailing(&mut self, end: usize) {
+        while let Some(comment) = self.comments.get(self.next).filter(|comment| comment.start < end).cloned() {
+            let before = self.source[..comment.start].trim_end_matches([' ', '\t']);
+            if before.is_empty() || before.ends_with(['\n', '\r']) {
+                break;
+            }
+
+            self.next += 1;
+            self.write(" ");
+            self.write(self.source[comment].trim_end());
+        }
+    }
+
+    /// Writes the comments that start before `end` where the printer is,
+    /// in the middle of a term. A line comment ends the line.
+    fn inline(&mut self, end: usize) {
+        while let Some(comment) = self.comments.get(self.next).filter(|comment| comment.start < end).cloned() {
+            self.next += 1;
+            if !self.output.ends_with([' ', '\n', '(', '[']) {
+                self.write(" ");
+            }
+
+            let text = self.source[comment].trim_end();
+            self.write(text);
+            // What's after a line comment goes on, a level deeper.
+            match text.starts_with("//") {
+                true => {
+                    self.indent += 1;
+                    self.line(0);
+                    self.indent -= 1;
+                }
+                false => self.write(" "),
+            }
+        }
+    }
+
+    /// Writes the delimiter that closes a term ending at `end`, after the
+    /// comments before it.
+    fn close(&mut self, end: usize, delimiter: &str) {
+        self.inline(end);
+        if self.output.ends_with("*/ ") {
+            self.output.pop();
+        }
+        self.write(delimiter);
+    }
+
+    /// Whether there are comments in `range` that aren't written yet.
+    fn commented(&self, range: Range<usize>) -> bool {
+        self.comments[self.next..].iter().any(|comment| range.contains(&comment.start))
+    }
+
+    /// Where the next token after `offset` starts, past the comments.
+    fn next_token(&self, mut offset: usize) -> usize {
+        loop {
+            let rest = self.source.get(offset..).unwrap_or_default();
+            offset += rest.len() - rest.trim_start().len();
+
+            match self.comments.iter().find(|comment| comment.start == offset) {
+                Some(comment) => offset = comment.end,
+                None => break offset,
+            }
+        }
+    }
+
+    /// The bindings of a sequence one in each line, and the term after
+    /// them. The sequence ends at `end`.
+    fn statements(&mut self, mut term: &Term, end: usize) {
+        loop {
+            self.flush(term.location().start);
+            self.line(term.location().start);
+
+            let next = match term {
+                Term::Let(binding) if is_sequence(binding) => {
+                    self.expr(&binding.value, Level::Expr);
+                    &binding.next
+                }
+
+                Term::Let(binding) => {
+                    self.write("let ");
+                    self.write(&binding.name.text);
+                    if let Some(ty) = binding.ty {
+                        self.write(": ");
+                        self.write(ty_name(ty));
+                    }
+                    self.write(" = ");
+                    self.expr(&binding.value, Level::Expr);
+                    &binding.next
+                }
+
+                Term::Destructure(destructure) => {
+                    self.write("let ");
+                    self.pattern(&destructure.pattern);
+                    self.write(" = ");
+                    self.expr(&destructure.value, Level::Expr);
+                    &destructure.next
+                }
+
+                Term::Import(import) => {
+                    self.write(&format!("import \"{}\" as {}", import.path, import.name.text));
+                    &import.next
+                }
+
+                Term::External(external) => {
+                    let parameters: Vec<_> = external.parameters.iter().map(|ty| ty_name(*ty)).collect();
+                    self.write(&format!(
+                        "external {}: ({}) => {}",
+                        external.name.text,
+                        parameters.join(", "),
+                        ty_name(external.returns)
+                    ));
+
+                    if external.symbol != external.name.text {
+                        self.write(&format!(" = \"{}\"", external.symbol));
+                    }
+                    &external.next
+                }
+
+                _ => {
+                    self.expr(term, Level::Expr);
+                    self.trailing(end);
+                    break;
+                }
+            };
+
+            self.write(";");
+            self.trailing(next.location().start);
+            term = next;
+        }
+    }
+
+    /// A sequence in braces, in lines of its own.
+    fn block(&mut self, term: &Term) {
+        let end = self.next_token(last(term).location().end);
+
+        self.write("{");
+        self.indent += 1;
+        self.statements(term, end);
+        self.flush(end);
+        self.indent -= 1;
+        self.line(0);
+        self.write("}");
+    }
+
+    /// A term where the grammar takes one of `level`, in parentheses if
+    /// it binds looser.
+    fn expr(&mut self, term: &Term, level: Level) {
+        self.inline(term.location().start);
+
+        match precedence(term) < level {
+            true => {
+                self.write("(");
+                self.term(term);
+                self.write(")");
+            }
+            false => self.term(term),
+        }
+    }
+
+    fn list(&mut self, terms: &[Term]) {
+        for (idx, term) in terms.iter().enumerate() {
+            if idx > 0 {
+                self.write(", ");
+            }
+            self.expr(term, Level::Term);
+        }
+    }
+
+    /// A builtin like `print(value)`, that ends at `end`.
+    fn builtin(&mut self, name: &str, arguments: &[&Term], end: usize) {
+        self.write(name);
+        self.write("(");
+        for (idx, argument) in arguments.iter().enumerate() {
+            if idx > 0 {
+                self.write(", ");
+            }
+            self.expr(argument, Level::Term);
+        }
+        self.close(end, ")");
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        self.inline(pattern.location().start);
+
+        match pattern {
+            Pattern::Var(name) => self.write(&name.text),
+            Pattern::Wildcard(_) => self.write("_"),
+            Pattern::Int(int) => self.write(&int.value.to_string()),
+            Pattern::Str(str) => self.write(&format!("\"{}\"", str.value)),
+            Pattern::Bool(bool) => self.write(&bool.value.to_string()),
+            Pattern::Tuple(tuple) => {
+                self.write("(");
+                self.pattern(&tuple.first);
+                self.write(", ");
+                self.pattern(&tuple.second);
+                self.close(tuple.location.end, ")");
+            }
+        }
+    }
+
+    /// A term as it's written on its own.
+    fn term(&mut self, term: &Term) {
+        let end = term.location().end;
+
+        match term {
+            Term::Error(error) => self.write(&error.full_text),
+
+            Term::Int(int) => match int.value {
+                // There are no negative literals.
+                i32::MIN => self.write("((0 - 2147483647) - 1)"),
+                value if value < 0 => self.write(&format!("(0 - {})", -value)),
+                value => self.write(&value.to_string()),
+            },
+
+            Term::Str(str) => self.write(&format!("\"{}\"", str.value)),
+            Term::Bool(bool) => self.write(&bool.value.to_string()),
+            Term::Var(var) => self.write(&var.text),
+
+            Term::Let(_) | Term::Destructure(_) | Term::Import(_) | Term::External(_) => self.block(term),
+
+            Term::Call(call) => {
+                self.expr(&call.callee, Level::Apply);
+                self.write("(");
+                self.list(&call.arguments);
+                self.close(call.location.end, ")");
+            }
+
+            Term::Binary(binary) => {
+                let level = precedence(term);
+                let op = match binary.op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "/",
+                    BinaryOp::Rem => "%",
+                    BinaryOp::Eq => "==",
+                    BinaryOp::Neq => "!=",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Gt => ">",
+                    BinaryOp::Lte => "<=",
+                    BinaryOp::Gte => ">=",
+                    BinaryOp::And => "&&",
+                    BinaryOp::Or => "||",
+                };
+
+                // The operators are right associative.
+                let tighter = match level {
+                    Level::Logical => Level::Arithmetic,
+                    Level::Arithmetic => Level::Factor,
+                    _ => Level::Apply,
+                };
+
+                self.expr(&binary.lhs, tighter);
+                self.write(&format!(" {op} "));
+                self.expr(&binary.rhs, level);
+            }
+
+            Term::Function(function) => {
+                self.write("fn (");
+                for (idx, parameter) in function.parameters.iter().enumerate() {
+                    if idx > 0 {
+                        self.write(", ");
+                    }
+                    self.inline(parameter.location.start);
+                    self.write(&parameter.text);
+                    if let Some(Some(ty)) = function.annotations.get(idx) {
+                        self.write(": ");
+                        self.write(ty_name(*ty));
+                    }
+                }
+                self.write(") => ");
+
+                // The comments after the body are in its braces.
+                let body = &*function.value;
+                match body {
+                    Term::If(_) => self.block(body),
+                    _ if self.commented(body.location().end..function.location.end) => self.block(body),
+                    _ => self.expr(body, Level::Expr),
+                }
+            }
+
+            Term::If(condition) => {
+                self.write("if (");
+                self.expr(&condition.condition, Level::Term);
+                self.write(") ");
+                self.block(&condition.then);
+                self.write(" else ");
+                self.block(&condition.otherwise);
+            }
+
+            Term::Match(matching) => {
+                self.write("match ");
+                match &*matching.value {
+                    value @ Term::Tuple(_) => self.term(value),
+                    value => self.expr(value, Level::Logical),
+                }
+                self.write(" {");
+
+                self.indent += 1;
+                for (idx, arm) in matching.arms.iter().enumerate() {
+                    self.flush(arm.pattern.location().start);
+                    self.line(arm.pattern.location().start);
+                    self.pattern(&arm.pattern);
+                    self.write(" => ");
+                    self.expr(&arm.value, Level::Term);
+
+                    // The comment at the end of an arm stays with it.
+                    match matching.arms.get(idx + 1) {
+                        Some(next) => {
+                            self.write(",");
+                            self.trailing(next.pattern.location().start);
+                        }
+                        None => self.trailing(matching.location.end),
+                    }
+                }
+                self.flush(matching.location.end);
+                self.indent -= 1;
+
+                self.line(0);
+                self.write("}");
+            }
+
+            Term::Tuple(tuple) => {
+                self.write("(");
+                self.expr(&tuple.first, Level::Term);
+                self.write(", ");
+                self.expr(&tuple.second, Level::Term);
+                self.close(tuple.location.end, ")");
+            }
+
+            Term::List(list) => {
+                self.write("[");
+                self.list(&list.elements);
+                self.close(list.location.end, "]");
+            }
+
+            Term::Record(record) if record.fields.is_empty() => self.write("{}"),
+            Term::Record(record) => {
+                self.write("{ ");
+                for (idx, field) in record.fields.iter().enumerate() {
+                    if idx > 0 {
+                        self.write(", ");
+                    }
+                    self.inline(field.name.location.start);
+                    self.write(&field.name.text);
+                    self.write(": ");
+                    self.expr(&field.value, Level::Term);
+                }
+                self.close(record.location.end, " }");
+            }
+
+            Term::FieldAccess(access) => {
+                self.expr(&access.value, Level::Apply);
+                self.write(".");
+                self.write(&access.name.text);
+            }
+
+            Term::Print(print) => self.builtin("print", &[&print.value], end),
+            Term::First(first) => self.builtin("first", &[&first.value], end),
+            Term::Second(second) => self.builtin("second", &[&second.value], end),
+            Term::Head(head) => self.builtin("head", &[&head.value], end),
+            Term::Tail(tail) => self.builtin("tail", &[&tail.value], end),
+            Term::Len(len) => self.builtin("len", &[&len.value], end),
+            Term::Cons(cons) => self.builtin("cons", &[&cons.head, &cons.tail], end),
+            Term::Substr(substr) => self.builtin("substr", &[&substr.value, &substr.start, &substr.length], end),
+            Term::ToInt(to_int) => self.builtin("to_int", &[&to_int.value], end),
+            Term::ToStr(to_str) => self.builtin("to_str", &[&to_str.value], end),
+            Term::Contains(contains) => self.builtin("contains", &[&contains.value, &contains.pattern], end),
+            Term::ReadLine(_) => self.builtin("read_line", &[], end),
+            Term::Argv(_) => self.builtin("argv", &[], end),
+        }
+    }
+}
+
+/// Whether a `let _` was written as `value; next`, its name is the `;`.
+fn is_sequence(binding: &crate::ast::Let) -> bool {
+    binding.name.text == "_" && binding.name.location.start >= binding.value.location().end
+}
+
+/// The term at the end of a sequence.
+fn last(mut term: &Term) -> &Term {
+    loop {
+        term = match term {
+            Term::Let(binding) => &binding.next,
+            Term::Destructure(destructure) => &destructure.next,
+            Term::Import(import) => &import.next,
+            Term::External(external) => &external.next,
+            _ => return term,
+        }
+    }
+}
+
+/// How tight a term binds as it's written by [`Printer::term`], the
+/// sequences are in a block.
+fn precedence(term: &Term) -> Level {
+    match term {
+        Term::Let(_) | Term::Destructure(_) | Term::Import(_) | Term::External(_) => Level::Expr,
+        Term::Tuple(_) | Term::Match(_) | Term::If(_) | Term::Function(_) => Level::Expr,
+
+        Term::Binary(binary) => match binary.op {
+            BinaryOp::Add | BinaryOp::Sub => Level::Arithmetic,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => Level::Factor,
+            _ => Level::Logical,
+        },
+
+        _ => Level::Apply,
+    }
+}
+
+fn ty_name(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "Int",
+        Type::Str => "Str",
+        Type::Bool => "Bool",
+    }
+}
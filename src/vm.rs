@@ -0,0 +1,666 @@
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::ast::BinaryOp;
+use crate::bytecode::{Op, Program};
+
+/// The kind of a value, the same set as the `Kind` of the C runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Str,
+    Int,
+    Bool,
+    Tuple,
+    Record,
+    List,
+    Function,
+}
+
+impl Kind {
+    /// The name of the kind in the error messages, like `KindName`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Str => "string",
+            Kind::Int => "int",
+            Kind::Bool => "bool",
+            Kind::Tuple => "tuple",
+            Kind::Record => "record",
+            Kind::List => "list",
+            Kind::Function => "function",
+        }
+    }
+}
+
+/// A record value, the names are shared by every record built by the
+/// same literal.
+#[derive(Debug)]
+pub struct Record {
+    pub names: Rc<[Rc<str>]>,
+    pub values: Vec<Value>,
+}
+
+/// A cons cell, it knows the length of the list it starts.
+#[derive(Debug)]
+pub struct Cell {
+    pub head: Value,
+    pub tail: Option<Rc<Cell>>,
+    pub len: usize,
+}
+
+/// The cells are dropped in a loop, dropping each one from the one before
+/// overflows the stack on a long list.
+impl Drop for Cell {
+    fn drop(&mut self) {
+        let mut tail = self.tail.take();
+        while let Some(cell) = tail {
+            tail = match Rc::try_unwrap(cell) {
+                Ok(mut cell) => cell.tail.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// A function value: the code and the captured values.
+#[derive(Debug)]
+pub struct Closure {
+    pub code: usize,
+    pub env: Vec<Value>,
+}
+
+/// A tagged value of the machine.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i32),
+    /// The bytes of the string, a `substr` can cut a character in two.
+    Str(Rc<[u8]>),
+    Bool(bool),
+    Tuple(Rc<(Value, Value)>),
+    Record(Rc<Record>),
+    List(Option<Rc<Cell>>),
+    Function(Rc<Closure>),
+}
+
+impl Value {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Value::Int(_) => Kind::Int,
+            Value::Str(_) => Kind::Str,
+            Value::Bool(_) => Kind::Bool,
+            Value::Tuple(_) => Kind::Tuple,
+            Value::Record(_) => Kind::Record,
+            Value::List(_) => Kind::List,
+            Value::Function(_) => Kind::Function,
+        }
+    }
+}
+
+impl Value {
+    /// Writes the value the way `pi` prints it, the strings as the bytes
+    /// they are.
+    pub fn write(&self, f: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Str(s) => f.write_all(s),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Tuple(t) => {
+                write!(f, "(")?;
+                t.0.write(f)?;
+                write!(f, ", ")?;
+                t.1.write(f)?;
+                write!(f, ")")
+            }
+            Value::Record(r) => {
+                write!(f, "{{")?;
+                for (idx, (name, value)) in r.names.iter().zip(&r.values).enumerate() {
+                    let separator = if idx > 0 { ", " } else { "" };
+                    write!(f, "{separator}{name}: ")?;
+                    value.write(f)?;
+                }
+                write!(f, "}}")
+            }
+            Value::List(list) => {
+                write!(f, "[")?;
+                let mut cell = list.as_deref();
+                while let Some(c) = cell {
+                    c.head.write(f)?;
+                    if c.tail.is_some() {
+                        write!(f, ", ")?;
+                    }
+                    cell = c.tail.as_deref();
+                }
+                write!(f, "]")
+            }
+            Value::Function(_) => write!(f, "<#closure>"),
+        }
+    }
+}
+
+/// Values are displayed the way `pi` prints them, with the bytes that
+/// aren't UTF-8 replaced.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = vec![];
+        self.write(&mut bytes).map_err(|_| fmt::Error)?;
+        write!(f, "{}", String::from_utf8_lossy(&bytes))
+    }
+}
+
+/// Runtime errors, the names are the ones of the C runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    InvalidOperands,
+    DivisionByZero,
+    NotATuple,
+    NotAFunction,
+    ArityMismatch,
+    NotABoolean,
+    NotARecord,
+    NoSuchField,
+    InvalidArgument,
+    TypeMismatch,
+    NotAList,
+    EmptyList,
+    NoMatch,
+    OutOfBounds,
+    EndOfInput,
+    StackOverflow,
+    WriteError,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Fault::InvalidOperands => "invalid-operands",
+            Fault::DivisionByZero => "division-by-zero",
+            Fault::NotATuple => "not-a-tuple",
+            Fault::NotAFunction => "not-a-function",
+            Fault::ArityMismatch => "arity-mismatch",
+            Fault::NotABoolean => "not-a-boolean",
+            Fault::NotARecord => "not-a-record",
+            Fault::NoSuchField => "no-such-field",
+            Fault::InvalidArgument => "invalid-argument",
+            Fault::TypeMismatch => "type-mismatch",
+            Fault::NotAList => "not-a-list",
+            Fault::EmptyList => "empty-list",
+            Fault::NoMatch => "no-match",
+            Fault::OutOfBounds => "out-of-bounds",
+            Fault::EndOfInput => "end-of-input",
+            Fault::StackOverflow => "stack-overflow",
+            Fault::WriteError => "write-error",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+/// An error of a running program, it reads like the ones of the C
+/// runtime so both backends can be compared.
+#[derive(Debug, thiserror::Error)]
+#[error("ToukaRT: error[{fault}] at {at}: {message}")]
+pub struct RuntimeError {
+    pub fault: Fault,
+    pub at: String,
+    pub message: String,
+}
+
+/// How many calls can be in progress, about as deep as the stack of the
+/// C runtime goes.
+const MAX_FRAMES: usize = 1 << 20;
+
+/// A call in progress.
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /* where its locals start */
+    base: usize,
+}
+
+/// The stack machine, it runs a [`Program`] writing what it prints to
+/// `out`.
+pub struct Vm<'p, W: Write> {
+    program: &'p Program,
+    out: W,
+    args: Vec<String>,
+    /* the standard input when there's none */
+    input: Option<Box<dyn BufRead + 'p>>,
+    stack: Vec<Value>,
+    locals: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl<'p, W: Write> Vm<'p, W> {
+    pub fn new(program: &'p Program, out: W) -> Self {
+        Self {
+            program,
+            out,
+            args: vec![],
+            input: None,
+            stack: vec![],
+            locals: vec![],
+            frames: vec![],
+        }
+    }
+
+    /// The arguments the program sees with `argv()`.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Where the program reads with `read_line()`, the standard input
+    /// otherwise.
+    pub fn input(mut self, input: impl BufRead + 'p) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Runs the program, the output is flushed even when it fails.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        let result = self.execute();
+        let _ = self.out.flush();
+        result
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        let entry = Rc::new(Closure {
+            code: self.program.entry,
+            env: vec![],
+        });
+
+        self.enter(entry, vec![]);
+
+        loop {
+            let frame = self.frames.last_mut().expect("no frame to run");
+            let code = &self.program.codes[frame.closure.code];
+            let op = &code.ops[frame.ip];
+            frame.ip += 1;
+
+            macro_rules! pop {
+                () => {
+                    self.stack.pop().expect("the operand stack is empty")
+                };
+            }
+
+            macro_rules! fail {
+                ($at:expr, $fault:expr, $($t:tt)*) => {
+                    return Err(RuntimeError {
+                        fault: $fault,
+                        at: self.program.locations[*$at].clone(),
+                        message: format!($($t)*),
+                    })
+                };
+            }
+
+            match op {
+                Op::Const(idx) => self.stack.push(self.program.constants[*idx].clone()),
+
+                Op::Load(slot) => {
+                    let base = frame.base;
+                    self.stack.push(self.locals[base + slot].clone());
+                }
+
+                Op::Store(slot) => {
+                    let base = frame.base;
+                    self.locals[base + slot] = pop!();
+                }
+
+                Op::Env(idx) => {
+                    let value = frame.closure.env[*idx].clone();
+                    self.stack.push(value);
+                }
+
+                Op::Current => {
+                    let value = Value::Function(frame.closure.clone());
+                    self.stack.push(value);
+                }
+
+                Op::Pop => {
+                    pop!();
+                }
+
+                Op::Jump(target) => frame.ip = *target,
+
+                Op::Branch(target, at) => match pop!() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => self.frames.last_mut().unwrap().ip = *target,
+                    value => fail!(at, Fault::NotABoolean, "expected a bool, found {}", value.kind().name()),
+                },
+
+                Op::Truthy(at) => {
+                    let value = self.stack.last().expect("the operand stack is empty");
+                    if value.kind() != Kind::Bool {
+                        fail!(at, Fault::NotABoolean, "expected a bool, found {}", value.kind().name());
+                    }
+                }
+
+                Op::Expect(kind, at) => {
+                    let value = self.stack.last().expect("the operand stack is empty");
+                    if value.kind() != *kind {
+                        fail!(
+                            at,
+                            Fault::TypeMismatch,
+                            "expected {}, found {}",
+                            kind.name(),
+                            value.kind().name()
+                        );
+                    }
+                }
+
+                Op::Binary(op, at) => {
+                    let rhs = pop!();
+                    let lhs = pop!();
+                    let result = binary(*op, lhs, rhs).map_err(|(fault, message)| RuntimeError {
+                        fault,
+                        at: self.program.locations[*at].clone(),
+                        message,
+                    })?;
+                    self.stack.push(result);
+                }
+
+                Op::Closure(code) => {
+                    let captures = self.program.codes[*code].captures;
+                    let env = self.stack.split_off(self.stack.len() - captures);
+                    self.stack
+                        .push(Value::Function(Rc::new(Closure { code: *code, env })));
+                }
+
+                Op::Call(arity, at) => {
+                    if self.frames.len() >= MAX_FRAMES {
+                        fail!(at, Fault::StackOverflow, "the recursion is too deep");
+                    }
+
+                    let arguments = self.stack.split_off(self.stack.len() - arity);
+                    let closure = match pop!() {
+                        Value::Function(closure) => closure,
+                        value => fail!(at, Fault::NotAFunction, "expected a function, found {}", value.kind().name()),
+                    };
+
+                    let expected = self.program.codes[closure.code].arity;
+                    if expected != *arity {
+                        fail!(at, Fault::ArityMismatch, "expected {expected} arguments, found {arity}");
+                    }
+
+                    self.enter(closure, arguments);
+                }
+
+                Op::Return => {
+                    let frame = self.frames.pop().expect("no frame to return from");
+                    self.locals.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(pop!());
+                    }
+                }
+
+                Op::Tuple => {
+                    let second = pop!();
+                    let first = pop!();
+                    self.stack.push(Value::Tuple(Rc::new((first, second))));
+                }
+
+                Op::First(at) | Op::Second(at) => match pop!() {
+                    Value::Tuple(t) => {
+                        let value = match op {
+                            Op::First(_) => t.0.clone(),
+                            _ => t.1.clone(),
+                        };
+                        self.stack.push(value);
+                    }
+                    value => fail!(at, Fault::NotATuple, "expected a tuple, found {}", value.kind().name()),
+                },
+
+                Op::Untuple(at) => match pop!() {
+                    Value::Tuple(t) => {
+                        self.stack.push(t.0.clone());
+                        self.stack.push(t.1.clone());
+                    }
+                    value => fail!(at, Fault::NotATuple, "expected a tuple, found {}", value.kind().name()),
+                },
+
+                Op::Record(names) => {
+                    let values = self.stack.split_off(self.stack.len() - names.len());
+                    self.stack.push(Value::Record(Rc::new(Record {
+                        names: names.clone(),
+                        values,
+                    })));
+                }
+
+                Op::Field(name, at) => match pop!() {
+                    Value::Record(r) => match r.names.iter().position(|other| other == name) {
+                        Some(idx) => self.stack.push(r.values[idx].clone()),
+                        None => fail!(at, Fault::NoSuchField, "the record has no field `{name}`"),
+                    },
+                    value => fail!(at, Fault::NotARecord, "expected a record, found {}", value.kind().name()),
+                },
+
+                Op::List(size) => {
+                    let elements = self.stack.split_off(self.stack.len() - size);
+                    let list = elements.into_iter().rev().fold(None, |tail, head| {
+                        let len = tail.as_ref().map_or(0, |cell: &Rc<Cell>| cell.len) + 1;
+                        Some(Rc::new(Cell { head, tail, len }))
+                    });
+                    self.stack.push(Value::List(list));
+                }
+
+                Op::Cons(at) => {
+                    let tail = match pop!() {
+                        Value::List(tail) => tail,
+                        value => fail!(at, Fault::NotAList, "expected a list, found {}", value.kind().name()),
+                    };
+                    let head = pop!();
+                    let len = tail.as_ref().map_or(0, |cell| cell.len) + 1;
+                    self.stack
+                        .push(Value::List(Some(Rc::new(Cell { head, tail, len }))));
+                }
+
+                Op::Head(at) | Op::Tail(at) => match pop!() {
+                    Value::List(Some(cell)) => {
+                        let value = match op {
+                            Op::Head(_) => cell.head.clone(),
+                            _ => Value::List(cell.tail.clone()),
+                        };
+                        self.stack.push(value);
+                    }
+                    Value::List(None) => fail!(at, Fault::EmptyList, "the list is empty"),
+                    value => fail!(at, Fault::NotAList, "expected a list, found {}", value.kind().name()),
+                },
+
+                Op::Len(at) => {
+                    let len = match pop!() {
+                        Value::Str(s) => s.len(),
+                        Value::List(list) => list.map_or(0, |cell| cell.len),
+                        value => fail!(
+                            at,
+                            Fault::InvalidArgument,
+                            "expected a list or a string, found {}",
+                            value.kind().name()
+                        ),
+                    };
+                    self.stack.push(Value::Int(len as i32));
+                }
+
+                Op::Substr(at) => {
+                    let (length, start, value) = (pop!(), pop!(), pop!());
+                    let (Value::Str(s), Value::Int(start), Value::Int(length)) = (value, start, length) else {
+                        unreachable!("the arguments of substr are checked")
+                    };
+
+                    let size = s.len() as i32;
+                    if start < 0 || length < 0 || start > size || length > size - start {
+                        fail!(
+                            at,
+                            Fault::OutOfBounds,
+                            "cannot take {length} characters from {start} of a string of length {size}"
+                        );
+                    }
+
+                    self.stack
+                        .push(Value::Str(s[start as usize..(start + length) as usize].into()));
+                }
+
+                Op::ToInt(at) => {
+                    let Value::Str(s) = pop!() else {
+                        unreachable!("the argument of to_int is checked")
+                    };
+
+                    // The same numbers `strtol` reads.
+                    let text = String::from_utf8_lossy(&s);
+                    let digits = text.trim_start_matches([' ', '\t', '\n', '\x0b', '\x0c', '\r']);
+                    match digits.parse::<i64>().ok().and_then(|n| i32::try_from(n).ok()) {
+                        Some(n) => self.stack.push(Value::Int(n)),
+                        None => fail!(at, Fault::InvalidArgument, "`{text}` is not a number"),
+                    }
+                }
+
+                Op::ToStr(at) => {
+                    let value = match pop!() {
+                        value @ Value::Str(_) => value,
+                        value @ (Value::Int(_) | Value::Bool(_)) => Value::Str(value.to_string().as_bytes().into()),
+                        value => fail!(at, Fault::InvalidArgument, "cannot convert {} to a string", value.kind().name()),
+                    };
+                    self.stack.push(value);
+                }
+
+                Op::Contains => {
+                    let (pattern, value) = (pop!(), pop!());
+                    let (Value::Str(s), Value::Str(pattern)) = (value, pattern) else {
+                        unreachable!("the arguments of contains are checked")
+                    };
+                    let found = pattern.is_empty() || s.windows(pattern.len()).any(|window| *window == *pattern);
+                    self.stack.push(Value::Bool(found));
+                }
+
+                Op::Print(at) => {
+                    let value = self.stack.last().expect("the operand stack is empty");
+                    if value.write(&mut self.out).and_then(|()| writeln!(self.out)).is_err() {
+                        fail!(at, Fault::WriteError, "cannot write to the standard output");
+                    }
+                }
+
+                Op::ReadLine(at) => {
+                    let _ = self.out.flush();
+                    let mut line = vec![];
+                    let read = match &mut self.input {
+                        Some(input) => input.read_until(b'\n', &mut line),
+                        None => std::io::stdin().lock().read_until(b'\n', &mut line),
+                    };
+                    match read {
+                        Ok(0) | Err(_) => fail!(at, Fault::EndOfInput, "there are no more lines to read"),
+                        Ok(_) => {}
+                    }
+
+                    if line.ends_with(b"\n") {
+                        line.pop();
+                    }
+                    if line.ends_with(b"\r") {
+                        line.pop();
+                    }
+                    self.stack.push(Value::Str(line.into()));
+                }
+
+                Op::Argv => {
+                    let elements = self.args.iter().rev().map(|arg| Value::Str(arg.as_bytes().into()));
+                    let list = elements.fold(None, |tail, head| {
+                        let len = tail.as_ref().map_or(0, |cell: &Rc<Cell>| cell.len) + 1;
+                        Some(Rc::new(Cell { head, tail, len }))
+                    });
+                    self.stack.push(Value::List(list));
+                }
+
+                Op::Test(slot, constant, target) => {
+                    let value = &self.locals[frame.base + slot];
+                    let matches = match (value, &self.program.constants[*constant]) {
+                        (Value::Int(a), Value::Int(b)) => a == b,
+                        (Value::Str(a), Value::Str(b)) => a == b,
+                        (Value::Bool(a), Value::Bool(b)) => a == b,
+                        _ => false,
+                    };
+                    if !matches {
+                        frame.ip = *target;
+                    }
+                }
+
+                Op::TestTuple(slot, target) => {
+                    if !matches!(self.locals[frame.base + slot], Value::Tuple(_)) {
+                        frame.ip = *target;
+                    }
+                }
+
+                Op::NoMatch(at) => fail!(at, Fault::NoMatch, "no arm matches the value"),
+            }
+        }
+    }
+
+    /// Starts a call, the arguments are the first locals.
+    fn enter(&mut self, closure: Rc<Closure>, arguments: Vec<Value>) {
+        let code = &self.program.codes[closure.code];
+        let base = self.locals.len();
+
+        self.locals.extend(arguments);
+        self.locals.resize(base + code.locals, Value::Int(0));
+        self.frames.push(Frame { closure, ip: 0, base });
+    }
+}
+
+/// The binary operations, with the errors of `S`, `MathEvaluateA` and
+/// `BinaryEvaluateA`.
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, (Fault, String)> {
+    use std::cmp::Ordering;
+
+    let ordering = |ordering: Ordering| match op {
+        BinaryOp::Eq => ordering.is_eq(),
+        BinaryOp::Neq => ordering.is_ne(),
+        BinaryOp::Lt => ordering.is_lt(),
+        BinaryOp::Gt => ordering.is_gt(),
+        BinaryOp::Lte => ordering.is_le(),
+        _ => ordering.is_ge(),
+    };
+
+    let value = match (op, &lhs, &rhs) {
+        (BinaryOp::Add, Value::Int(a), Value::Int(b)) => Value::Int(a.wrapping_add(*b)),
+        (BinaryOp::Add, Value::Str(a), Value::Str(b)) => Value::Str([&a[..], b].concat().into()),
+        (BinaryOp::Add, Value::Str(a), Value::Int(b)) => Value::Str([&a[..], b.to_string().as_bytes()].concat().into()),
+        (BinaryOp::Add, Value::Int(a), Value::Str(b)) => Value::Str([a.to_string().as_bytes(), b].concat().into()),
+        (BinaryOp::Add, a, b) => {
+            let message = format!("cannot add {} and {}", a.kind().name(), b.kind().name());
+            return Err((Fault::InvalidOperands, message));
+        }
+
+        (BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, Value::Int(a), Value::Int(b)) => {
+            Value::Int(match op {
+                BinaryOp::Sub => a.wrapping_sub(*b),
+                BinaryOp::Mul => a.wrapping_mul(*b),
+                _ if *b == 0 => return Err((Fault::DivisionByZero, "division by zero".into())),
+                BinaryOp::Div => a.wrapping_div(*b),
+                _ => a.wrapping_rem(*b),
+            })
+        }
+
+        (BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem, a, b) => {
+            let message = format!(
+                "cannot do arithmetic between {} and {}",
+                a.kind().name(),
+                b.kind().name()
+            );
+            return Err((Fault::InvalidOperands, message));
+        }
+
+        (_, Value::Int(a), Value::Int(b)) => Value::Bool(ordering(a.cmp(b))),
+        (_, Value::Str(a), Value::Str(b)) => Value::Bool(ordering(a.cmp(b))),
+
+        (BinaryOp::Eq, Value::Bool(a), Value::Bool(b)) => Value::Bool(a == b),
+        (BinaryOp::Neq, Value::Bool(a), Value::Bool(b)) => Value::Bool(a != b),
+        (_, Value::Bool(_), Value::Bool(_)) => {
+            return Err((Fault::InvalidOperands, "cannot compare booleans by order".into()))
+        }
+
+        (_, a, b) => {
+            let message = format!("cannot compare {} and {}", a.kind().name(), b.kind().name());
+            return Err((Fault::InvalidOperands, message));
+        }
+    };
+
+    Ok(value)
+}
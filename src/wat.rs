@@ -0,0 +1,463 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::ast::BinaryOp;
+use crate::bytecode::{Op, Program};
+use crate::vm::{Kind, Value};
+
+/// The texts of the runtime, as the names of their globals.
+const TEXTS: &[(&str, &str)] = &[
+    ("kind_str", "string"),
+    ("kind_int", "int"),
+    ("kind_bool", "bool"),
+    ("kind_tuple", "tuple"),
+    ("kind_record", "record"),
+    ("kind_list", "list"),
+    ("kind_function", "function"),
+    ("kind_unknown", "unknown"),
+    ("fault_invalid_operands", "invalid-operands"),
+    ("fault_division_by_zero", "division-by-zero"),
+    ("fault_not_a_tuple", "not-a-tuple"),
+    ("fault_not_a_function", "not-a-function"),
+    ("fault_arity_mismatch", "arity-mismatch"),
+    ("fault_not_a_boolean", "not-a-boolean"),
+    ("fault_not_a_record", "not-a-record"),
+    ("fault_no_such_field", "no-such-field"),
+    ("fault_invalid_argument", "invalid-argument"),
+    ("fault_type_mismatch", "type-mismatch"),
+    ("fault_not_a_list", "not-a-list"),
+    ("fault_empty_list", "empty-list"),
+    ("fault_no_match", "no-match"),
+    ("fault_out_of_bounds", "out-of-bounds"),
+    ("fault_end_of_input", "end-of-input"),
+    ("fault_stack_overflow", "stack-overflow"),
+    ("text_true", "true"),
+    ("text_false", "false"),
+    ("text_open_paren", "("),
+    ("text_close_paren", ")"),
+    ("text_open_bracket", "["),
+    ("text_close_bracket", "]"),
+    ("text_open_brace", "{"),
+    ("text_close_brace", "}"),
+    ("text_comma", ", "),
+    ("text_colon", ": "),
+    ("text_closure", "<#closure>"),
+    ("text_error", "ToukaRT: error["),
+    ("text_at", "] at "),
+    ("text_and", " and "),
+    ("text_expected", "expected "),
+    ("text_found", ", found "),
+    ("text_arguments", " arguments, found "),
+    ("text_backtick", "`"),
+    ("text_cannot_add", "cannot add "),
+    ("text_cannot_compare", "cannot compare "),
+    ("text_compare_booleans", "cannot compare booleans by order"),
+    ("text_arithmetic", "cannot do arithmetic between "),
+    ("text_division_by_zero", "division by zero"),
+    ("text_no_match", "no arm matches the value"),
+    ("text_no_field", "the record has no field `"),
+    ("text_expected_bool", "expected a bool, found "),
+    ("text_expected_tuple", "expected a tuple, found "),
+    ("text_expected_record", "expected a record, found "),
+    ("text_expected_list", "expected a list, found "),
+    ("text_expected_function", "expected a function, found "),
+    ("text_expected_sized", "expected a list or a string, found "),
+    ("text_empty_list", "the list is empty"),
+    ("text_cannot_convert", "cannot convert "),
+    ("text_to_string", " to a string"),
+    ("text_not_a_number", "` is not a number"),
+    ("text_cannot_take", "cannot take "),
+    ("text_characters_from", " characters from "),
+    ("text_of_length", " of a string of length "),
+    ("text_no_more_lines", "there are no more lines to read"),
+    ("text_too_deep", "the recursion is too deep"),
+];
+
+/// Where the data starts, the bytes before it are the host's and the
+/// runtime's scratch space.
+const DATA: usize = 64;
+
+/// The values being computed, 8 bytes each.
+const STACK_SIZE: usize = 1 << 20;
+
+/// The static data, strings are their length as 4 bytes and then the
+/// bytes.
+#[derive(Default)]
+struct Data {
+    bytes: Vec<u8>,
+    strings: HashMap<String, usize>,
+}
+
+impl Data {
+    fn align(&mut self) {
+        while self.bytes.len() % 4 != 0 {
+            self.bytes.push(0);
+        }
+    }
+
+    fn string(&mut self, text: &str) -> usize {
+        if let Some(&address) = self.strings.get(text) {
+            return address;
+        }
+
+        self.align();
+        let address = DATA + self.bytes.len();
+        self.bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(text.as_bytes());
+        self.strings.insert(text.to_string(), address);
+        address
+    }
+
+    fn words(&mut self, words: &[usize]) -> usize {
+        self.align();
+        let address = DATA + self.bytes.len();
+        for word in words {
+            self.bytes.extend_from_slice(&(*word as u32).to_le_bytes());
+        }
+        address
+    }
+
+    fn end(&self) -> usize {
+        DATA + self.bytes.len()
+    }
+
+    /// The operand of `data`, every byte that isn't plain text escaped.
+    fn quoted(&self) -> String {
+        let mut quoted = String::from("\"");
+        for &byte in &self.bytes {
+            match byte {
+                b'"' | b'\\' => write!(quoted, "\\{}", byte as char),
+                0x20..=0x7e => write!(quoted, "{}", byte as char),
+                _ => write!(quoted, "\\{byte:02x}"),
+            }
+            .unwrap();
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+/// The globals of the names of the fields, by their order.
+#[derive(Default)]
+struct Names {
+    fields: HashMap<Rc<str>, usize>,
+    records: HashMap<Vec<Rc<str>>, usize>,
+}
+
+/// The kind in the runtime.
+fn kind(kind: Kind) -> u8 {
+    match kind {
+        Kind::Str => 0xca,
+        Kind::Int => 0xfe,
+        Kind::Bool => 0xba,
+        Kind::Tuple => 0x10,
+        Kind::Record => 0x11,
+        Kind::List => 0x12,
+        Kind::Function => 0x42,
+    }
+}
+
+/// The instructions that start a basic block: the first one, the targets
+/// of the jumps and the ones after them.
+fn leaders(ops: &[Op]) -> BTreeSet<usize> {
+    let mut leaders = BTreeSet::from([0]);
+    for (ip, op) in ops.iter().enumerate() {
+        match op {
+            Op::Jump(target) | Op::Branch(target, _) | Op::Test(_, _, target) | Op::TestTuple(_, target) => {
+                leaders.insert(*target);
+                leaders.insert(ip + 1);
+            }
+            Op::Return | Op::NoMatch(_) => {
+                leaders.insert(ip + 1);
+            }
+            _ => {}
+        }
+    }
+
+    leaders.retain(|&ip| ip < ops.len());
+    leaders
+}
+
+/// The body of one function. WebAssembly has no jumps, so the blocks are
+/// the cases of a `br_table` in a loop, and a jump sets `$pc` and goes
+/// back to it. A block falls through to the next one.
+struct Function<'p> {
+    program: &'p Program,
+    names: &'p Names,
+    blocks: HashMap<usize, usize>,
+    body: String,
+}
+
+impl Function<'_> {
+    fn line(&mut self, line: &str) {
+        writeln!(self.body, "        {line}").unwrap();
+    }
+
+    fn jump(&self, target: usize) -> String {
+        format!("(local.set $pc (i32.const {})) (br $dispatch)", self.blocks[&target])
+    }
+
+    /// Jumps to the target when the condition holds.
+    fn jump_if(&mut self, condition: &str, target: usize) {
+        let jump = self.jump(target);
+        self.line(&format!("(if {condition} (then {jump}))"));
+    }
+
+    fn op(&mut self, op: &Op) {
+        let program = self.program;
+
+        match op {
+            Op::Const(idx) => match &program.constants[*idx] {
+                Value::Int(i) => self.line(&format!("(call $push (i32.const 0xfe) (i32.const {i}))")),
+                Value::Bool(b) => self.line(&format!("(call $push (i32.const 0xba) (i32.const {}))", *b as u8)),
+                Value::Str(_) => self.line(&format!("(call $push (i32.const 0xca) (global.get $c{idx}))")),
+                value => unreachable!("no constants of kind {}", value.kind().name()),
+            },
+
+            Op::Load(slot) => self.line(&format!("(call $push (local.get $k{slot}) (local.get $p{slot}))")),
+
+            Op::Store(slot) => {
+                self.line("(call $drop (i32.const 1))");
+                self.line(&format!("(local.set $k{slot} (i32.load (global.get $sp)))"));
+                self.line(&format!("(local.set $p{slot} (i32.load offset=4 (global.get $sp)))"));
+            }
+
+            Op::Env(idx) => self.line(&format!(
+                "(call $push (i32.load offset={} (local.get $env)) (i32.load offset={} (local.get $env)))",
+                12 + 8 * idx,
+                16 + 8 * idx
+            )),
+
+            Op::Current => self.line("(call $push (i32.const 0x42) (local.get $env))"),
+            Op::Pop => self.line("(call $drop (i32.const 1))"),
+            Op::Jump(target) => {
+                let jump = self.jump(*target);
+                self.line(&jump)
+            }
+            Op::Branch(target, at) => self.jump_if(&format!("(i32.eqz (call $rt_branch (global.get $at{at})))"), *target),
+            Op::Truthy(at) => self.line(&format!("(call $rt_truthy (global.get $at{at}))")),
+
+            Op::Expect(expected, at) => {
+                self.line(&format!("(call $rt_expect (i32.const {:#x}) (global.get $at{at}))", kind(*expected)))
+            }
+
+            Op::Binary(op, at) => match op {
+                BinaryOp::Add => self.line(&format!("(call $rt_add (global.get $at{at}))")),
+                BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+                    let op = [BinaryOp::Sub, BinaryOp::Mul, BinaryOp::Div, BinaryOp::Rem].iter().position(|o| o == op);
+                    self.line(&format!("(call $rt_arithmetic (i32.const {}) (global.get $at{at}))", op.unwrap()))
+                }
+                _ => {
+                    let order = [BinaryOp::Eq, BinaryOp::Neq, BinaryOp::Lt, BinaryOp::Gt, BinaryOp::Lte, BinaryOp::Gte];
+                    let op = order.iter().position(|o| o == op).expect("the logical operators are jumps");
+                    self.line(&format!("(call $rt_compare (i32.const {op}) (global.get $at{at}))"))
+                }
+            },
+
+            Op::Closure(code) => self.line(&format!(
+                "(call $rt_closure (i32.const {code}) (i32.const {}) (i32.const {}))",
+                program.codes[*code].arity, program.codes[*code].captures
+            )),
+
+            Op::Call(arity, at) => {
+                self.line(&format!("(local.set $callee (call $rt_callee (i32.const {arity}) (global.get $at{at})))"));
+                self.line("(call_indirect (type $code) (local.get $callee) (i32.load (local.get $callee)))");
+                self.line("(call $rt_returned)");
+            }
+
+            Op::Return => self.line("(return)"),
+            Op::Tuple => self.line("(call $rt_tuple)"),
+            Op::First(at) => self.line(&format!("(call $rt_first (global.get $at{at}))")),
+            Op::Second(at) => self.line(&format!("(call $rt_second (global.get $at{at}))")),
+            Op::Untuple(at) => self.line(&format!("(call $rt_untuple (global.get $at{at}))")),
+
+            Op::Record(names) => self.line(&format!(
+                "(call $rt_record (i32.const {}) (global.get $names{}))",
+                names.len(),
+                self.names.records[&names.to_vec()]
+            )),
+
+            Op::Field(name, at) => self.line(&format!(
+                "(call $rt_field (global.get $field{}) (global.get $at{at}))",
+                self.names.fields[name]
+            )),
+            Op::List(size) => self.line(&format!("(call $rt_list (i32.const {size}))")),
+            Op::Cons(at) => self.line(&format!("(call $rt_cons (global.get $at{at}))")),
+            Op::Head(at) => self.line(&format!("(call $rt_head (global.get $at{at}))")),
+            Op::Tail(at) => self.line(&format!("(call $rt_tail (global.get $at{at}))")),
+            Op::Len(at) => self.line(&format!("(call $rt_len (global.get $at{at}))")),
+            Op::Substr(at) => self.line(&format!("(call $rt_substr (global.get $at{at}))")),
+            Op::ToInt(at) => self.line(&format!("(call $rt_to_int (global.get $at{at}))")),
+            Op::ToStr(at) => self.line(&format!("(call $rt_to_str (global.get $at{at}))")),
+            Op::Contains => self.line("(call $rt_contains)"),
+            Op::Print(_) => self.line("(call $rt_print)"),
+            Op::ReadLine(at) => self.line(&format!("(call $rt_read_line (global.get $at{at}))")),
+            Op::Argv => self.line("(call $rt_argv)"),
+
+            Op::Test(slot, idx, target) => match &program.constants[*idx] {
+                Value::Int(i) => self.jump_if(
+                    &format!(
+                        "(i32.or (i32.ne (local.get $k{slot}) (i32.const 0xfe)) (i32.ne (local.get $p{slot}) (i32.const {i})))"
+                    ),
+                    *target,
+                ),
+                Value::Bool(b) => self.jump_if(
+                    &format!(
+                        "(i32.or (i32.ne (local.get $k{slot}) (i32.const 0xba)) (i32.ne (local.get $p{slot}) (i32.const {})))",
+                        *b as u8
+                    ),
+                    *target,
+                ),
+                // The payload is only a string when the kind says so.
+                Value::Str(_) => {
+                    self.jump_if(&format!("(i32.ne (local.get $k{slot}) (i32.const 0xca))"), *target);
+                    self.jump_if(
+                        &format!("(i32.eqz (call $str_eq (local.get $p{slot}) (global.get $c{idx})))"),
+                        *target,
+                    );
+                }
+                value => unreachable!("no patterns of kind {}", value.kind().name()),
+            },
+
+            Op::TestTuple(slot, target) => {
+                self.jump_if(&format!("(i32.ne (local.get $k{slot}) (i32.const 0x10))"), *target)
+            }
+
+            Op::NoMatch(at) => self.line(&format!(
+                "(call $fail_msg (global.get $at{at}) (global.get $fault_no_match) (global.get $text_no_match))"
+            )),
+        }
+    }
+}
+
+/// Writes the program as a WebAssembly text module. It imports `print`,
+/// `error`, `read`, `args_sizes`, `args` and `memory` from `touka` and
+/// exports `_start`, see the runtime for what the host has to do.
+pub fn write(program: &Program, output: &mut impl Write) -> io::Result<()> {
+    let mut data = Data::default();
+    let mut globals = String::new();
+
+    let mut global = |name: &str, address: usize| {
+        writeln!(globals, "  (global ${name} i32 (i32.const {address}))").unwrap();
+    };
+
+    for (name, text) in TEXTS {
+        global(name, data.string(text));
+    }
+
+    for (idx, location) in program.locations.iter().enumerate() {
+        global(&format!("at{idx}"), data.string(location));
+    }
+
+    for (idx, constant) in program.constants.iter().enumerate() {
+        if let Value::Str(s) = constant {
+            global(&format!("c{idx}"), data.string(&String::from_utf8_lossy(s)));
+        }
+    }
+
+    // The names of the fields, for the records and the accesses.
+    let mut names = Names::default();
+    for op in program.codes.iter().flat_map(|code| &code.ops) {
+        match op {
+            Op::Field(name, _) if !names.fields.contains_key(name) => {
+                global(&format!("field{}", names.fields.len()), data.string(name));
+                names.fields.insert(name.clone(), names.fields.len());
+            }
+            Op::Record(fields) if !names.records.contains_key(&fields.to_vec()) => {
+                let strings: Vec<_> = fields.iter().map(|name| data.string(name)).collect();
+                global(&format!("names{}", names.records.len()), data.words(&strings));
+                names.records.insert(fields.to_vec(), names.records.len());
+            }
+            _ => {}
+        }
+    }
+
+    // The stack of values comes right after the data, and then the heap.
+    let stack = (data.end() + 7) & !7;
+    let heap = stack + STACK_SIZE;
+
+    writeln!(output, "(module")?;
+    writeln!(output, "  (import \"touka\" \"print\" (func $print (param i32 i32)))")?;
+    writeln!(output, "  (import \"touka\" \"error\" (func $error (param i32 i32)))")?;
+    writeln!(output, "  (import \"touka\" \"read\" (func $read (param i32 i32) (result i32)))")?;
+    writeln!(output, "  (import \"touka\" \"args_sizes\" (func $args_sizes (param i32 i32)))")?;
+    writeln!(output, "  (import \"touka\" \"args\" (func $args (param i32 i32)))")?;
+    writeln!(output, "  (import \"touka\" \"memory\" (memory {}))", ((data.end() + 65535) / 65536).max(1))?;
+    writeln!(output, "  (data (i32.const {DATA}) {})", data.quoted())?;
+    write!(output, "{globals}")?;
+    writeln!(output, "  (global $sp (mut i32) (i32.const {stack}))")?;
+    writeln!(output, "  (global $heap (mut i32) (i32.const {heap}))")?;
+    writeln!(output, "{}", include_str!("yamero.wat"))?;
+
+    for (idx, code) in program.codes.iter().enumerate() {
+        let leaders = leaders(&code.ops);
+        let blocks: HashMap<_, _> = leaders.iter().enumerate().map(|(block, &ip)| (ip, block)).collect();
+
+        writeln!(output, "  ;; {}", code.name)?;
+        writeln!(output, "  (func $code{idx} (type $code) (param $env i32)")?;
+        write!(output, "    (local $pc i32) (local $callee i32)")?;
+        for slot in 0..code.locals {
+            write!(output, " (local $k{slot} i32) (local $p{slot} i32)")?;
+        }
+        writeln!(output)?;
+
+        // The arguments are moved from the stack of values to the locals,
+        // and the function itself is dropped.
+        for slot in 0..code.arity {
+            let offset = 8 * (code.arity - slot);
+            writeln!(
+                output,
+                "    (local.set $k{slot} (i32.load (i32.sub (global.get $sp) (i32.const {offset}))))"
+            )?;
+            writeln!(
+                output,
+                "    (local.set $p{slot} (i32.load offset=4 (i32.sub (global.get $sp) (i32.const {offset}))))"
+            )?;
+        }
+        writeln!(output, "    (call $drop (i32.const {}))", code.arity + 1)?;
+
+        writeln!(output, "    loop $dispatch")?;
+        for block in (0..leaders.len()).rev() {
+            writeln!(output, "    block $b{block}")?;
+        }
+        write!(output, "      local.get $pc\n      br_table")?;
+        for block in 0..leaders.len() {
+            write!(output, " $b{block}")?;
+        }
+        writeln!(output)?;
+
+        let mut function = Function {
+            program,
+            names: &names,
+            blocks,
+            body: String::new(),
+        };
+
+        for (ip, op) in code.ops.iter().enumerate() {
+            if let Some(block) = function.blocks.get(&ip) {
+                writeln!(function.body, "    end ;; $b{block}").unwrap();
+            }
+            function.op(op);
+        }
+
+        write!(output, "{}", function.body)?;
+        writeln!(output, "    end")?;
+        writeln!(output, "    unreachable)")?;
+    }
+
+    writeln!(output, "  (table {} funcref)", program.codes.len())?;
+    write!(output, "  (elem (i32.const 0)")?;
+    for idx in 0..program.codes.len() {
+        write!(output, " $code{idx}")?;
+    }
+    writeln!(output, ")")?;
+
+    // The program is called like any other function.
+    writeln!(output, "  (func (export \"_start\")")?;
+    writeln!(output, "    (call $reserve (global.get $heap))")?;
+    writeln!(output, "    (call $push (i32.const 0) (i32.const 0))")?;
+    writeln!(output, "    (call $code{} (i32.const 0)))", program.entry)?;
+    writeln!(output, ")")
+}
@@ -1,14 +1,12 @@
-
+#define _POSIX_C_SOURCE 200809L
+#include <pthread.h>
+#include <stdint.h>
 #include <stdio.h>
 #include <stdlib.h>
 #include <string.h>
+#include <sys/resource.h>
 
-#define fnDecl(name, ...) void *name(void *tr, ##__VA_ARGS__)
-#define panic(fmt, ...)                                                        \
-  do {                                                                         \
-    fprintf(stderr, "ToukaRT: " fmt, ##__VA_ARGS__);                           \
-    exit(0);                                                                   \
-  } while (0)
+#define fnDecl(name, ...) Any name(Kind *tr, Closure *env, ##__VA_ARGS__)
 
 typedef enum MathOp { Sub = 0x99, Rem = 0x98, Mul = 0x97, Div = 0x96 } MathOp;
 
@@ -18,92 +16,242 @@ typedef enum Kind {
   b = 0xba,
   u = 0xbe,
   kTuple = 0x10,
+  kRecord = 0x11,
+  kList = 0x12,
   kFunction = 0x42,
 } Kind;
 
 typedef enum BinaryOp { Lte = 1, Gte, Lt, Gt, Eq, Neq } BinaryOp;
 
+/* Runtime errors, the names are part of the message format. */
+typedef enum Fault {
+  InvalidOperands,
+  DivisionByZero,
+  NotATuple,
+  NotAFunction,
+  ArityMismatch,
+  NotABoolean,
+  NotARecord,
+  NoSuchField,
+  InvalidArgument,
+  TypeMismatch,
+  NotAList,
+  EmptyList,
+  NoMatch,
+  OutOfBounds,
+  EndOfInput,
+  NullString,
+  StackOverflow,
+  WriteError,
+} Fault;
+
+const char *Faults[] = {
+    "invalid-operands", "division-by-zero", "not-a-tuple",
+    "not-a-function",   "arity-mismatch",   "not-a-boolean",
+    "not-a-record",     "no-such-field",    "invalid-argument",
+    "type-mismatch",    "not-a-list",       "empty-list",
+    "no-match",         "out-of-bounds",
+    "end-of-input",     "null-string",      "stack-overflow",
+    "write-error",
+};
+
+/* Reports `ToukaRT: error[<fault>] at <file>:<location>: <message>` and
+ * exits with a failure status. */
+#define panic(at, fault, fmt, ...)                                             \
+  do {                                                                         \
+    fflush(stdout);                                                            \
+    fprintf(stderr, "ToukaRT: error[%s] at %s: " fmt "\n", Faults[fault], at,  \
+            ##__VA_ARGS__);                                                    \
+    exit(1);                                                                   \
+  } while (0)
+
+struct Tuple;
+struct Record;
+struct List;
+struct Closure;
+
+/* Every slot of a program, the kind lives aside as a `Kind`. */
+typedef union Any {
+  int i;
+  char *s;
+  char b;
+  struct Tuple *tuple;
+  struct Record *record;
+  struct List *list;
+  struct Closure *fn;
+} Any;
+
 typedef struct Tuple {
-  void *a, *b;
+  Any a, b;
   Kind ta, tb;
 } Tuple;
 
+typedef struct Record {
+  int size;
+  const char **names;
+  Any *v;
+  Kind *t;
+} Record;
+
+/* A cons cell, the empty list is `NULL`. Every cell knows the length of
+ * the list it starts. */
+typedef struct List {
+  Any head;
+  Kind t;
+  int len;
+  struct List *tail;
+} List;
+
+/* A function value: the code pointer and the captured slots. */
+typedef struct Closure {
+  void *code;
+  int arity;
+  Any *v;
+  Kind *t;
+} Closure;
+
 typedef char **PSTR;
 #define true 1
 #define false 0
 
+const char *KindName(Kind t) {
+  switch (t) {
+  case s:
+    return "string";
+  case i:
+    return "int";
+  case b:
+    return "bool";
+  case kTuple:
+    return "tuple";
+  case kRecord:
+    return "record";
+  case kList:
+    return "list";
+  case kFunction:
+    return "function";
+  default:
+    return "unknown";
+  }
+}
+
 /* Print */
-void pi(void *v, Kind t) {
+void pi(Any *v, Kind t) {
   switch (t) {
   case s:
-    printf("%s", *(char **)v);
+    printf("%s", v->s);
     break;
   case i:
-    printf("%d", *(int *)v);
+    printf("%d", v->i);
     break;
   case u:
     printf("<#unknown>");
     break;
   case b:
-    printf("%s", *(char *)v ? "true" : "false");
+    printf("%s", v->b ? "true" : "false");
     break;
-  case kTuple: {
-    struct Tuple _t = *((Tuple *)v);
+  case kTuple:
     printf("(");
-    pi(_t.a, _t.ta);
+    pi(&v->tuple->a, v->tuple->ta);
     printf(", ");
-    pi(_t.b, _t.tb);
+    pi(&v->tuple->b, v->tuple->tb);
     printf(")");
     break;
-  }
+  case kRecord:
+    printf("{");
+    for (int idx = 0; idx < v->record->size; idx++) {
+      printf(idx ? ", %s: " : "%s: ", v->record->names[idx]);
+      pi(&v->record->v[idx], v->record->t[idx]);
+    }
+    printf("}");
+    break;
+  case kList:
+    printf("[");
+    for (List *l = v->list; l; l = l->tail) {
+      pi(&l->head, l->t);
+      if (l->tail)
+        printf(", ");
+    }
+    printf("]");
+    break;
+  case kFunction:
+    printf("<#closure>");
+    break;
   }
 }
 
-inline void p(void *v, Kind t) {
+/* The output is line buffered like the virtual machine's, so a print
+ * that can't be written fails where it is. */
+void p(Any *v, Kind t, const char *at) {
 #ifdef dbg
-  fprintf(stderr, "ToukaRT/IO/WriteStdout: v=%x, t=%x: ", v, t);
+  fprintf(stderr, "ToukaRT/IO/WriteStdout: v=%p, t=%x: ", (void *)v, t);
 #endif
 
   pi(v, t);
   puts("");
+  if (fflush(stdout) == EOF) {
+    clearerr(stdout);
+    panic(at, WriteError, "cannot write to the standard output");
+  }
 }
 
+/* The ints wrap around, the math is done on unsigned ints where it's
+ * defined, and `INT_MIN / -1` is `INT_MIN` instead of a trap. */
+int WrapAdd(int a, int b) { return (int)((unsigned)a + (unsigned)b); }
+int WrapSub(int a, int b) { return (int)((unsigned)a - (unsigned)b); }
+int WrapMul(int a, int b) { return (int)((unsigned)a * (unsigned)b); }
+int WrapDiv(int a, int b) { return b == -1 ? WrapSub(0, a) : a / b; }
+int WrapRem(int a, int b) { return b == -1 ? 0 : a % b; }
+
 /* Sum */
-void S(void *r, Kind *t_r, void *a, void *b, Kind t_a, Kind t_b) {
-  /* TODO: Use arrays. */
+void S(Any *r, Kind *t_r, Any *lhs, Any *rhs, Kind t_a, Kind t_b,
+       const char *at) {
 #ifdef dbg
-  fprintf(stderr, "ToukaRT/Sum: %x + %x (?%p, ?%p) -> %p/%x\n", t_a, t_b, a, b,
-          r, t_r);
+  fprintf(stderr, "ToukaRT/Sum: %x + %x (?%p, ?%p) -> %p\n", t_a, t_b,
+          (void *)lhs, (void *)rhs, (void *)r);
 #endif
 
-  if (t_a == s && t_b == s)
-    sprintf(*(char **)r, "%s%s", *(PSTR)a, *(PSTR)b);
+  if (t_a == i && t_b == i) {
+    r->i = WrapAdd(lhs->i, rhs->i);
+    *t_r = i;
+    return;
+  }
 
-  else if (t_a == s && t_b == i)
-    sprintf(*(char **)r, "%s%d", *(PSTR)a, *(int *)b);
+  int size;
 
+  if (t_a == s && t_b == s)
+    size = snprintf(NULL, 0, "%s%s", lhs->s, rhs->s);
+  else if (t_a == s && t_b == i)
+    size = snprintf(NULL, 0, "%s%d", lhs->s, rhs->i);
   else if (t_a == i && t_b == s)
-    sprintf(*(char **)r, "%d%s", *(int *)a, *(PSTR)b);
+    size = snprintf(NULL, 0, "%d%s", lhs->i, rhs->s);
+  else
+    panic(at, InvalidOperands, "cannot add %s and %s", KindName(t_a),
+          KindName(t_b));
 
-  else if (t_a == i && t_b == i) {
-    *(int *)r = *(int *)a + *(int *)b;
-    *t_r = i;
-    return;
-  }
+  char *out = malloc(size + 1);
 
+  if (t_a == s && t_b == s)
+    sprintf(out, "%s%s", lhs->s, rhs->s);
+  else if (t_a == s && t_b == i)
+    sprintf(out, "%s%d", lhs->s, rhs->i);
   else
-    panic("Invalid sum between %x and %x. Aborting program exec.", t_a, t_b);
+    sprintf(out, "%d%s", lhs->i, rhs->s);
 
+  r->s = out;
   *t_r = s;
 }
 
-/* Do the math  */
-void BinaryEvaluateA(char *r, void *a, void *b, Kind t_a, Kind t_b, MathOp op) {
+/* Do the comparisons */
+void BinaryEvaluateA(Any *r, Kind *t_r, Any *lhs, Any *rhs, Kind t_a, Kind t_b,
+                     BinaryOp op, const char *at) {
 #define each(x, y)                                                             \
   case x:                                                                      \
-    *(int *)r = *(int *)a y * (int *)b;                                        \
+    r->b = lhs->i y rhs->i;                                                    \
     break;
 
+  *t_r = b;
+
   if (t_a == i && t_a == t_b) {
     switch (op) {
       each(Eq, ==);
@@ -115,60 +263,373 @@ void BinaryEvaluateA(char *r, void *a, void *b, Kind t_a, Kind t_b, MathOp op) {
     }
   }
 
+#undef each
+#define each(x, y)                                                             \
+  case x:                                                                      \
+    r->b = strcmp(lhs->s, rhs->s) y 0;                                         \
+    break;
+
   else if (t_a == s && t_a == t_b) {
+    switch (op) {
+      each(Eq, ==);
+      each(Neq, !=);
+      each(Gt, >);
+      each(Lt, <);
+      each(Gte, >=);
+      each(Lte, <=);
+    }
+  }
+
+  else if (t_a == b && t_a == t_b) {
     switch (op) {
     case Eq:
-      *r = (0 == strcmp(*(PSTR)a, *(PSTR)b));
+      r->b = lhs->b == rhs->b;
       break;
 
     case Neq:
-      *r = (0 != strcmp(*(PSTR)a, *(PSTR)b));
+      r->b = lhs->b != rhs->b;
       break;
     default:
-      panic("String comparation doesn't support %x. Aborting program exec.",
-            op);
+      panic(at, InvalidOperands, "cannot compare booleans by order");
     }
   }
 
   else
-    panic("Invalid %x operation between %x and %x. Aborting program exec.", op,
-          t_a, t_b);
+    panic(at, InvalidOperands, "cannot compare %s and %s", KindName(t_a),
+          KindName(t_b));
 #undef each
 }
 
-void MathEvaluateA(int *r, void *a, void *b, Kind t_a, Kind t_b, MathOp op) {
+/* Do the math  */
+void MathEvaluateA(Any *r, Kind *t_r, Any *lhs, Any *rhs, Kind t_a, Kind t_b,
+                   MathOp op, const char *at) {
 #define each(x, y)                                                             \
   case x:                                                                      \
-    *r = *(int *)a y * (int *)b;                                               \
+    r->i = y(lhs->i, rhs->i);                                                  \
     break;
 
-  if (t_a == i && t_a == t_b) {
-    switch (op) {
-      each(Sub, -);
-      each(Div, /);
-      each(Rem, %);
-      each(Mul, *);
-    }
-  } else
-    panic("Invalid %x operation between %x and %x. Aborting program exec.", op,
-          t_a, t_b);
+  *t_r = i;
+
+  if (t_a != i || t_a != t_b)
+    panic(at, InvalidOperands, "cannot do arithmetic between %s and %s",
+          KindName(t_a), KindName(t_b));
+
+  if ((op == Div || op == Rem) && rhs->i == 0)
+    panic(at, DivisionByZero, "division by zero");
+
+  switch (op) {
+    each(Sub, WrapSub);
+    each(Div, WrapDiv);
+    each(Rem, WrapRem);
+    each(Mul, WrapMul);
+  }
 #undef each
 }
 
-void TupleIdxA(void **r, Kind *tR, void *t, Kind k, char idx) {
-  if (k != kTuple)
-    panic("I need a tuple blyat!");
+char Truthy(Any v, Kind t, const char *at) {
+  if (t != b)
+    panic(at, NotABoolean, "expected a bool, found %s", KindName(t));
+
+  return v.b;
+}
+
+void MakeTuple(Any *r, Kind *t_r, Any first, Kind ta, Any second, Kind tb) {
+  Tuple *_t = malloc(sizeof(Tuple));
 
-  if (idx > 1)
-    panic("Tuples have no more than 2 idx Vadim!");
+  _t->a = first;
+  _t->b = second;
+  _t->ta = ta;
+  _t->tb = tb;
 
-  struct Tuple _t = *(Tuple *)t;
+  r->tuple = _t;
+  *t_r = kTuple;
+}
+
+void TupleIdxA(Any *r, Kind *tR, Any *t, Kind k, char idx, const char *at) {
+  if (k != kTuple)
+    panic(at, NotATuple, "expected a tuple, found %s", KindName(k));
 
   if (!idx) {
-    *r = *(void **)_t.a;
-    *tR = _t.ta;
+    *r = t->tuple->a;
+    *tR = t->tuple->ta;
   } else {
-    *r = *(void **)_t.b;
-    *tR = _t.tb;
+    *r = t->tuple->b;
+    *tR = t->tuple->tb;
+  }
+}
+
+/* Takes a tuple apart, like `let (a, b) = v`. */
+void Untuple(Any *a, Kind *ta, Any *b, Kind *tb, Any *v, Kind k,
+             const char *at) {
+  if (k != kTuple)
+    panic(at, NotATuple, "expected a tuple, found %s", KindName(k));
+
+  *a = v->tuple->a;
+  *ta = v->tuple->ta;
+  *b = v->tuple->b;
+  *tb = v->tuple->tb;
+}
+
+void MakeRecord(Any *r, Kind *t_r, int size, const char **names) {
+  Record *_r = malloc(sizeof(Record));
+
+  _r->size = size;
+  _r->names = malloc(size * sizeof(char *));
+  _r->v = calloc(size, sizeof(Any));
+  _r->t = calloc(size, sizeof(Kind));
+  memcpy(_r->names, names, size * sizeof(char *));
+
+  r->record = _r;
+  *t_r = kRecord;
+}
+
+void FieldA(Any *r, Kind *tR, Any *v, Kind k, const char *name,
+            const char *at) {
+  if (k != kRecord)
+    panic(at, NotARecord, "expected a record, found %s", KindName(k));
+
+  for (int idx = 0; idx < v->record->size; idx++) {
+    if (0 == strcmp(v->record->names[idx], name)) {
+      *r = v->record->v[idx];
+      *tR = v->record->t[idx];
+      return;
+    }
   }
+
+  panic(at, NoSuchField, "the record has no field `%s`", name);
+}
+
+void Cons(Any *r, Kind *t_r, Any head, Kind t, Any *list, Kind k,
+          const char *at) {
+  if (k != kList)
+    panic(at, NotAList, "expected a list, found %s", KindName(k));
+
+  List *l = malloc(sizeof(List));
+
+  l->head = head;
+  l->t = t;
+  l->tail = list->list;
+  l->len = list->list ? list->list->len + 1 : 1;
+
+  r->list = l;
+  *t_r = kList;
+}
+
+/* The first cell of a list, which mustn't be empty. */
+List *Cell(Any *v, Kind k, const char *at) {
+  if (k != kList)
+    panic(at, NotAList, "expected a list, found %s", KindName(k));
+
+  if (!v->list)
+    panic(at, EmptyList, "the list is empty");
+
+  return v->list;
+}
+
+void HeadA(Any *r, Kind *t_r, Any *v, Kind k, const char *at) {
+  List *l = Cell(v, k, at);
+
+  *r = l->head;
+  *t_r = l->t;
+}
+
+void TailA(Any *r, Kind *t_r, Any *v, Kind k, const char *at) {
+  r->list = Cell(v, k, at)->tail;
+  *t_r = kList;
+}
+
+void LenA(Any *r, Kind *t_r, Any *v, Kind k, const char *at) {
+  if (k == s)
+    r->i = strlen(v->s);
+  else if (k == kList)
+    r->i = v->list ? v->list->len : 0;
+  else
+    panic(at, InvalidArgument, "expected a list or a string, found %s",
+          KindName(k));
+
+  *t_r = i;
+}
+
+/* String builtins, the kinds of the arguments are already checked. */
+void Substr(Any *r, Kind *t_r, Any *v, int start, int length,
+            const char *at) {
+  int size = strlen(v->s);
+
+  if (start < 0 || length < 0 || start > size || length > size - start)
+    panic(at, OutOfBounds,
+          "cannot take %d characters from %d of a string of length %d", length,
+          start, size);
+
+  char *out = malloc(length + 1);
+  memcpy(out, v->s + start, length);
+  out[length] = 0;
+
+  r->s = out;
+  *t_r = s;
+}
+
+void ToInt(Any *r, Kind *t_r, Any *v, const char *at) {
+  char *end;
+  long n = strtol(v->s, &end, 10);
+
+  if (!*v->s || *end || n < -2147483648L || n > 2147483647L)
+    panic(at, InvalidArgument, "`%s` is not a number", v->s);
+
+  r->i = n;
+  *t_r = i;
+}
+
+void ToStr(Any *r, Kind *t_r, Any *v, Kind k, const char *at) {
+  switch (k) {
+  case s:
+    r->s = v->s;
+    break;
+  case i:
+    r->s = malloc(snprintf(NULL, 0, "%d", v->i) + 1);
+    sprintf(r->s, "%d", v->i);
+    break;
+  case b:
+    r->s = v->b ? "true" : "false";
+    break;
+  default:
+    panic(at, InvalidArgument, "cannot convert %s to a string", KindName(k));
+  }
+
+  *t_r = s;
+}
+
+/* The command-line arguments, without the name of the program. */
+int Argc;
+char **Args;
+
+void Init(int argc, char **argv) {
+  Argc = argc - 1;
+  Args = argv + 1;
+}
+
+void ArgvA(Any *r, Kind *t_r) {
+  r->list = NULL;
+  *t_r = kList;
+
+  for (int idx = Argc - 1; idx >= 0; idx--) {
+    List *l = malloc(sizeof(List));
+
+    l->head.s = Args[idx];
+    l->t = s;
+    l->tail = r->list;
+    l->len = Argc - idx;
+    r->list = l;
+  }
+}
+
+void ReadLine(Any *r, Kind *t_r, const char *at) {
+  char *line = NULL;
+  size_t capacity = 0;
+  ssize_t size = getline(&line, &capacity, stdin);
+
+  if (size < 0)
+    panic(at, EndOfInput, "there are no more lines to read");
+
+  if (size > 0 && line[size - 1] == '\n')
+    line[--size] = 0;
+  if (size > 0 && line[size - 1] == '\r')
+    line[--size] = 0;
+
+  r->s = line;
+  *t_r = s;
+}
+
+/* Checks the kind of an argument of an external function, or of an
+ * annotated binding. */
+void Expect(Kind t, Kind expected, Fault fault, const char *at) {
+  if (t != expected)
+    panic(at, fault, "expected %s, found %s", KindName(expected), KindName(t));
+}
+
+/* The string an external function returned, a NULL isn't one. */
+char *NonNull(char *s, const char *at) {
+  if (s == NULL)
+    panic(at, NullString, "the external function returned NULL");
+
+  return s;
+}
+
+int NonZero(int n, const char *at) {
+  if (n == 0)
+    panic(at, DivisionByZero, "division by zero");
+
+  return n;
+}
+
+void MakeClosure(Any *r, Kind *t_r, void *code, int arity, int size) {
+  Closure *c = malloc(sizeof(Closure));
+
+  c->code = code;
+  c->arity = arity;
+  c->v = calloc(size, sizeof(Any));
+  c->t = calloc(size, sizeof(Kind));
+
+  r->fn = c;
+  *t_r = kFunction;
+}
+
+/* The program runs on a stack of its own, big enough for the recursions
+ * the virtual machine runs too. A call that goes past its end, less a
+ * margin for the runtime, is an error instead of a crash. */
+#define STACK_SIZE ((size_t)256 << 20)
+#define STACK_MARGIN ((size_t)256 << 10)
+
+uintptr_t StackEnd;
+
+/* Marks the end of the stack that starts at `top`. */
+void StackFrom(char *top, size_t size) {
+  StackEnd = size > STACK_MARGIN ? (uintptr_t)top - size + STACK_MARGIN : 0;
+}
+
+/* Checks that there's room for one more call. */
+void Deeper(const char *at) {
+  char here;
+
+  if ((uintptr_t)&here < StackEnd)
+    panic(at, StackOverflow, "the recursion is too deep");
+}
+
+void *Start(void *program) {
+  char top;
+
+  StackFrom(&top, STACK_SIZE);
+  ((void (*)(void))program)();
+  return NULL;
+}
+
+/* Runs the program on its stack, or on this one if there can't be
+ * another. */
+int Run(void (*program)(void)) {
+  pthread_attr_t attr;
+  pthread_t thread;
+  struct rlimit limit;
+  char top;
+
+  pthread_attr_init(&attr);
+  pthread_attr_setstacksize(&attr, STACK_SIZE);
+  if (pthread_create(&thread, &attr, Start, (void *)program) == 0)
+    return pthread_join(thread, NULL);
+
+  if (getrlimit(RLIMIT_STACK, &limit) == 0 && limit.rlim_cur != RLIM_INFINITY)
+    StackFrom(&top, limit.rlim_cur);
+  program();
+  return 0;
+}
+
+Closure *Callable(Any v, Kind t, int arity, const char *at) {
+  Deeper(at);
+
+  if (t != kFunction)
+    panic(at, NotAFunction, "expected a function, found %s", KindName(t));
+
+  if (v.fn->arity != arity)
+    panic(at, ArityMismatch, "expected %d arguments, found %d", v.fn->arity,
+          arity);
+
+  return v.fn;
 }
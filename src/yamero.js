@@ -0,0 +1,279 @@
+// The runtime of the JavaScript backend, for node and the browsers.
+//
+// Ints are numbers that wrap like the C ones, or BigInts that don't when
+// `$big` is set. Strings are strings, bools are booleans, tuples are
+// frozen arrays, records are frozen objects, lists are `$List` cells and
+// functions are functions.
+//
+// A string has a character for each of its UTF-8 bytes, so they're
+// measured, cut and ordered as bytes like the C runtime does. They're
+// decoded when they're written.
+
+class $Fault extends Error {}
+
+function $fail(fault, at, message) {
+  throw new $Fault(`ToukaRT: error[${fault}] at ${at}: ${message}`);
+}
+
+class $List {
+  constructor(head, tail) {
+    this.head = head;
+    this.tail = tail;
+    this.len = tail === undefined ? 0 : tail.len + 1;
+    Object.freeze(this);
+  }
+}
+
+const $empty = new $List(undefined, undefined);
+
+function $int(n) {
+  return $big ? BigInt(n) : n;
+}
+
+function $kind(value) {
+  switch (typeof value) {
+    case "number":
+    case "bigint":
+      return "int";
+    case "string":
+      return "string";
+    case "boolean":
+      return "bool";
+    case "function":
+      return "function";
+  }
+
+  if (Array.isArray(value)) return "tuple";
+  if (value instanceof $List) return "list";
+  return "record";
+}
+
+// The text of a value, the way `pi` writes it.
+function $show(value) {
+  switch ($kind(value)) {
+    case "tuple":
+      return `(${$show(value[0])}, ${$show(value[1])})`;
+    case "list": {
+      const elements = [];
+      for (let cell = value; cell.len > 0; cell = cell.tail) elements.push($show(cell.head));
+      return `[${elements.join(", ")}]`;
+    }
+    case "record":
+      return `{${Object.entries(value).map(([name, field]) => `${name}: ${$show(field)}`).join(", ")}}`;
+    case "function":
+      return "<#closure>";
+    default:
+      return String(value);
+  }
+}
+
+const $utf8 = { encoder: new TextEncoder(), decoder: new TextDecoder() };
+
+// The string of some bytes.
+function $chars(bytes) {
+  return Array.from(bytes, (byte) => String.fromCharCode(byte)).join("");
+}
+
+// The string of the UTF-8 bytes of a text.
+function $string(text) {
+  return $chars($utf8.encoder.encode(text));
+}
+
+// The text of a string, the bytes that aren't UTF-8 are replaced.
+function $text(s) {
+  return $utf8.decoder.decode(Uint8Array.from(s, (c) => c.charCodeAt(0)));
+}
+
+// Writes the bytes as they are under node, a browser has only text.
+function $print(value) {
+  const line = $show(value);
+  if (typeof Buffer === "function") process.stdout.write(Buffer.from(line + "\n", "latin1"));
+  else console.log($text(line));
+  return value;
+}
+
+function $expect(value, kind, at) {
+  if ($kind(value) !== kind) $fail("type-mismatch", at, `expected ${kind}, found ${$kind(value)}`);
+  return value;
+}
+
+function $bool(value, at) {
+  if (typeof value !== "boolean") $fail("not-a-boolean", at, `expected a bool, found ${$kind(value)}`);
+  return value;
+}
+
+// A call that runs out of the stack of the engine fails at its location,
+// once there's room again to build the error.
+function $call(f, at, ...args) {
+  if (typeof f !== "function") $fail("not-a-function", at, `expected a function, found ${$kind(f)}`);
+  if (f.length !== args.length) $fail("arity-mismatch", at, `expected ${f.length} arguments, found ${args.length}`);
+  try {
+    return f(...args);
+  } catch (e) {
+    if ($exhausted(e)) $fail("stack-overflow", at, "the recursion is too deep");
+    throw e;
+  }
+}
+
+// Whether an error is the engine running out of stack, "Maximum call
+// stack size exceeded" under node.
+function $exhausted(e) {
+  return e instanceof RangeError && /call stack/i.test(e.message);
+}
+
+function $add(a, b, at) {
+  const [x, y] = [$kind(a), $kind(b)];
+  if (x === "int" && y === "int") return $big ? a + b : (a + b) | 0;
+  if ((x === "string" || x === "int") && (y === "string" || y === "int")) return String(a) + String(b);
+  $fail("invalid-operands", at, `cannot add ${x} and ${y}`);
+}
+
+function $arithmetic(op, a, b, at) {
+  if ($kind(a) !== "int" || $kind(b) !== "int") {
+    $fail("invalid-operands", at, `cannot do arithmetic between ${$kind(a)} and ${$kind(b)}`);
+  }
+
+  if ((op === "/" || op === "%") && b == 0) $fail("division-by-zero", at, "division by zero");
+
+  switch (op) {
+    case "-":
+      return $big ? a - b : (a - b) | 0;
+    case "*":
+      return $big ? a * b : Math.imul(a, b);
+    case "/":
+      return $big ? a / b : (a / b) | 0;
+    default:
+      return $big ? a % b : (a % b) | 0;
+  }
+}
+
+function $compare(op, a, b, at) {
+  const kind = $kind(a);
+  if (kind !== $kind(b) || !["int", "string", "bool"].includes(kind)) {
+    $fail("invalid-operands", at, `cannot compare ${kind} and ${$kind(b)}`);
+  }
+
+  if (kind === "bool" && op !== "==" && op !== "!=") {
+    $fail("invalid-operands", at, "cannot compare booleans by order");
+  }
+
+  const ordering = a === b ? 0 : a < b ? -1 : 1;
+  switch (op) {
+    case "==":
+      return ordering === 0;
+    case "!=":
+      return ordering !== 0;
+    case "<":
+      return ordering < 0;
+    case ">":
+      return ordering > 0;
+    case "<=":
+      return ordering <= 0;
+    default:
+      return ordering >= 0;
+  }
+}
+
+function $tuple(value, at) {
+  if (!Array.isArray(value)) $fail("not-a-tuple", at, `expected a tuple, found ${$kind(value)}`);
+  return value;
+}
+
+function $field(record, name, at) {
+  if ($kind(record) !== "record") $fail("not-a-record", at, `expected a record, found ${$kind(record)}`);
+  if (!Object.hasOwn(record, name)) $fail("no-such-field", at, `the record has no field \`${name}\``);
+  return record[name];
+}
+
+function $list(elements) {
+  return elements.reduceRight((tail, head) => new $List(head, tail), $empty);
+}
+
+function $cons(head, tail, at) {
+  if (!(tail instanceof $List)) $fail("not-a-list", at, `expected a list, found ${$kind(tail)}`);
+  return new $List(head, tail);
+}
+
+function $cell(list, at) {
+  if (!(list instanceof $List)) $fail("not-a-list", at, `expected a list, found ${$kind(list)}`);
+  if (list.len === 0) $fail("empty-list", at, "the list is empty");
+  return list;
+}
+
+function $len(value, at) {
+  if (typeof value === "string") return $int(value.length);
+  if (value instanceof $List) return $int(value.len);
+  $fail("invalid-argument", at, `expected a list or a string, found ${$kind(value)}`);
+}
+
+function $substr(s, start, length, at) {
+  const [from, count] = [Number(start), Number(length)];
+  if (from < 0 || count < 0 || from > s.length || count > s.length - from) {
+    $fail("out-of-bounds", at, `cannot take ${length} characters from ${start} of a string of length ${s.length}`);
+  }
+  return s.slice(from, from + count);
+}
+
+// The numbers `strtol` reads, in the range of the ints unless they're
+// BigInts.
+function $toInt(s, at) {
+  const match = /^[ \t\n\v\f\r]*([+-]?[0-9]+)$/.exec(s);
+  const n = match && BigInt(match[1]);
+  if (n === null || (!$big && (n < -2147483648n || n > 2147483647n))) {
+    $fail("invalid-argument", at, `\`${$text(s)}\` is not a number`);
+  }
+  return $big ? n : Number(n);
+}
+
+function $toStr(value, at) {
+  if (["int", "string", "bool"].includes($kind(value))) return String(value);
+  $fail("invalid-argument", at, `cannot convert ${$kind(value)} to a string`);
+}
+
+// The standard input, only under node.
+const $input = { bytes: new Uint8Array(), done: typeof require !== "function" };
+
+function $readLine(at) {
+  const fs = $input.done ? null : require("fs");
+  while (!$input.done && !$input.bytes.includes(10)) {
+    const chunk = new Uint8Array(4096);
+    let read = 0;
+    try {
+      read = fs.readSync(0, chunk, 0, chunk.length, null);
+    } catch (e) {
+      if (e.code === "EAGAIN") continue;
+      if (e.code !== "EOF") throw e;
+    }
+
+    if (read === 0) $input.done = true;
+    $input.bytes = new Uint8Array([...$input.bytes, ...chunk.subarray(0, read)]);
+  }
+
+  if ($input.bytes.length === 0) $fail("end-of-input", at, "there are no more lines to read");
+
+  const end = $input.bytes.includes(10) ? $input.bytes.indexOf(10) : $input.bytes.length;
+  let line = $input.bytes.subarray(0, end);
+  $input.bytes = $input.bytes.subarray(end + 1);
+  if (line[line.length - 1] === 13) line = line.subarray(0, line.length - 1);
+  return $chars(line);
+}
+
+function $argv() {
+  return $list(typeof process === "undefined" ? [] : process.argv.slice(2).map($string));
+}
+
+function $noMatch(at) {
+  $fail("no-match", at, "no arm matches the value");
+}
+
+// Runs the program, a runtime error is written like the C runtime does.
+function $main(program) {
+  try {
+    program();
+  } catch (e) {
+    if ($exhausted(e)) e = new $Fault("ToukaRT: error[stack-overflow] at <program>: the recursion is too deep");
+    if (!(e instanceof $Fault)) throw e;
+    console.error(e.message);
+    if (typeof process !== "undefined") process.exitCode = 1;
+  }
+}
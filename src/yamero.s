@@ -0,0 +1,1377 @@
+# The runtime of the x86-64 backend, for Linux and without libc: what it
+# needs from the system goes through syscalls.
+#
+# Every value is 16 bytes, the kind and then the payload, with the kinds
+# of the C runtime. The values being computed live on a stack of their
+# own, r15 points past its top. The runtime keeps rbx, rbp and r12-r15.
+
+        .intel_syntax noprefix
+
+        .equ KIND_STR, 0xca
+        .equ KIND_INT, 0xfe
+        .equ KIND_BOOL, 0xba
+        .equ KIND_TUPLE, 0x10
+        .equ KIND_RECORD, 0x11
+        .equ KIND_LIST, 0x12
+        .equ KIND_FUNCTION, 0x42
+
+        .equ SYS_READ, 0
+        .equ SYS_WRITE, 1
+        .equ SYS_MMAP, 9
+        .equ SYS_EXIT, 60
+
+        .equ OUT_SIZE, 65536
+        .equ IN_SIZE, 4096
+        .equ AREA_SIZE, 0x40000000
+        .equ STACK_SIZE, 0x10000000
+        .equ STACK_MARGIN, 0x40000
+
+        .section .rodata
+kind_str:       .asciz "string"
+kind_int:       .asciz "int"
+kind_bool:      .asciz "bool"
+kind_tuple:     .asciz "tuple"
+kind_record:    .asciz "record"
+kind_list:      .asciz "list"
+kind_function:  .asciz "function"
+kind_unknown:   .asciz "unknown"
+
+fault_invalid_operands: .asciz "invalid-operands"
+fault_division_by_zero: .asciz "division-by-zero"
+fault_not_a_tuple:      .asciz "not-a-tuple"
+fault_not_a_function:   .asciz "not-a-function"
+fault_arity_mismatch:   .asciz "arity-mismatch"
+fault_not_a_boolean:    .asciz "not-a-boolean"
+fault_not_a_record:     .asciz "not-a-record"
+fault_no_such_field:    .asciz "no-such-field"
+fault_invalid_argument: .asciz "invalid-argument"
+fault_type_mismatch:    .asciz "type-mismatch"
+fault_not_a_list:       .asciz "not-a-list"
+fault_empty_list:       .asciz "empty-list"
+fault_no_match:         .asciz "no-match"
+fault_out_of_bounds:    .asciz "out-of-bounds"
+fault_end_of_input:     .asciz "end-of-input"
+fault_stack_overflow:   .asciz "stack-overflow"
+fault_out_of_memory:    .asciz "out-of-memory"
+
+text_error:             .asciz "ToukaRT: error["
+text_at:                .asciz "] at "
+text_colon:             .asciz ": "
+text_newline:           .asciz "\n"
+text_open_paren:        .asciz "("
+text_close_paren:       .asciz ")"
+text_open_bracket:      .asciz "["
+text_close_bracket:     .asciz "]"
+text_open_brace:        .asciz "{"
+text_close_brace:       .asciz "}"
+text_comma:             .asciz ", "
+text_closure:           .asciz "<#closure>"
+text_and:               .asciz " and "
+text_expected:          .asciz "expected "
+text_found:             .asciz ", found "
+text_arguments:         .asciz " arguments, found "
+text_backtick:          .asciz "`"
+text_cannot_add:        .asciz "cannot add "
+text_cannot_compare:    .asciz "cannot compare "
+text_compare_booleans:  .asciz "cannot compare booleans by order"
+text_arithmetic:        .asciz "cannot do arithmetic between "
+text_division_by_zero:  .asciz "division by zero"
+text_no_match:          .asciz "no arm matches the value"
+text_no_field:          .asciz "the record has no field `"
+text_expected_bool:     .asciz "expected a bool, found "
+text_expected_tuple:    .asciz "expected a tuple, found "
+text_expected_record:   .asciz "expected a record, found "
+text_expected_list:     .asciz "expected a list, found "
+text_expected_function: .asciz "expected a function, found "
+text_expected_sized:    .asciz "expected a list or a string, found "
+text_empty_list:        .asciz "the list is empty"
+text_cannot_convert:    .asciz "cannot convert "
+text_to_string:         .asciz " to a string"
+text_not_a_number:      .asciz "` is not a number"
+text_cannot_take:       .asciz "cannot take "
+text_characters_from:   .asciz " characters from "
+text_of_length:         .asciz " of a string of length "
+text_no_more_lines:     .asciz "there are no more lines to read"
+text_too_deep:          .asciz "the recursion is too deep"
+text_heap_full:         .asciz "the heap is full"
+text_no_area:           .asciz "cannot map the memory of the program"
+text_start:             .asciz "_start"
+
+        .balign 8
+str_true:       .quad 4
+                .ascii "true"
+        .balign 8
+str_false:      .quad 5
+                .ascii "false"
+
+        .bss
+        .balign 16
+out_buffer:     .zero OUT_SIZE
+out_size:       .zero 8
+in_buffer:      .zero IN_SIZE
+in_position:    .zero 8
+in_size:        .zero 8
+heap:           .zero 8
+heap_end:       .zero 8
+stack_end:      .zero 8
+location:       .zero 8
+args_count:     .zero 8
+args:           .zero 8
+digits:         .zero 16
+
+        .text
+
+# Writes rdx bytes from rsi to the file descriptor in rdi.
+rt_write_fd:
+.Lwrite_loop:
+        test rdx, rdx
+        jz .Lwrite_done
+        mov eax, SYS_WRITE
+        syscall
+        test rax, rax
+        jle .Lwrite_done
+        add rsi, rax
+        sub rdx, rax
+        jmp .Lwrite_loop
+.Lwrite_done:
+        ret
+
+rt_flush:
+        mov edi, 1
+        lea rsi, [rip + out_buffer]
+        mov rdx, [rip + out_size]
+        call rt_write_fd
+        mov qword ptr [rip + out_size], 0
+        ret
+
+# Buffers rdx bytes from rsi for the standard output.
+rt_out:
+        push rbx
+        push r12
+        mov rbx, rsi
+        mov r12, rdx
+.Lout_loop:
+        test r12, r12
+        jz .Lout_done
+        mov rax, [rip + out_size]
+        cmp rax, OUT_SIZE
+        jb .Lout_store
+        call rt_flush
+        xor eax, eax
+.Lout_store:
+        mov cl, [rbx]
+        lea rdx, [rip + out_buffer]
+        mov [rdx + rax], cl
+        inc rax
+        mov [rip + out_size], rax
+        inc rbx
+        dec r12
+        jmp .Lout_loop
+.Lout_done:
+        pop r12
+        pop rbx
+        ret
+
+# The length of the C string in rdi, in rax.
+rt_cstrlen:
+        xor eax, eax
+.Lcstrlen_loop:
+        cmp byte ptr [rdi + rax], 0
+        je .Lcstrlen_done
+        inc rax
+        jmp .Lcstrlen_loop
+.Lcstrlen_done:
+        ret
+
+rt_out_cstr:
+        call rt_cstrlen
+        mov rsi, rdi
+        mov rdx, rax
+        jmp rt_out
+
+# Whether the C strings in rdi and rsi are the same, in eax.
+rt_cstreq:
+        mov al, [rdi]
+        cmp al, [rsi]
+        jne .Lcstreq_no
+        test al, al
+        jz .Lcstreq_yes
+        inc rdi
+        inc rsi
+        jmp rt_cstreq
+.Lcstreq_yes:
+        mov eax, 1
+        ret
+.Lcstreq_no:
+        xor eax, eax
+        ret
+
+# Compares the strings in rdi and rsi byte by byte, like strcmp: eax is
+# -1, 0 or 1.
+rt_strcmp:
+        mov rcx, [rdi]
+        mov rdx, [rsi]
+        add rdi, 8
+        add rsi, 8
+        xor r8d, r8d
+.Lstrcmp_loop:
+        cmp r8, rcx
+        je .Lstrcmp_end
+        cmp r8, rdx
+        je .Lstrcmp_greater
+        movzx eax, byte ptr [rdi + r8]
+        movzx r9d, byte ptr [rsi + r8]
+        cmp eax, r9d
+        jb .Lstrcmp_less
+        ja .Lstrcmp_greater
+        inc r8
+        jmp .Lstrcmp_loop
+.Lstrcmp_end:
+        cmp r8, rdx
+        je .Lstrcmp_equal
+.Lstrcmp_less:
+        mov eax, -1
+        ret
+.Lstrcmp_greater:
+        mov eax, 1
+        ret
+.Lstrcmp_equal:
+        xor eax, eax
+        ret
+
+# The digits of the int in edi: rsi points to them, rdx is how many.
+rt_itoa:
+        movsxd rax, edi
+        lea rsi, [rip + digits + 16]
+        xor ecx, ecx
+        test rax, rax
+        jns .Litoa_loop
+        neg rax
+        mov ecx, 1
+.Litoa_loop:
+        mov r8, 10
+        xor edx, edx
+        div r8
+        add dl, '0'
+        dec rsi
+        mov [rsi], dl
+        test rax, rax
+        jnz .Litoa_loop
+        test ecx, ecx
+        jz .Litoa_done
+        dec rsi
+        mov byte ptr [rsi], '-'
+.Litoa_done:
+        lea rdx, [rip + digits + 16]
+        sub rdx, rsi
+        ret
+
+# The bytes of a string or an int (rdi kind, rsi payload): rsi points to
+# them, rdx is how many.
+rt_text:
+        cmp rdi, KIND_INT
+        jne .Ltext_str
+        mov edi, esi
+        jmp rt_itoa
+.Ltext_str:
+        mov rdx, [rsi]
+        add rsi, 8
+        ret
+
+# rdi bytes from the heap, in rax. Nothing is ever freed, a heap that's
+# full is an error at the last location that ran.
+rt_alloc:
+        add rdi, 15
+        and rdi, -16
+        mov rax, [rip + heap]
+        add rdi, rax
+        cmp rdi, [rip + heap_end]
+        ja .Lalloc_full
+        mov [rip + heap], rdi
+        ret
+.Lalloc_full:
+        mov rdi, [rip + location]
+        lea rsi, [rip + fault_out_of_memory]
+        lea rdx, [rip + text_heap_full]
+        call rt_fail_msg
+
+# A new string of rdx bytes from rsi, in rax.
+rt_string:
+        push rsi
+        push rdx
+        lea rdi, [rdx + 8]
+        call rt_alloc
+        pop rcx
+        pop rsi
+        mov [rax], rcx
+        lea rdi, [rax + 8]
+        rep movsb
+        ret
+
+# The name of the kind in rdi, in rax.
+rt_kind_name:
+        lea rax, [rip + kind_str]
+        cmp rdi, KIND_STR
+        je .Lkind_done
+        lea rax, [rip + kind_int]
+        cmp rdi, KIND_INT
+        je .Lkind_done
+        lea rax, [rip + kind_bool]
+        cmp rdi, KIND_BOOL
+        je .Lkind_done
+        lea rax, [rip + kind_tuple]
+        cmp rdi, KIND_TUPLE
+        je .Lkind_done
+        lea rax, [rip + kind_record]
+        cmp rdi, KIND_RECORD
+        je .Lkind_done
+        lea rax, [rip + kind_list]
+        cmp rdi, KIND_LIST
+        je .Lkind_done
+        lea rax, [rip + kind_function]
+        cmp rdi, KIND_FUNCTION
+        je .Lkind_done
+        lea rax, [rip + kind_unknown]
+.Lkind_done:
+        ret
+
+# Prints a value (rdi kind, rsi payload) the way `pi` does.
+rt_show:
+        push rbx
+        push r12
+        push r13
+        mov rbx, rdi
+        mov r12, rsi
+
+        cmp rbx, KIND_INT
+        jne .Lshow_str
+        mov edi, r12d
+        call rt_itoa
+        call rt_out
+        jmp .Lshow_done
+
+.Lshow_str:
+        cmp rbx, KIND_STR
+        jne .Lshow_bool
+        lea rsi, [r12 + 8]
+        mov rdx, [r12]
+        call rt_out
+        jmp .Lshow_done
+
+.Lshow_bool:
+        cmp rbx, KIND_BOOL
+        jne .Lshow_tuple
+        lea rax, [rip + str_true]
+        lea rcx, [rip + str_false]
+        test r12, r12
+        cmovz rax, rcx
+        lea rsi, [rax + 8]
+        mov rdx, [rax]
+        call rt_out
+        jmp .Lshow_done
+
+.Lshow_tuple:
+        cmp rbx, KIND_TUPLE
+        jne .Lshow_list
+        lea rdi, [rip + text_open_paren]
+        call rt_out_cstr
+        mov rdi, [r12]
+        mov rsi, [r12 + 8]
+        call rt_show
+        lea rdi, [rip + text_comma]
+        call rt_out_cstr
+        mov rdi, [r12 + 16]
+        mov rsi, [r12 + 24]
+        call rt_show
+        lea rdi, [rip + text_close_paren]
+        call rt_out_cstr
+        jmp .Lshow_done
+
+.Lshow_list:
+        cmp rbx, KIND_LIST
+        jne .Lshow_record
+        lea rdi, [rip + text_open_bracket]
+        call rt_out_cstr
+.Lshow_list_loop:
+        test r12, r12
+        jz .Lshow_list_done
+        mov rdi, [r12]
+        mov rsi, [r12 + 8]
+        call rt_show
+        mov r12, [r12 + 16]
+        test r12, r12
+        jz .Lshow_list_done
+        lea rdi, [rip + text_comma]
+        call rt_out_cstr
+        jmp .Lshow_list_loop
+.Lshow_list_done:
+        lea rdi, [rip + text_close_bracket]
+        call rt_out_cstr
+        jmp .Lshow_done
+
+.Lshow_record:
+        cmp rbx, KIND_RECORD
+        jne .Lshow_function
+        lea rdi, [rip + text_open_brace]
+        call rt_out_cstr
+        xor r13d, r13d
+.Lshow_record_loop:
+        cmp r13, [r12]
+        jae .Lshow_record_done
+        test r13, r13
+        jz .Lshow_record_field
+        lea rdi, [rip + text_comma]
+        call rt_out_cstr
+.Lshow_record_field:
+        mov rax, [r12 + 8]
+        mov rdi, [rax + r13 * 8]
+        call rt_out_cstr
+        lea rdi, [rip + text_colon]
+        call rt_out_cstr
+        mov rax, r13
+        shl rax, 4
+        mov rdi, [r12 + rax + 16]
+        mov rsi, [r12 + rax + 24]
+        call rt_show
+        inc r13
+        jmp .Lshow_record_loop
+.Lshow_record_done:
+        lea rdi, [rip + text_close_brace]
+        call rt_out_cstr
+        jmp .Lshow_done
+
+.Lshow_function:
+        cmp rbx, KIND_FUNCTION
+        jne .Lshow_done
+        lea rdi, [rip + text_closure]
+        call rt_out_cstr
+
+.Lshow_done:
+        pop r13
+        pop r12
+        pop rbx
+        ret
+
+# Prints the value on the top, it stays there.
+rt_print:
+        mov rdi, [r15 - 16]
+        mov rsi, [r15 - 8]
+        call rt_show
+        lea rdi, [rip + text_newline]
+        jmp rt_out_cstr
+
+# Runtime errors, `ToukaRT: error[<fault>] at <location>: <message>` on
+# the standard error. The message is written in pieces, between
+# rt_error_begin (rdi fault, rsi location) and rt_error_end.
+rt_error_begin:
+        push rbx
+        push r12
+        mov rbx, rdi
+        mov r12, rsi
+        call rt_flush
+        lea rdi, [rip + text_error]
+        call rt_error_str
+        mov rdi, rbx
+        call rt_error_str
+        lea rdi, [rip + text_at]
+        call rt_error_str
+        mov rdi, r12
+        call rt_error_str
+        lea rdi, [rip + text_colon]
+        call rt_error_str
+        pop r12
+        pop rbx
+        ret
+
+rt_error_str:
+        call rt_cstrlen
+        mov rsi, rdi
+        mov rdx, rax
+        mov edi, 2
+        jmp rt_write_fd
+
+rt_error_kind:
+        call rt_kind_name
+        mov rdi, rax
+        jmp rt_error_str
+
+rt_error_int:
+        call rt_itoa
+        mov edi, 2
+        jmp rt_write_fd
+
+rt_error_end:
+        lea rdi, [rip + text_newline]
+        call rt_error_str
+        mov edi, 1
+        mov eax, SYS_EXIT
+        syscall
+
+# rdi location, rsi fault, rdx message.
+rt_fail_msg:
+        push rdx
+        push rdx
+        mov rax, rdi
+        mov rdi, rsi
+        mov rsi, rax
+        call rt_error_begin
+        pop rdi
+        call rt_error_str
+        call rt_error_end
+
+# rdi location, rsi fault, rdx the message before the kind, rcx kind.
+rt_fail_kind:
+        push rcx
+        push rdx
+        mov rax, rdi
+        mov rdi, rsi
+        mov rsi, rax
+        call rt_error_begin
+        pop rdi
+        call rt_error_str
+        pop rdi
+        call rt_error_kind
+        call rt_error_end
+
+# rdi location, rsi fault, rdx the message before the kinds, rcx and r8
+# the kinds.
+rt_fail_kinds:
+        push r8
+        push rcx
+        push rdx
+        mov rax, rdi
+        mov rdi, rsi
+        mov rsi, rax
+        call rt_error_begin
+        pop rdi
+        call rt_error_str
+        pop rdi
+        call rt_error_kind
+        lea rdi, [rip + text_and]
+        call rt_error_str
+        pop rdi
+        call rt_error_kind
+        call rt_error_end
+
+# rdi location, rsi expected kind, rdx found kind.
+rt_fail_expect:
+        push rdx
+        push rsi
+        mov rsi, rdi
+        lea rdi, [rip + fault_type_mismatch]
+        call rt_error_begin
+        lea rdi, [rip + text_expected]
+        call rt_error_str
+        pop rdi
+        call rt_error_kind
+        lea rdi, [rip + text_found]
+        call rt_error_str
+        pop rdi
+        call rt_error_kind
+        call rt_error_end
+
+# rdi location, rsi expected arity, rdx found.
+rt_fail_arity:
+        push rdx
+        push rsi
+        mov rsi, rdi
+        lea rdi, [rip + fault_arity_mismatch]
+        call rt_error_begin
+        lea rdi, [rip + text_expected]
+        call rt_error_str
+        pop rdi
+        call rt_error_int
+        lea rdi, [rip + text_arguments]
+        call rt_error_str
+        pop rdi
+        call rt_error_int
+        call rt_error_end
+
+# Sum of the two values on the top (rdi location): ints, or strings
+# joined with strings or ints.
+rt_add:
+        push rbx
+        push r12
+        push r13
+        push r14
+        mov r13, rdi
+        mov rax, [r15 - 32]
+        mov rcx, [r15 - 16]
+        cmp rax, KIND_INT
+        jne .Ladd_text
+        cmp rcx, KIND_INT
+        jne .Ladd_text
+        mov eax, [r15 - 24]
+        add eax, [r15 - 8]
+        sub r15, 16
+        mov [r15 - 8], rax
+        jmp .Ladd_done
+
+.Ladd_text:
+        cmp rax, KIND_STR
+        je .Ladd_lhs
+        cmp rax, KIND_INT
+        jne .Ladd_fail
+.Ladd_lhs:
+        cmp rcx, KIND_STR
+        je .Ladd_join
+        cmp rcx, KIND_INT
+        jne .Ladd_fail
+.Ladd_join:
+        mov rdi, [r15 - 32]
+        mov rsi, [r15 - 24]
+        call rt_text
+        mov rbx, rsi
+        mov r12, rdx
+        mov rdi, [r15 - 16]
+        mov rsi, [r15 - 8]
+        call rt_text
+        mov r14, rsi
+        push rdx
+        push rdx
+        lea rdi, [r12 + rdx + 8]
+        call rt_alloc
+        pop rdx
+        pop rdx
+        lea rcx, [r12 + rdx]
+        mov [rax], rcx
+        mov r13, rax
+        lea rdi, [rax + 8]
+        mov rsi, rbx
+        mov rcx, r12
+        rep movsb
+        mov rsi, r14
+        mov rcx, rdx
+        rep movsb
+        sub r15, 16
+        mov qword ptr [r15 - 16], KIND_STR
+        mov [r15 - 8], r13
+        jmp .Ladd_done
+
+.Ladd_fail:
+        mov rdi, r13
+        lea rsi, [rip + fault_invalid_operands]
+        lea rdx, [rip + text_cannot_add]
+        mov rcx, [r15 - 32]
+        mov r8, [r15 - 16]
+        call rt_fail_kinds
+
+.Ladd_done:
+        pop r14
+        pop r13
+        pop r12
+        pop rbx
+        ret
+
+# Compares the two values on the top (rdi location, rsi operation: Eq,
+# Neq, Lt, Gt, Lte, Gte from 0).
+rt_compare:
+        push rbx
+        push r12
+        push r13
+        mov r12, rdi
+        mov r13, rsi
+        mov rax, [r15 - 32]
+        cmp rax, [r15 - 16]
+        jne .Lcompare_fail
+
+        cmp rax, KIND_INT
+        jne .Lcompare_str
+        mov eax, [r15 - 24]
+        xor ebx, ebx
+        cmp eax, [r15 - 8]
+        je .Lcompare_ordering
+        mov ebx, 1
+        jg .Lcompare_ordering
+        mov ebx, -1
+        jmp .Lcompare_ordering
+
+.Lcompare_str:
+        cmp rax, KIND_STR
+        jne .Lcompare_bool
+        mov rdi, [r15 - 24]
+        mov rsi, [r15 - 8]
+        call rt_strcmp
+        mov ebx, eax
+        jmp .Lcompare_ordering
+
+.Lcompare_bool:
+        cmp rax, KIND_BOOL
+        jne .Lcompare_fail
+        cmp r13, 1
+        ja .Lcompare_booleans
+        mov rax, [r15 - 24]
+        xor ebx, ebx
+        cmp rax, [r15 - 8]
+        setne bl
+
+.Lcompare_ordering:
+        xor eax, eax
+        cmp r13, 0
+        je .Lcompare_eq
+        cmp r13, 1
+        je .Lcompare_neq
+        cmp r13, 2
+        je .Lcompare_lt
+        cmp r13, 3
+        je .Lcompare_gt
+        cmp r13, 4
+        je .Lcompare_lte
+        test ebx, ebx
+        setge al
+        jmp .Lcompare_done
+.Lcompare_eq:
+        test ebx, ebx
+        sete al
+        jmp .Lcompare_done
+.Lcompare_neq:
+        test ebx, ebx
+        setne al
+        jmp .Lcompare_done
+.Lcompare_lt:
+        test ebx, ebx
+        setl al
+        jmp .Lcompare_done
+.Lcompare_gt:
+        test ebx, ebx
+        setg al
+        jmp .Lcompare_done
+.Lcompare_lte:
+        test ebx, ebx
+        setle al
+
+.Lcompare_done:
+        sub r15, 16
+        mov qword ptr [r15 - 16], KIND_BOOL
+        mov [r15 - 8], rax
+        pop r13
+        pop r12
+        pop rbx
+        ret
+
+.Lcompare_fail:
+        mov rdi, r12
+        lea rsi, [rip + fault_invalid_operands]
+        lea rdx, [rip + text_cannot_compare]
+        mov rcx, [r15 - 32]
+        mov r8, [r15 - 16]
+        call rt_fail_kinds
+
+.Lcompare_booleans:
+        mov rdi, r12
+        lea rsi, [rip + fault_invalid_operands]
+        lea rdx, [rip + text_compare_booleans]
+        call rt_fail_msg
+
+rt_tuple:
+        mov edi, 32
+        call rt_alloc
+        mov rcx, [r15 - 32]
+        mov [rax], rcx
+        mov rcx, [r15 - 24]
+        mov [rax + 8], rcx
+        mov rcx, [r15 - 16]
+        mov [rax + 16], rcx
+        mov rcx, [r15 - 8]
+        mov [rax + 24], rcx
+        sub r15, 16
+        mov qword ptr [r15 - 16], KIND_TUPLE
+        mov [r15 - 8], rax
+        ret
+
+# Checks that the top is a tuple (rdi location), its address in rax.
+rt_expect_tuple:
+        cmp qword ptr [r15 - 16], KIND_TUPLE
+        jne .Lexpect_tuple_fail
+        mov rax, [r15 - 8]
+        ret
+.Lexpect_tuple_fail:
+        lea rsi, [rip + fault_not_a_tuple]
+        lea rdx, [rip + text_expected_tuple]
+        mov rcx, [r15 - 16]
+        call rt_fail_kind
+
+rt_first:
+        call rt_expect_tuple
+        mov rcx, [rax]
+        mov [r15 - 16], rcx
+        mov rcx, [rax + 8]
+        mov [r15 - 8], rcx
+        ret
+
+rt_second:
+        call rt_expect_tuple
+        mov rcx, [rax + 16]
+        mov [r15 - 16], rcx
+        mov rcx, [rax + 24]
+        mov [r15 - 8], rcx
+        ret
+
+rt_untuple:
+        call rt_expect_tuple
+        mov rcx, [rax]
+        mov [r15 - 16], rcx
+        mov rcx, [rax + 8]
+        mov [r15 - 8], rcx
+        mov rcx, [rax + 16]
+        mov [r15], rcx
+        mov rcx, [rax + 24]
+        mov [r15 + 8], rcx
+        add r15, 16
+        ret
+
+# A record of the rdi values on the top, rsi are the names.
+rt_record:
+        push rbx
+        push r12
+        mov rbx, rdi
+        mov r12, rsi
+        shl rdi, 4
+        add rdi, 16
+        call rt_alloc
+        mov [rax], rbx
+        mov [rax + 8], r12
+        mov rcx, rbx
+        shl rcx, 4
+        sub r15, rcx
+        lea rdi, [rax + 16]
+        mov rsi, r15
+        rep movsb
+        mov qword ptr [r15], KIND_RECORD
+        mov [r15 + 8], rax
+        add r15, 16
+        pop r12
+        pop rbx
+        ret
+
+# The field named rsi of the record on the top (rdi location).
+rt_field:
+        push rbx
+        push r12
+        push r13
+        push r14
+        mov r12, rdi
+        mov r13, rsi
+        cmp qword ptr [r15 - 16], KIND_RECORD
+        jne .Lfield_not_record
+        mov rbx, [r15 - 8]
+        xor r14d, r14d
+.Lfield_loop:
+        cmp r14, [rbx]
+        jae .Lfield_missing
+        mov rax, [rbx + 8]
+        mov rdi, [rax + r14 * 8]
+        mov rsi, r13
+        call rt_cstreq
+        test eax, eax
+        jnz .Lfield_found
+        inc r14
+        jmp .Lfield_loop
+.Lfield_found:
+        shl r14, 4
+        mov rcx, [rbx + r14 + 16]
+        mov [r15 - 16], rcx
+        mov rcx, [rbx + r14 + 24]
+        mov [r15 - 8], rcx
+        pop r14
+        pop r13
+        pop r12
+        pop rbx
+        ret
+
+.Lfield_not_record:
+        mov rdi, r12
+        lea rsi, [rip + fault_not_a_record]
+        lea rdx, [rip + text_expected_record]
+        mov rcx, [r15 - 16]
+        call rt_fail_kind
+
+.Lfield_missing:
+        mov rsi, r12
+        lea rdi, [rip + fault_no_such_field]
+        call rt_error_begin
+        lea rdi, [rip + text_no_field]
+        call rt_error_str
+        mov rdi, r13
+        call rt_error_str
+        lea rdi, [rip + text_backtick]
+        call rt_error_str
+        call rt_error_end
+
+# A cell with the value in rdi, rsi, in front of the list in rdx, in rax.
+rt_cell:
+        push rdi
+        push rsi
+        push rdx
+        push rdx
+        mov edi, 32
+        call rt_alloc
+        pop rdx
+        pop rdx
+        pop rsi
+        pop rdi
+        mov [rax], rdi
+        mov [rax + 8], rsi
+        mov [rax + 16], rdx
+        mov ecx, 1
+        test rdx, rdx
+        jz .Lcell_done
+        mov rcx, [rdx + 24]
+        inc rcx
+.Lcell_done:
+        mov [rax + 24], rcx
+        ret
+
+# A list of the rdi values on the top, the last one goes in first.
+rt_list:
+        push rbx
+        push r12
+        mov rbx, rdi
+        xor r12d, r12d
+.Llist_loop:
+        test rbx, rbx
+        jz .Llist_done
+        sub r15, 16
+        mov rdi, [r15]
+        mov rsi, [r15 + 8]
+        mov rdx, r12
+        call rt_cell
+        mov r12, rax
+        dec rbx
+        jmp .Llist_loop
+.Llist_done:
+        mov qword ptr [r15], KIND_LIST
+        mov [r15 + 8], r12
+        add r15, 16
+        pop r12
+        pop rbx
+        ret
+
+# Checks that the top is a list (rdi location), its first cell in rax.
+rt_expect_list:
+        cmp qword ptr [r15 - 16], KIND_LIST
+        jne .Lexpect_list_fail
+        mov rax, [r15 - 8]
+        ret
+.Lexpect_list_fail:
+        lea rsi, [rip + fault_not_a_list]
+        lea rdx, [rip + text_expected_list]
+        mov rcx, [r15 - 16]
+        call rt_fail_kind
+
+rt_cons:
+        call rt_expect_list
+        mov rdi, [r15 - 32]
+        mov rsi, [r15 - 24]
+        mov rdx, rax
+        call rt_cell
+        sub r15, 16
+        mov qword ptr [r15 - 16], KIND_LIST
+        mov [r15 - 8], rax
+        ret
+
+# Checks that the top is a list with something in it (rdi location),
+# its first cell in rax.
+rt_expect_cell:
+        call rt_expect_list
+        test rax, rax
+        jz .Lexpect_cell_fail
+        ret
+.Lexpect_cell_fail:
+        lea rsi, [rip + fault_empty_list]
+        lea rdx, [rip + text_empty_list]
+        call rt_fail_msg
+
+rt_head:
+        call rt_expect_cell
+        mov rcx, [rax]
+        mov [r15 - 16], rcx
+        mov rcx, [rax + 8]
+        mov [r15 - 8], rcx
+        ret
+
+rt_tail:
+        call rt_expect_cell
+        mov rcx, [rax + 16]
+        mov [r15 - 8], rcx
+        ret
+
+rt_len:
+        mov rax, [r15 - 8]
+        cmp qword ptr [r15 - 16], KIND_STR
+        jne .Llen_list
+        mov rax, [rax]
+        jmp .Llen_done
+.Llen_list:
+        cmp qword ptr [r15 - 16], KIND_LIST
+        jne .Llen_fail
+        test rax, rax
+        jz .Llen_done
+        mov rax, [rax + 24]
+.Llen_done:
+        mov qword ptr [r15 - 16], KIND_INT
+        mov [r15 - 8], eax
+        mov dword ptr [r15 - 4], 0
+        ret
+.Llen_fail:
+        lea rsi, [rip + fault_invalid_argument]
+        lea rdx, [rip + text_expected_sized]
+        mov rcx, [r15 - 16]
+        call rt_fail_kind
+
+# The string, int start and int length on the top (rdi location), they
+# are already checked.
+rt_substr:
+        push rbx
+        push r12
+        push r13
+        mov r12, [r15 - 40]
+        movsxd rbx, dword ptr [r15 - 24]
+        movsxd r13, dword ptr [r15 - 8]
+        mov rax, [r12]
+        test rbx, rbx
+        js .Lsubstr_fail
+        test r13, r13
+        js .Lsubstr_fail
+        cmp rbx, rax
+        jg .Lsubstr_fail
+        sub rax, rbx
+        cmp r13, rax
+        jg .Lsubstr_fail
+        lea rsi, [r12 + rbx + 8]
+        mov rdx, r13
+        call rt_string
+        sub r15, 32
+        mov [r15 - 8], rax
+        pop r13
+        pop r12
+        pop rbx
+        ret
+.Lsubstr_fail:
+        mov rsi, rdi
+        lea rdi, [rip + fault_out_of_bounds]
+        call rt_error_begin
+        lea rdi, [rip + text_cannot_take]
+        call rt_error_str
+        mov edi, r13d
+        call rt_error_int
+        lea rdi, [rip + text_characters_from]
+        call rt_error_str
+        mov edi, ebx
+        call rt_error_int
+        lea rdi, [rip + text_of_length]
+        call rt_error_str
+        mov rdi, [r12]
+        call rt_error_int
+        call rt_error_end
+
+# The number in the string on the top (rdi location), the same ones
+# `strtol` reads.
+rt_to_int:
+        mov rsi, [r15 - 8]
+        mov rcx, [rsi]
+        add rsi, 8
+        xor r8d, r8d
+.Lto_int_space:
+        cmp r8, rcx
+        je .Lto_int_fail
+        movzx eax, byte ptr [rsi + r8]
+        cmp eax, ' '
+        je .Lto_int_skip
+        sub eax, 9
+        cmp eax, 4
+        ja .Lto_int_sign
+.Lto_int_skip:
+        inc r8
+        jmp .Lto_int_space
+.Lto_int_sign:
+        xor r9d, r9d
+        movzx eax, byte ptr [rsi + r8]
+        cmp eax, '+'
+        je .Lto_int_signed
+        cmp eax, '-'
+        jne .Lto_int_digits
+        mov r9d, 1
+.Lto_int_signed:
+        inc r8
+.Lto_int_digits:
+        cmp r8, rcx
+        je .Lto_int_fail
+        xor eax, eax
+        mov r10, 0x80000000
+.Lto_int_loop:
+        cmp r8, rcx
+        je .Lto_int_done
+        movzx edx, byte ptr [rsi + r8]
+        sub edx, '0'
+        cmp edx, 9
+        ja .Lto_int_fail
+        imul rax, rax, 10
+        add rax, rdx
+        cmp rax, r10
+        ja .Lto_int_fail
+        inc r8
+        jmp .Lto_int_loop
+.Lto_int_done:
+        test r9d, r9d
+        jz .Lto_int_positive
+        neg rax
+        jmp .Lto_int_store
+.Lto_int_positive:
+        cmp rax, r10
+        je .Lto_int_fail
+.Lto_int_store:
+        mov qword ptr [r15 - 16], KIND_INT
+        mov [r15 - 8], eax
+        mov dword ptr [r15 - 4], 0
+        ret
+.Lto_int_fail:
+        mov rsi, rdi
+        lea rdi, [rip + fault_invalid_argument]
+        call rt_error_begin
+        lea rdi, [rip + text_backtick]
+        call rt_error_str
+        mov rsi, [r15 - 8]
+        mov rdx, [rsi]
+        add rsi, 8
+        mov edi, 2
+        call rt_write_fd
+        lea rdi, [rip + text_not_a_number]
+        call rt_error_str
+        call rt_error_end
+
+rt_to_str:
+        mov rax, [r15 - 16]
+        cmp rax, KIND_STR
+        je .Lto_str_done
+        cmp rax, KIND_INT
+        jne .Lto_str_bool
+        mov edi, [r15 - 8]
+        call rt_itoa
+        call rt_string
+        jmp .Lto_str_store
+.Lto_str_bool:
+        cmp rax, KIND_BOOL
+        jne .Lto_str_fail
+        lea rax, [rip + str_true]
+        lea rcx, [rip + str_false]
+        cmp qword ptr [r15 - 8], 0
+        cmove rax, rcx
+.Lto_str_store:
+        mov qword ptr [r15 - 16], KIND_STR
+        mov [r15 - 8], rax
+.Lto_str_done:
+        ret
+.Lto_str_fail:
+        mov rsi, rdi
+        lea rdi, [rip + fault_invalid_argument]
+        call rt_error_begin
+        lea rdi, [rip + text_cannot_convert]
+        call rt_error_str
+        mov rdi, [r15 - 16]
+        call rt_error_kind
+        lea rdi, [rip + text_to_string]
+        call rt_error_str
+        call rt_error_end
+
+# Whether the string on the top is in the one under it, they are
+# already checked.
+rt_contains:
+        mov rsi, [r15 - 24]
+        mov rdi, [r15 - 8]
+        mov rcx, [rsi]
+        mov rdx, [rdi]
+        xor eax, eax
+        xor r8d, r8d
+.Lcontains_start:
+        mov r9, rcx
+        sub r9, r8
+        cmp r9, rdx
+        jl .Lcontains_done
+        xor r9d, r9d
+.Lcontains_loop:
+        cmp r9, rdx
+        je .Lcontains_found
+        lea r10, [r8 + r9]
+        mov r11b, [rsi + r10 + 8]
+        cmp r11b, [rdi + r9 + 8]
+        jne .Lcontains_next
+        inc r9
+        jmp .Lcontains_loop
+.Lcontains_next:
+        inc r8
+        jmp .Lcontains_start
+.Lcontains_found:
+        mov eax, 1
+.Lcontains_done:
+        sub r15, 16
+        mov qword ptr [r15 - 16], KIND_BOOL
+        mov [r15 - 8], rax
+        ret
+
+# The next byte of the standard input in eax, -1 at the end.
+rt_getc:
+        mov rax, [rip + in_position]
+        cmp rax, [rip + in_size]
+        jb .Lgetc_buffered
+        push rdi
+        push rsi
+        xor edi, edi
+        lea rsi, [rip + in_buffer]
+        mov edx, IN_SIZE
+        mov eax, SYS_READ
+        syscall
+        pop rsi
+        pop rdi
+        test rax, rax
+        jle .Lgetc_end
+        mov [rip + in_size], rax
+        xor eax, eax
+.Lgetc_buffered:
+        lea rdx, [rip + in_buffer]
+        movzx edx, byte ptr [rdx + rax]
+        inc rax
+        mov [rip + in_position], rax
+        mov eax, edx
+        ret
+.Lgetc_end:
+        mov qword ptr [rip + in_size], 0
+        mov qword ptr [rip + in_position], 0
+        mov eax, -1
+        ret
+
+# A line of the standard input without the line break (rdi location),
+# it's written right on the heap.
+rt_read_line:
+        push rbx
+        push r12
+        push r13
+        mov r13, rdi
+        mov rbx, [rip + heap]
+        lea r12, [rbx + 8]
+.Lread_line_loop:
+        call rt_getc
+        cmp eax, -1
+        je .Lread_line_end
+        cmp eax, '\n'
+        je .Lread_line_done
+        mov [r12], al
+        inc r12
+        jmp .Lread_line_loop
+.Lread_line_end:
+        lea rax, [rbx + 8]
+        cmp r12, rax
+        je .Lread_line_fail
+.Lread_line_done:
+        lea rax, [rbx + 8]
+        cmp r12, rax
+        je .Lread_line_store
+        cmp byte ptr [r12 - 1], '\r'
+        jne .Lread_line_store
+        dec r12
+.Lread_line_store:
+        mov rax, r12
+        sub rax, rbx
+        sub rax, 8
+        mov [rbx], rax
+        lea rax, [r12 + 15]
+        and rax, -16
+        mov [rip + heap], rax
+        mov qword ptr [r15], KIND_STR
+        mov [r15 + 8], rbx
+        add r15, 16
+        pop r13
+        pop r12
+        pop rbx
+        ret
+.Lread_line_fail:
+        mov rdi, r13
+        lea rsi, [rip + fault_end_of_input]
+        lea rdx, [rip + text_no_more_lines]
+        call rt_fail_msg
+
+# The command-line arguments as a list of strings.
+rt_argv:
+        push rbx
+        push r12
+        mov rbx, [rip + args_count]
+        xor r12d, r12d
+.Largv_loop:
+        test rbx, rbx
+        jz .Largv_done
+        dec rbx
+        mov rax, [rip + args]
+        mov rdi, [rax + rbx * 8]
+        call rt_cstrlen
+        mov rsi, rdi
+        mov rdx, rax
+        call rt_string
+        mov edi, KIND_STR
+        mov rsi, rax
+        mov rdx, r12
+        call rt_cell
+        mov r12, rax
+        jmp .Largv_loop
+.Largv_done:
+        mov qword ptr [r15], KIND_LIST
+        mov [r15 + 8], r12
+        add r15, 16
+        pop r12
+        pop rbx
+        ret
+
+# A fresh area of rdi bytes in rax, the pages are only taken when
+# they're touched.
+rt_area:
+        mov rsi, rdi
+        mov eax, SYS_MMAP
+        xor edi, edi
+        mov edx, 3
+        mov r10d, 0x4022
+        mov r8, -1
+        xor r9d, r9d
+        syscall
+        cmp rax, -4095
+        jae .Larea_failed
+        ret
+.Larea_failed:
+        mov rdi, [rip + location]
+        lea rsi, [rip + fault_out_of_memory]
+        lea rdx, [rip + text_no_area]
+        call rt_fail_msg
+
+        .globl _start
+_start:
+        mov rax, [rsp]
+        dec rax
+        mov [rip + args_count], rax
+        lea rax, [rsp + 16]
+        mov [rip + args], rax
+        lea rax, [rip + text_start]
+        mov [rip + location], rax
+        mov edi, AREA_SIZE
+        call rt_area
+        mov [rip + heap], rax
+        add rax, AREA_SIZE
+        mov [rip + heap_end], rax
+        mov edi, AREA_SIZE
+        call rt_area
+        mov r15, rax
+
+        # The program runs on a stack of its own, like the one of the C
+        # runtime. A call past its end, less a margin for the runtime, is
+        # an error instead of a crash.
+        mov edi, STACK_SIZE
+        call rt_area
+        lea rsp, [rax + STACK_SIZE]
+        add rax, STACK_MARGIN
+        mov [rip + stack_end], rax
+
+        # The program is called like any other function.
+        add r15, 16
+        xor r14d, r14d
+        call touka_main
+        call rt_flush
+        mov eax, SYS_EXIT
+        xor edi, edi
+        syscall
@@ -0,0 +1,817 @@
+  ;; The runtime of the WebAssembly backend. The host gives the memory and
+  ;; the output: `print` gets each printed value, `error` the message of a
+  ;; runtime error, and neither gets the line break. It also gives the
+  ;; input: `read` is a read of the standard input, and `args_sizes` and
+  ;; `args` are WASI's `args_sizes_get` and `args_get`. The first 32 bytes
+  ;; of the memory are the host's, for its own calls.
+  ;;
+  ;; Every value is 8 bytes, the kind and then the payload, with the kinds
+  ;; of the C runtime. The values being computed live on a stack of their
+  ;; own, `$sp` points past its top. The texts the runtime needs are laid
+  ;; out by the generator, the `$text_`, `$fault_` and `$kind_` globals.
+
+  (type $code (func (param i32)))
+
+  (global $line (mut i32) (i32.const 0))
+  (global $line_len (mut i32) (i32.const 0))
+
+  ;; The calls in progress. A call deeper than `$max_depth` is an error
+  ;; instead of a trap of the host, the hosts give about as many frames.
+  (global $depth (mut i32) (i32.const 0))
+  (global $max_depth i32 (i32.const 4096))
+
+  ;; Grows the memory up to `end`.
+  (func $reserve (param $end i32)
+    (local $pages i32)
+    (local.set $pages
+      (i32.sub
+        (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+        (memory.size)))
+    (if (i32.gt_s (local.get $pages) (i32.const 0))
+      (then
+        (if (i32.eq (memory.grow (local.get $pages)) (i32.const -1))
+          (then unreachable)))))
+
+  ;; `size` bytes from the heap, nothing is ever freed.
+  (func $alloc (param $size i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap
+      (i32.and
+        (i32.add (i32.add (local.get $ptr) (local.get $size)) (i32.const 7))
+        (i32.const -8)))
+    (call $reserve (global.get $heap))
+    (local.get $ptr))
+
+  (func $push (param $kind i32) (param $payload i32)
+    (i32.store (global.get $sp) (local.get $kind))
+    (i32.store offset=4 (global.get $sp) (local.get $payload))
+    (global.set $sp (i32.add (global.get $sp) (i32.const 8))))
+
+  (func $drop (param $count i32)
+    (global.set $sp (i32.sub (global.get $sp) (i32.mul (local.get $count) (i32.const 8)))))
+
+  ;; The value `depth` places from the top, 1 is the top.
+  (func $at (param $depth i32) (result i32)
+    (i32.sub (global.get $sp) (i32.mul (local.get $depth) (i32.const 8))))
+
+  (func $kind (param $depth i32) (result i32)
+    (i32.load (call $at (local.get $depth))))
+
+  (func $payload (param $depth i32) (result i32)
+    (i32.load offset=4 (call $at (local.get $depth))))
+
+  (func $set_top (param $kind i32) (param $payload i32)
+    (call $drop (i32.const 1))
+    (call $push (local.get $kind) (local.get $payload)))
+
+  ;; A new string of `len` bytes from `ptr`.
+  (func $string (param $ptr i32) (param $len i32) (result i32)
+    (local $s i32)
+    (local.set $s (call $alloc (i32.add (local.get $len) (i32.const 4))))
+    (i32.store (local.get $s) (local.get $len))
+    (memory.copy (i32.add (local.get $s) (i32.const 4)) (local.get $ptr) (local.get $len))
+    (local.get $s))
+
+  ;; Whether two strings are the same.
+  (func $str_eq (param $a i32) (param $b i32) (result i32)
+    (i32.eqz (call $strcmp (local.get $a) (local.get $b))))
+
+  ;; Compares two strings byte by byte, like strcmp: -1, 0 or 1.
+  (func $strcmp (param $a i32) (param $b i32) (result i32)
+    (local $i i32) (local $la i32) (local $lb i32) (local $x i32) (local $y i32)
+    (local.set $la (i32.load (local.get $a)))
+    (local.set $lb (i32.load (local.get $b)))
+    (block $done
+      (loop $bytes
+        (br_if $done (i32.eq (local.get $i) (local.get $la)))
+        (if (i32.eq (local.get $i) (local.get $lb))
+          (then (return (i32.const 1))))
+        (local.set $x (i32.load8_u offset=4 (i32.add (local.get $a) (local.get $i))))
+        (local.set $y (i32.load8_u offset=4 (i32.add (local.get $b) (local.get $i))))
+        (if (i32.lt_u (local.get $x) (local.get $y))
+          (then (return (i32.const -1))))
+        (if (i32.gt_u (local.get $x) (local.get $y))
+          (then (return (i32.const 1))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $bytes)))
+    (select (i32.const 0) (i32.const -1) (i32.eq (local.get $i) (local.get $lb))))
+
+  ;; The digits of the int as a string, in the scratch space at 32. It's
+  ;; gone with the next call.
+  (func $itoa (param $n i32) (result i32)
+    (local $v i64) (local $pos i32) (local $negative i32)
+    (local.set $v (i64.extend_i32_s (local.get $n)))
+    (if (i64.lt_s (local.get $v) (i64.const 0))
+      (then
+        (local.set $negative (i32.const 1))
+        (local.set $v (i64.sub (i64.const 0) (local.get $v)))))
+    (local.set $pos (i32.const 48))
+    (loop $digits
+      (local.set $pos (i32.sub (local.get $pos) (i32.const 1)))
+      (i32.store8 (local.get $pos)
+        (i32.add (i32.const 48) (i32.wrap_i64 (i64.rem_u (local.get $v) (i64.const 10)))))
+      (local.set $v (i64.div_u (local.get $v) (i64.const 10)))
+      (br_if $digits (i64.ne (local.get $v) (i64.const 0))))
+    (if (local.get $negative)
+      (then
+        (local.set $pos (i32.sub (local.get $pos) (i32.const 1)))
+        (i32.store8 (local.get $pos) (i32.const 45))))
+    (local.set $pos (i32.sub (local.get $pos) (i32.const 4)))
+    (i32.store (local.get $pos) (i32.sub (i32.const 44) (local.get $pos)))
+    (local.get $pos))
+
+  (func $kind_name (param $kind i32) (result i32)
+    (if (i32.eq (local.get $kind) (i32.const 0xca)) (then (return (global.get $kind_str))))
+    (if (i32.eq (local.get $kind) (i32.const 0xfe)) (then (return (global.get $kind_int))))
+    (if (i32.eq (local.get $kind) (i32.const 0xba)) (then (return (global.get $kind_bool))))
+    (if (i32.eq (local.get $kind) (i32.const 0x10)) (then (return (global.get $kind_tuple))))
+    (if (i32.eq (local.get $kind) (i32.const 0x11)) (then (return (global.get $kind_record))))
+    (if (i32.eq (local.get $kind) (i32.const 0x12)) (then (return (global.get $kind_list))))
+    (if (i32.eq (local.get $kind) (i32.const 0x42)) (then (return (global.get $kind_function))))
+    (global.get $kind_unknown))
+
+  ;; The line being written, for `print` and `error`. It's built right
+  ;; past the heap, which doesn't move while it's written.
+  (func $begin
+    (global.set $line (global.get $heap))
+    (global.set $line_len (i32.const 0)))
+
+  (func $emit (param $ptr i32) (param $len i32)
+    (local $end i32)
+    (local.set $end (i32.add (global.get $line) (global.get $line_len)))
+    (call $reserve (i32.add (local.get $end) (local.get $len)))
+    (memory.copy (local.get $end) (local.get $ptr) (local.get $len))
+    (global.set $line_len (i32.add (global.get $line_len) (local.get $len))))
+
+  (func $emit_str (param $s i32)
+    (call $emit (i32.add (local.get $s) (i32.const 4)) (i32.load (local.get $s))))
+
+  ;; Writes a value the way `pi` does.
+  (func $show (param $kind i32) (param $payload i32)
+    (local $i i32)
+    (block $done
+      (if (i32.eq (local.get $kind) (i32.const 0xfe))
+        (then
+          (call $emit_str (call $itoa (local.get $payload)))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0xca))
+        (then
+          (call $emit_str (local.get $payload))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0xba))
+        (then
+          (call $emit_str (select (global.get $text_true) (global.get $text_false) (local.get $payload)))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0x10))
+        (then
+          (call $emit_str (global.get $text_open_paren))
+          (call $show (i32.load (local.get $payload)) (i32.load offset=4 (local.get $payload)))
+          (call $emit_str (global.get $text_comma))
+          (call $show (i32.load offset=8 (local.get $payload)) (i32.load offset=12 (local.get $payload)))
+          (call $emit_str (global.get $text_close_paren))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0x12))
+        (then
+          (call $emit_str (global.get $text_open_bracket))
+          (block $end
+            (loop $cells
+              (br_if $end (i32.eqz (local.get $payload)))
+              (call $show (i32.load (local.get $payload)) (i32.load offset=4 (local.get $payload)))
+              (local.set $payload (i32.load offset=8 (local.get $payload)))
+              (br_if $end (i32.eqz (local.get $payload)))
+              (call $emit_str (global.get $text_comma))
+              (br $cells)))
+          (call $emit_str (global.get $text_close_bracket))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0x11))
+        (then
+          (call $emit_str (global.get $text_open_brace))
+          (block $end
+            (loop $fields
+              (br_if $end (i32.ge_u (local.get $i) (i32.load (local.get $payload))))
+              (if (local.get $i)
+                (then (call $emit_str (global.get $text_comma))))
+              (call $emit_str
+                (i32.load
+                  (i32.add (i32.load offset=4 (local.get $payload)) (i32.mul (local.get $i) (i32.const 4)))))
+              (call $emit_str (global.get $text_colon))
+              (call $show
+                (i32.load offset=8 (i32.add (local.get $payload) (i32.mul (local.get $i) (i32.const 8))))
+                (i32.load offset=12 (i32.add (local.get $payload) (i32.mul (local.get $i) (i32.const 8)))))
+              (local.set $i (i32.add (local.get $i) (i32.const 1)))
+              (br $fields)))
+          (call $emit_str (global.get $text_close_brace))
+          (br $done)))
+
+      (if (i32.eq (local.get $kind) (i32.const 0x42))
+        (then (call $emit_str (global.get $text_closure))))))
+
+  ;; Prints the value on the top, it stays there.
+  (func $rt_print
+    (call $begin)
+    (call $show (call $kind (i32.const 1)) (call $payload (i32.const 1)))
+    (call $print (global.get $line) (global.get $line_len)))
+
+  ;; Runtime errors, `ToukaRT: error[<fault>] at <location>: <message>`.
+  ;; The message is written in pieces, between $error_begin and $error_end.
+  (func $error_begin (param $fault i32) (param $at i32)
+    (call $begin)
+    (call $emit_str (global.get $text_error))
+    (call $emit_str (local.get $fault))
+    (call $emit_str (global.get $text_at))
+    (call $emit_str (local.get $at))
+    (call $emit_str (global.get $text_colon)))
+
+  (func $error_end
+    (call $error (global.get $line) (global.get $line_len))
+    unreachable)
+
+  (func $fail_msg (param $at i32) (param $fault i32) (param $message i32)
+    (call $error_begin (local.get $fault) (local.get $at))
+    (call $emit_str (local.get $message))
+    (call $error_end))
+
+  (func $fail_kind (param $at i32) (param $fault i32) (param $message i32) (param $kind i32)
+    (call $error_begin (local.get $fault) (local.get $at))
+    (call $emit_str (local.get $message))
+    (call $emit_str (call $kind_name (local.get $kind)))
+    (call $error_end))
+
+  (func $fail_kinds (param $at i32) (param $fault i32) (param $message i32) (param $a i32) (param $b i32)
+    (call $error_begin (local.get $fault) (local.get $at))
+    (call $emit_str (local.get $message))
+    (call $emit_str (call $kind_name (local.get $a)))
+    (call $emit_str (global.get $text_and))
+    (call $emit_str (call $kind_name (local.get $b)))
+    (call $error_end))
+
+  ;; Checks the kind of the top, for the annotations and the builtins.
+  (func $rt_expect (param $expected i32) (param $at i32)
+    (if (i32.ne (call $kind (i32.const 1)) (local.get $expected))
+      (then
+        (call $error_begin (global.get $fault_type_mismatch) (local.get $at))
+        (call $emit_str (global.get $text_expected))
+        (call $emit_str (call $kind_name (local.get $expected)))
+        (call $emit_str (global.get $text_found))
+        (call $emit_str (call $kind_name (call $kind (i32.const 1))))
+        (call $error_end))))
+
+  (func $rt_truthy (param $at i32)
+    (if (i32.ne (call $kind (i32.const 1)) (i32.const 0xba))
+      (then
+        (call $fail_kind
+          (local.get $at)
+          (global.get $fault_not_a_boolean)
+          (global.get $text_expected_bool)
+          (call $kind (i32.const 1))))))
+
+  ;; Pops a bool.
+  (func $rt_branch (param $at i32) (result i32)
+    (call $rt_truthy (local.get $at))
+    (call $drop (i32.const 1))
+    (i32.load offset=4 (global.get $sp)))
+
+  ;; Checks the function under the `arity` arguments, it's returned. The
+  ;; call is counted until `$rt_returned`.
+  (func $rt_callee (param $arity i32) (param $at i32) (result i32)
+    (local $closure i32)
+    (if (i32.ge_u (global.get $depth) (global.get $max_depth))
+      (then
+        (call $fail_msg (local.get $at) (global.get $fault_stack_overflow) (global.get $text_too_deep))))
+    (global.set $depth (i32.add (global.get $depth) (i32.const 1)))
+    (if (i32.ne (call $kind (i32.add (local.get $arity) (i32.const 1))) (i32.const 0x42))
+      (then
+        (call $fail_kind
+          (local.get $at)
+          (global.get $fault_not_a_function)
+          (global.get $text_expected_function)
+          (call $kind (i32.add (local.get $arity) (i32.const 1))))))
+    (local.set $closure (call $payload (i32.add (local.get $arity) (i32.const 1))))
+    (if (i32.ne (i32.load offset=4 (local.get $closure)) (local.get $arity))
+      (then
+        (call $error_begin (global.get $fault_arity_mismatch) (local.get $at))
+        (call $emit_str (global.get $text_expected))
+        (call $emit_str (call $itoa (i32.load offset=4 (local.get $closure))))
+        (call $emit_str (global.get $text_arguments))
+        (call $emit_str (call $itoa (local.get $arity)))
+        (call $error_end)))
+    (local.get $closure))
+
+  (func $rt_returned
+    (global.set $depth (i32.sub (global.get $depth) (i32.const 1))))
+
+  ;; A function of the code at `index` in the table, the captured values
+  ;; are popped.
+  (func $rt_closure (param $index i32) (param $arity i32) (param $captures i32)
+    (local $closure i32)
+    (local.set $closure (call $alloc (i32.add (i32.const 12) (i32.mul (local.get $captures) (i32.const 8)))))
+    (i32.store (local.get $closure) (local.get $index))
+    (i32.store offset=4 (local.get $closure) (local.get $arity))
+    (i32.store offset=8 (local.get $closure) (local.get $captures))
+    (call $drop (local.get $captures))
+    (memory.copy
+      (i32.add (local.get $closure) (i32.const 12))
+      (global.get $sp)
+      (i32.mul (local.get $captures) (i32.const 8)))
+    (call $push (i32.const 0x42) (local.get $closure)))
+
+  ;; Sum of the two values on the top: ints, or strings joined with strings
+  ;; or ints.
+  (func $rt_add (param $at i32)
+    (local $lk i32) (local $rk i32) (local $a i32) (local $b i32) (local $s i32)
+    (local.set $lk (call $kind (i32.const 2)))
+    (local.set $rk (call $kind (i32.const 1)))
+    (local.set $a (call $payload (i32.const 2)))
+    (local.set $b (call $payload (i32.const 1)))
+    (call $drop (i32.const 1))
+
+    (if (i32.and (i32.eq (local.get $lk) (i32.const 0xfe)) (i32.eq (local.get $rk) (i32.const 0xfe)))
+      (then
+        (call $set_top (i32.const 0xfe) (i32.add (local.get $a) (local.get $b)))
+        (return)))
+
+    (if (i32.eqz
+          (i32.and
+            (i32.or (i32.eq (local.get $lk) (i32.const 0xca)) (i32.eq (local.get $lk) (i32.const 0xfe)))
+            (i32.or (i32.eq (local.get $rk) (i32.const 0xca)) (i32.eq (local.get $rk) (i32.const 0xfe)))))
+      (then
+        (call $fail_kinds
+          (local.get $at)
+          (global.get $fault_invalid_operands)
+          (global.get $text_cannot_add)
+          (local.get $lk)
+          (local.get $rk))))
+
+    ;; Only one of them is an int here, the scratch space is enough.
+    (if (i32.eq (local.get $lk) (i32.const 0xfe))
+      (then (local.set $a (call $itoa (local.get $a)))))
+    (if (i32.eq (local.get $rk) (i32.const 0xfe))
+      (then (local.set $b (call $itoa (local.get $b)))))
+
+    (local.set $s
+      (call $alloc (i32.add (i32.add (i32.load (local.get $a)) (i32.load (local.get $b))) (i32.const 4))))
+    (i32.store (local.get $s) (i32.add (i32.load (local.get $a)) (i32.load (local.get $b))))
+    (memory.copy
+      (i32.add (local.get $s) (i32.const 4))
+      (i32.add (local.get $a) (i32.const 4))
+      (i32.load (local.get $a)))
+    (memory.copy
+      (i32.add (i32.add (local.get $s) (i32.const 4)) (i32.load (local.get $a)))
+      (i32.add (local.get $b) (i32.const 4))
+      (i32.load (local.get $b)))
+    (call $set_top (i32.const 0xca) (local.get $s)))
+
+  ;; Sub, Mul, Div and Rem from 0, on the two ints on the top. They wrap,
+  ;; the division that overflows too.
+  (func $rt_arithmetic (param $op i32) (param $at i32)
+    (local $a i32) (local $b i32) (local $r i32)
+    (if (i32.or
+          (i32.ne (call $kind (i32.const 2)) (i32.const 0xfe))
+          (i32.ne (call $kind (i32.const 1)) (i32.const 0xfe)))
+      (then
+        (call $fail_kinds
+          (local.get $at)
+          (global.get $fault_invalid_operands)
+          (global.get $text_arithmetic)
+          (call $kind (i32.const 2))
+          (call $kind (i32.const 1)))))
+    (local.set $a (call $payload (i32.const 2)))
+    (local.set $b (call $payload (i32.const 1)))
+
+    (if (i32.and (i32.ge_u (local.get $op) (i32.const 2)) (i32.eqz (local.get $b)))
+      (then
+        (call $fail_msg
+          (local.get $at)
+          (global.get $fault_division_by_zero)
+          (global.get $text_division_by_zero))))
+
+    (block $done
+      (if (i32.eqz (local.get $op))
+        (then (local.set $r (i32.sub (local.get $a) (local.get $b))) (br $done)))
+      (if (i32.eq (local.get $op) (i32.const 1))
+        (then (local.set $r (i32.mul (local.get $a) (local.get $b))) (br $done)))
+      (if (i32.eq (local.get $b) (i32.const -1))
+        (then
+          (local.set $r
+            (select (i32.sub (i32.const 0) (local.get $a)) (i32.const 0) (i32.eq (local.get $op) (i32.const 2))))
+          (br $done)))
+      (local.set $r
+        (select
+          (i32.div_s (local.get $a) (local.get $b))
+          (i32.rem_s (local.get $a) (local.get $b))
+          (i32.eq (local.get $op) (i32.const 2)))))
+
+    (call $drop (i32.const 1))
+    (call $set_top (i32.const 0xfe) (local.get $r)))
+
+  ;; Compares the two values on the top, Eq, Neq, Lt, Gt, Lte and Gte
+  ;; from 0.
+  (func $rt_compare (param $op i32) (param $at i32)
+    (local $kind i32) (local $a i32) (local $b i32) (local $ordering i32) (local $r i32)
+    (local.set $kind (call $kind (i32.const 2)))
+    (local.set $a (call $payload (i32.const 2)))
+    (local.set $b (call $payload (i32.const 1)))
+
+    (if (i32.ne (local.get $kind) (call $kind (i32.const 1)))
+      (then
+        (call $fail_kinds
+          (local.get $at)
+          (global.get $fault_invalid_operands)
+          (global.get $text_cannot_compare)
+          (local.get $kind)
+          (call $kind (i32.const 1)))))
+
+    (block $ordered
+      (if (i32.eq (local.get $kind) (i32.const 0xfe))
+        (then
+          (local.set $ordering
+            (i32.sub
+              (i32.gt_s (local.get $a) (local.get $b))
+              (i32.lt_s (local.get $a) (local.get $b))))
+          (br $ordered)))
+      (if (i32.eq (local.get $kind) (i32.const 0xca))
+        (then
+          (local.set $ordering (call $strcmp (local.get $a) (local.get $b)))
+          (br $ordered)))
+      (if (i32.eq (local.get $kind) (i32.const 0xba))
+        (then
+          (if (i32.gt_u (local.get $op) (i32.const 1))
+            (then
+              (call $fail_msg
+                (local.get $at)
+                (global.get $fault_invalid_operands)
+                (global.get $text_compare_booleans))))
+          (local.set $ordering (i32.ne (local.get $a) (local.get $b)))
+          (br $ordered)))
+      (call $fail_kinds
+        (local.get $at)
+        (global.get $fault_invalid_operands)
+        (global.get $text_cannot_compare)
+        (local.get $kind)
+        (local.get $kind)))
+
+    (block $done
+      (if (i32.eqz (local.get $op))
+        (then (local.set $r (i32.eqz (local.get $ordering))) (br $done)))
+      (if (i32.eq (local.get $op) (i32.const 1))
+        (then (local.set $r (i32.ne (local.get $ordering) (i32.const 0))) (br $done)))
+      (if (i32.eq (local.get $op) (i32.const 2))
+        (then (local.set $r (i32.lt_s (local.get $ordering) (i32.const 0))) (br $done)))
+      (if (i32.eq (local.get $op) (i32.const 3))
+        (then (local.set $r (i32.gt_s (local.get $ordering) (i32.const 0))) (br $done)))
+      (if (i32.eq (local.get $op) (i32.const 4))
+        (then (local.set $r (i32.le_s (local.get $ordering) (i32.const 0))) (br $done)))
+      (local.set $r (i32.ge_s (local.get $ordering) (i32.const 0))))
+
+    (call $drop (i32.const 1))
+    (call $set_top (i32.const 0xba) (local.get $r)))
+
+  (func $rt_tuple
+    (local $tuple i32)
+    (local.set $tuple (call $alloc (i32.const 16)))
+    (i64.store (local.get $tuple) (i64.load (call $at (i32.const 2))))
+    (i64.store offset=8 (local.get $tuple) (i64.load (call $at (i32.const 1))))
+    (call $drop (i32.const 1))
+    (call $set_top (i32.const 0x10) (local.get $tuple)))
+
+  ;; The tuple on the top.
+  (func $expect_tuple (param $at i32) (result i32)
+    (if (i32.ne (call $kind (i32.const 1)) (i32.const 0x10))
+      (then
+        (call $fail_kind
+          (local.get $at)
+          (global.get $fault_not_a_tuple)
+          (global.get $text_expected_tuple)
+          (call $kind (i32.const 1)))))
+    (call $payload (i32.const 1)))
+
+  (func $rt_first (param $at i32)
+    (i64.store (call $at (i32.const 1)) (i64.load (call $expect_tuple (local.get $at)))))
+
+  (func $rt_second (param $at i32)
+    (i64.store (call $at (i32.const 1)) (i64.load offset=8 (call $expect_tuple (local.get $at)))))
+
+  ;; Pops a tuple, pushes the first and then the second value.
+  (func $rt_untuple (param $at i32)
+    (local $tuple i32)
+    (local.set $tuple (call $expect_tuple (local.get $at)))
+    (i64.store (call $at (i32.const 1)) (i64.load (local.get $tuple)))
+    (i64.store (global.get $sp) (i64.load offset=8 (local.get $tuple)))
+    (global.set $sp (i32.add (global.get $sp) (i32.const 8))))
+
+  ;; A record of the `size` values on the top, `names` are the strings of
+  ;; the names of the fields.
+  (func $rt_record (param $size i32) (param $names i32)
+    (local $record i32)
+    (local.set $record (call $alloc (i32.add (i32.const 8) (i32.mul (local.get $size) (i32.const 8)))))
+    (i32.store (local.get $record) (local.get $size))
+    (i32.store offset=4 (local.get $record) (local.get $names))
+    (call $drop (local.get $size))
+    (memory.copy
+      (i32.add (local.get $record) (i32.const 8))
+      (global.get $sp)
+      (i32.mul (local.get $size) (i32.const 8)))
+    (call $push (i32.const 0x11) (local.get $record)))
+
+  (func $rt_field (param $name i32) (param $at i32)
+    (local $record i32) (local $i i32)
+    (if (i32.ne (call $kind (i32.const 1)) (i32.const 0x11))
+      (then
+        (call $fail_kind
+          (local.get $at)
+          (global.get $fault_not_a_record)
+          (global.get $text_expected_record)
+          (call $kind (i32.const 1)))))
+    (local.set $record (call $payload (i32.const 1)))
+
+    (block $missing
+      (loop $fields
+        (br_if $missing (i32.ge_u (local.get $i) (i32.load (local.get $record))))
+        (if (call $str_eq
+              (i32.load (i32.add (i32.load offset=4 (local.get $record)) (i32.mul (local.get $i) (i32.const 4))))
+              (local.get $name))
+          (then
+            (i64.store
+              (call $at (i32.const 1))
+              (i64.load offset=8 (i32.add (local.get $record) (i32.mul (local.get $i) (i32.const 8)))))
+            (return)))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $fields)))
+
+    (call $error_begin (global.get $fault_no_such_field) (local.get $at))
+    (call $emit_str (global.get $text_no_field))
+    (call $emit_str (local.get $name))
+    (call $emit_str (global.get $text_backtick))
+    (call $error_end))
+
+  ;; A cell in front of `tail`: the head, the tail and the length.
+  (func $cell (param $head i64) (param $tail i32) (result i32)
+    (local $cell i32)
+    (local.set $cell (call $alloc (i32.const 16)))
+    (i64.store (local.get $cell) (local.get $head))
+    (i32.store offset=8 (local.get $cell) (local.get $tail))
+    (i32.store offset=12 (local.get $cell)
+      (i32.add
+        (i32.const 1)
+        (if (result i32) (local.get $tail)
+          (then (i32.load offset=12 (local.get $tail)))
+          (else (i32.const 0)))))
+    (local.get $cell))
+
+  ;; A list of the `size` values on the top, the last one goes in first.
+  (func $rt_list (param $size i32)
+    (local $list i32)
+    (block $done
+      (loop $elements
+        (br_if $done (i32.eqz (local.get $size)))
+        (local.set $list (call $cell (i64.load (call $at (i32.const 1))) (local.get $list)))
+        (call $drop (i32.const 1))
+        (local.set $size (i32.sub (local.get $size) (i32.const 1)))
+        (br $elements)))
+    (call $push (i32.const 0x12) (local.get $list)))
+
+  ;; The list on the top.
+  (func $expect_list (param $at i32) (result i32)
+    (if (i32.ne (call $kind (i32.const 1)) (i32.const 0x12))
+      (then
+        (call $fail_kind
+          (local.get $at)
+          (global.get $fault_not_a_list)
+          (global.get $text_expected_list)
+          (call $kind (i32.const 1)))))
+    (call $payload (i32.const 1)))
+
+  ;; The first cell of the list on the top.
+  (func $expect_cell (param $at i32) (result i32)
+    (local $cell i32)
+    (local.set $cell (call $expect_list (local.get $at)))
+    (if (i32.eqz (local.get $cell))
+      (then
+        (call $fail_msg
+          (local.get $at)
+          (global.get $fault_empty_list)
+          (global.get $text_empty_list))))
+    (local.get $cell))
+
+  (func $rt_cons (param $at i32)
+    (local $cell i32)
+    (local.set $cell (call $cell (i64.load (call $at (i32.const 2))) (call $expect_list (local.get $at))))
+    (call $drop (i32.const 1))
+    (call $set_top (i32.const 0x12) (local.get $cell)))
+
+  (func $rt_head (param $at i32)
+    (i64.store (call $at (i32.const 1)) (i64.load (call $expect_cell (local.get $at)))))
+
+  (func $rt_tail (param $at i32)
+    (call $set_top (i32.const 0x12) (i32.load offset=8 (call $expect_cell (local.get $at)))))
+
+  (func $rt_len (param $at i32)
+    (local $len i32)
+    (block $done
+      (if (i32.eq (call $kind (i32.const 1)) (i32.const 0xca))
+        (then
+          (local.set $len (i32.load (call $payload (i32.const 1))))
+          (br $done)))
+      (if (i32.eq (call $kind (i32.const 1)) (i32.const 0x12))
+        (then
+          (if (call $payload (i32.const 1))
+            (then (local.set $len (i32.load offset=12 (call $payload (i32.const 1))))))
+          (br $done)))
+      (call $fail_kind
+        (local.get $at)
+        (global.get $fault_invalid_argument)
+        (global.get $text_expected_sized)
+        (call $kind (i32.const 1))))
+    (call $set_top (i32.const 0xfe) (local.get $len)))
+
+  ;; The string, int start and int length on the top, they are already
+  ;; checked.
+  (func $rt_substr (param $at i32)
+    (local $s i32) (local $start i32) (local $len i32) (local $size i32)
+    (local.set $s (call $payload (i32.const 3)))
+    (local.set $start (call $payload (i32.const 2)))
+    (local.set $len (call $payload (i32.const 1)))
+    (local.set $size (i32.load (local.get $s)))
+    (if (i32.or
+          (i32.or (i32.lt_s (local.get $start) (i32.const 0)) (i32.lt_s (local.get $len) (i32.const 0)))
+          (i32.or
+            (i32.gt_s (local.get $start) (local.get $size))
+            (i32.gt_s (local.get $len) (i32.sub (local.get $size) (local.get $start)))))
+      (then
+        (call $error_begin (global.get $fault_out_of_bounds) (local.get $at))
+        (call $emit_str (global.get $text_cannot_take))
+        (call $emit_str (call $itoa (local.get $len)))
+        (call $emit_str (global.get $text_characters_from))
+        (call $emit_str (call $itoa (local.get $start)))
+        (call $emit_str (global.get $text_of_length))
+        (call $emit_str (call $itoa (local.get $size)))
+        (call $error_end)))
+    (call $drop (i32.const 2))
+    (call $set_top
+      (i32.const 0xca)
+      (call $string
+        (i32.add (i32.add (local.get $s) (i32.const 4)) (local.get $start))
+        (local.get $len))))
+
+  ;; The number in the string on the top, the same ones `strtol` reads.
+  (func $rt_to_int (param $at i32)
+    (local $s i32) (local $size i32) (local $i i32) (local $c i32) (local $negative i32) (local $v i64)
+    (local.set $s (call $payload (i32.const 1)))
+    (local.set $size (i32.load (local.get $s)))
+
+    (block $invalid
+      (block $spaces
+        (loop $skip
+          (br_if $invalid (i32.eq (local.get $i) (local.get $size)))
+          (local.set $c (i32.load8_u offset=4 (i32.add (local.get $s) (local.get $i))))
+          (br_if $spaces
+            (i32.and
+              (i32.ne (local.get $c) (i32.const 32))
+              (i32.gt_u (i32.sub (local.get $c) (i32.const 9)) (i32.const 4))))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $skip)))
+
+      (if (i32.or (i32.eq (local.get $c) (i32.const 43)) (i32.eq (local.get $c) (i32.const 45)))
+        (then
+          (local.set $negative (i32.eq (local.get $c) (i32.const 45)))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))))
+      (br_if $invalid (i32.eq (local.get $i) (local.get $size)))
+
+      (block $end
+        (loop $digits
+          (br_if $end (i32.eq (local.get $i) (local.get $size)))
+          (local.set $c
+            (i32.sub (i32.load8_u offset=4 (i32.add (local.get $s) (local.get $i))) (i32.const 48)))
+          (br_if $invalid (i32.gt_u (local.get $c) (i32.const 9)))
+          (local.set $v (i64.add (i64.mul (local.get $v) (i64.const 10)) (i64.extend_i32_u (local.get $c))))
+          (br_if $invalid (i64.gt_u (local.get $v) (i64.const 0x80000000)))
+          (local.set $i (i32.add (local.get $i) (i32.const 1)))
+          (br $digits)))
+
+      (if (local.get $negative)
+        (then (local.set $v (i64.sub (i64.const 0) (local.get $v))))
+        (else (br_if $invalid (i64.eq (local.get $v) (i64.const 0x80000000)))))
+      (call $set_top (i32.const 0xfe) (i32.wrap_i64 (local.get $v)))
+      (return))
+
+    (call $error_begin (global.get $fault_invalid_argument) (local.get $at))
+    (call $emit_str (global.get $text_backtick))
+    (call $emit_str (local.get $s))
+    (call $emit_str (global.get $text_not_a_number))
+    (call $error_end))
+
+  (func $rt_to_str (param $at i32)
+    (local $kind i32) (local $s i32)
+    (local.set $kind (call $kind (i32.const 1)))
+    (if (i32.eq (local.get $kind) (i32.const 0xca))
+      (then (return)))
+    (if (i32.eq (local.get $kind) (i32.const 0xfe))
+      (then
+        (local.set $s (call $itoa (call $payload (i32.const 1))))
+        (call $set_top
+          (i32.const 0xca)
+          (call $string (i32.add (local.get $s) (i32.const 4)) (i32.load (local.get $s))))
+        (return)))
+    (if (i32.eq (local.get $kind) (i32.const 0xba))
+      (then
+        (call $set_top
+          (i32.const 0xca)
+          (select (global.get $text_true) (global.get $text_false) (call $payload (i32.const 1))))
+        (return)))
+    (call $error_begin (global.get $fault_invalid_argument) (local.get $at))
+    (call $emit_str (global.get $text_cannot_convert))
+    (call $emit_str (call $kind_name (local.get $kind)))
+    (call $emit_str (global.get $text_to_string))
+    (call $error_end))
+
+  ;; Whether the string on the top is in the one under it, they are
+  ;; already checked.
+  (func $rt_contains
+    (local $s i32) (local $pattern i32) (local $start i32) (local $i i32) (local $found i32)
+    (local.set $s (call $payload (i32.const 2)))
+    (local.set $pattern (call $payload (i32.const 1)))
+    (block $done
+      (loop $starts
+        (br_if $done
+          (i32.gt_s
+            (i32.load (local.get $pattern))
+            (i32.sub (i32.load (local.get $s)) (local.get $start))))
+        (local.set $i (i32.const 0))
+        (block $mismatch
+          (loop $bytes
+            (if (i32.eq (local.get $i) (i32.load (local.get $pattern)))
+              (then
+                (local.set $found (i32.const 1))
+                (br $done)))
+            (br_if $mismatch
+              (i32.ne
+                (i32.load8_u offset=4 (i32.add (i32.add (local.get $s) (local.get $start)) (local.get $i)))
+                (i32.load8_u offset=4 (i32.add (local.get $pattern) (local.get $i)))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br $bytes)))
+        (local.set $start (i32.add (local.get $start) (i32.const 1)))
+        (br $starts)))
+    (call $drop (i32.const 1))
+    (call $set_top (i32.const 0xba) (local.get $found)))
+
+  ;; A line of the standard input without its line break, read from the
+  ;; host a byte at a time right where its string goes.
+  (func $rt_read_line (param $at i32)
+    (local $s i32) (local $len i32) (local $read i32)
+    (local.set $s (global.get $heap))
+    (block $done
+      (loop $bytes
+        (call $reserve (i32.add (i32.add (local.get $s) (local.get $len)) (i32.const 5)))
+        (local.set $read
+          (call $read (i32.add (i32.add (local.get $s) (local.get $len)) (i32.const 4)) (i32.const 1)))
+        (br_if $done (i32.eqz (local.get $read)))
+        (br_if $done
+          (i32.eq (i32.load8_u offset=4 (i32.add (local.get $s) (local.get $len))) (i32.const 10)))
+        (local.set $len (i32.add (local.get $len) (i32.const 1)))
+        (br $bytes)))
+    (if (i32.and (i32.eqz (local.get $read)) (i32.eqz (local.get $len)))
+      (then
+        (call $fail_msg
+          (local.get $at)
+          (global.get $fault_end_of_input)
+          (global.get $text_no_more_lines))))
+    (if (local.get $len)
+      (then
+        (if (i32.eq (i32.load8_u offset=3 (i32.add (local.get $s) (local.get $len))) (i32.const 13))
+          (then (local.set $len (i32.sub (local.get $len) (i32.const 1)))))))
+    (i32.store (call $alloc (i32.add (local.get $len) (i32.const 4))) (local.get $len))
+    (call $push (i32.const 0xca) (local.get $s)))
+
+  ;; The command-line arguments, without the name of the program. The host
+  ;; gives them like WASI's `args_get`, NUL-terminated.
+  (func $rt_argv
+    (local $sizes i32) (local $argc i32) (local $argv i32) (local $idx i32) (local $arg i32) (local $len i32)
+    (local.set $sizes (call $alloc (i32.const 8)))
+    (call $args_sizes (local.get $sizes) (i32.add (local.get $sizes) (i32.const 4)))
+    (local.set $argc (i32.load (local.get $sizes)))
+    (local.set $argv (call $alloc (i32.mul (local.get $argc) (i32.const 4))))
+    (call $args (local.get $argv) (call $alloc (i32.load offset=4 (local.get $sizes))))
+    (local.set $idx (i32.const 1))
+    (block $done
+      (loop $arguments
+        (br_if $done (i32.ge_s (local.get $idx) (local.get $argc)))
+        (local.set $arg (i32.load (i32.add (local.get $argv) (i32.mul (local.get $idx) (i32.const 4)))))
+        (local.set $len (i32.const 0))
+        (block $end
+          (loop $bytes
+            (br_if $end (i32.eqz (i32.load8_u (i32.add (local.get $arg) (local.get $len)))))
+            (local.set $len (i32.add (local.get $len) (i32.const 1)))
+            (br $bytes)))
+        (call $push (i32.const 0xca) (call $string (local.get $arg) (local.get $len)))
+        (local.set $idx (i32.add (local.get $idx) (i32.const 1)))
+        (br $arguments)))
+    (call $rt_list
+      (if (result i32) (local.get $argc)
+        (then (i32.sub (local.get $argc) (i32.const 1)))
+        (else (i32.const 0)))))
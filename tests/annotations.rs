@@ -0,0 +1,24 @@
+//! The annotations that are known to be wrong before the program runs,
+//! every backend rejects them when it compiles.
+
+mod common;
+
+const WRONG: &[(&str, &str)] = &[
+    (r#"let x: Int = "a"; print(x)"#, "expected Int, found Str"),
+    ("let n = 1; let s: Str = n + 1; print(s)", "expected Str, found Int"),
+    ("let f = fn (n) => n; let g: Int = f; print(g)", "expected Int, found function"),
+    ("let r: Str = { a: 1 }; print(r)", "expected Str, found record"),
+    ("let f = fn (s: Str) => { let n: Int = s; n }; print(f)", "expected Int, found Str"),
+    ("print(substr(1, 0, 1))", "expected Str, found Int"),
+];
+
+#[test]
+fn wrong_annotations_dont_compile() {
+    for (program, message) in WRONG {
+        let file = touka::parse(program).unwrap();
+
+        for (backend, report) in common::compile_everywhere_err(&file) {
+            assert!(report.contains(message), "{program} on {backend}: {report}");
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! The x86-64 assembly of `--backend asm` against the virtual machine,
+//! assembled with `as` and linked with `ld`.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether the programs can be assembled and run here.
+fn supported() -> bool {
+    cfg!(all(target_arch = "x86_64", target_os = "linux")) && common::installed("as")
+}
+
+/// Assembles and links a program in `dir`, the binary is named like it.
+fn build(dir: &Path, program: &Path) -> PathBuf {
+    let name = program.file_stem().unwrap().to_string_lossy();
+    let (source, object, binary) = (dir.join(format!("{name}.s")), dir.join(format!("{name}.o")), dir.join(&*name));
+
+    let asm = common::run(common::touka().arg(program).args(["--backend", "asm", "-o"]).arg(&source));
+    assert!(asm.status.success(), "{name}: {}", String::from_utf8_lossy(&asm.stderr));
+
+    let assembled = common::run(Command::new("as").arg("-o").arg(&object).arg(&source));
+    assert!(assembled.status.success(), "{name}: {}", String::from_utf8_lossy(&assembled.stderr));
+
+    let linked = common::run(Command::new("ld").arg("-o").arg(&binary).arg(&object));
+    assert!(linked.status.success(), "{name}: {}", String::from_utf8_lossy(&linked.stderr));
+
+    binary
+}
+
+#[test]
+fn asm_matches_the_vm() {
+    if !supported() {
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("touka-asm-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    for program in common::programs() {
+        let name = program.file_stem().unwrap().to_string_lossy();
+        let binary = build(&dir, &program);
+        common::assert_same(&name, &common::vm(&program), &common::run(&mut Command::new(&binary)));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A program that can't map its memory fails like on a runtime error,
+/// instead of crashing on the first value.
+#[test]
+fn asm_reports_the_memory_it_cannot_map() {
+    if !supported() {
+        return;
+    }
+
+    let dir = std::env::temp_dir().join(format!("touka-asm-memory-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let binary = build(&dir, Path::new("tests/programs/fib.rinha"));
+    let found = common::run(Command::new("sh").arg("-c").arg("ulimit -v 500000 && exec \"$0\"").arg(&binary));
+
+    assert_eq!(
+        String::from_utf8_lossy(&found.stderr),
+        "ToukaRT: error[out-of-memory] at _start: cannot map the memory of the program\n"
+    );
+    assert_eq!(found.status.code(), Some(1));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,110 @@
+//! The JSON AST of `--emit ast`: its locations have lines and columns, and
+//! reading it back points them to the same file, by its name.
+
+mod common;
+
+use touka::ast::{Element, FileId, SourceMap, Term};
+
+const PROGRAM: &str = "let x = 1;\nprint(x + \"ö\" + y)";
+
+/// Every location of a JSON value, with the lines and columns written
+/// beside the offsets.
+fn locations(value: &serde_json::Value, found: &mut Vec<serde_json::Value>) {
+    match value {
+        serde_json::Value::Object(object) => {
+            if object.contains_key("filename") {
+                found.push(value.clone());
+            }
+            object.values().for_each(|value| locations(value, found));
+        }
+        serde_json::Value::Array(array) => array.iter().for_each(|value| locations(value, found)),
+        _ => {}
+    }
+}
+
+#[test]
+fn the_locations_have_lines_and_columns() {
+    let file = touka::parse_named("ast.rinha", PROGRAM).unwrap();
+    let json = serde_json::to_value(&file).unwrap();
+
+    let mut found = vec![];
+    locations(&json, &mut found);
+
+    // The columns count characters, the offsets bytes.
+    let y = found.iter().find(|location| location["start"] == 28).unwrap();
+    assert_eq!(y["filename"], "ast.rinha");
+    assert_eq!((&y["line"], &y["column"]), (&2.into(), &17.into()));
+    assert_eq!((&y["end_line"], &y["end_column"]), (&2.into(), &18.into()));
+}
+
+#[test]
+fn the_json_reads_back_to_the_same_locations() {
+    let file = touka::parse_named("ast.rinha", PROGRAM).unwrap();
+    let json = serde_json::to_string(&file).unwrap();
+    let mut sources = SourceMap::default();
+    sources.insert(file.location.file.clone());
+    let read = touka::parser::parse_json(&json, &sources).unwrap();
+
+    assert_eq!(read.location, file.location);
+    let (Term::Let(before), Term::Let(after)) = (&file.expression, &read.expression) else {
+        panic!("the program isn't a `let`");
+    };
+    assert_eq!(after.next.location(), before.next.location());
+    assert_eq!(after.next.location().line_col(), (2, 1));
+    assert_eq!(format!("{:?}", after.next.location()), "ast.rinha:2:1-2:19");
+}
+
+#[test]
+fn a_name_is_the_last_file_with_it() {
+    let first = FileId::new("named.rinha", "print(1)");
+    let second = FileId::new("named.rinha", "\nprint(2)");
+    assert_ne!(first, second);
+
+    let mut sources = SourceMap::default();
+    sources.insert(first);
+    sources.insert(second.clone());
+
+    let file = touka::parse_named("named.rinha", "\nprint(3)").unwrap();
+    let json = serde_json::to_string(&file).unwrap();
+    let read = touka::parser::parse_json(&json, &sources).unwrap();
+    assert_eq!(read.location.file, second);
+    assert_eq!(read.expression.location().line_col(), (2, 1));
+}
+
+/// A JSON AST whose file isn't known has the offsets only, without its
+/// text every location is at its start. The locations are still in the
+/// same file.
+#[test]
+fn an_unknown_name_has_no_lines() {
+    let file = touka::parse_named("unknown.rinha", "let x = 1;\nprint(x)").unwrap();
+    let json = serde_json::to_string(&file).unwrap();
+    let read = touka::parser::parse_json(&json, &SourceMap::default()).unwrap();
+
+    let Term::Let(read) = &read.expression else {
+        panic!("the program isn't a `let`");
+    };
+    assert_eq!(read.next.location().file.name(), "unknown.rinha");
+    assert_eq!(read.next.location().line_col(), (1, 1));
+    assert_eq!(read.next.location().file, read.location.file);
+}
+
+/// The command line reads the JSON of a program with the source beside
+/// it, the errors point to the source.
+#[test]
+fn the_command_line_reads_the_json_back() {
+    let dir = std::env::temp_dir().join(format!("touka-ast-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let (program, json) = (dir.join("ast.rinha"), dir.join("ast.json"));
+    std::fs::write(&program, "let x = 1;\nprint(x / 0)").unwrap();
+
+    let emitted = common::run(common::touka().arg(&program).args(["--emit", "ast", "-o"]).arg(&json));
+    assert!(emitted.status.success(), "{}", String::from_utf8_lossy(&emitted.stderr));
+
+    let from_source = common::run(common::touka().arg(&program).args(["--backend", "vm"]));
+    let from_json = common::run(common::touka().arg(&json).args(["--backend", "vm"]));
+    common::assert_same("ast.json", &from_source, &from_json);
+
+    let error = String::from_utf8_lossy(&from_json.stderr).into_owned();
+    assert!(error.contains(&format!("{}:2:7", program.display())), "{error}");
+}
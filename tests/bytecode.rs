@@ -0,0 +1,63 @@
+//! The listing of `--emit bytecode`.
+
+mod common;
+
+fn listing(program: &str) -> String {
+    let output = common::run(common::touka().arg(program).args(["--emit", "bytecode"]));
+    assert!(output.status.success(), "{program}: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn the_listing_of_fib() {
+    let expected = "\
+code 0 fib (arity 1, captures 0, locals 1):
+     0  load 0
+     1  const 2
+     2  lt                      ; tests/programs/fib.rinha:2:7
+     3  branch 6                ; tests/programs/fib.rinha:2:7
+     4  load 0
+     5  jump 17
+     6  current
+     7  load 0
+     8  const 1
+     9  sub                     ; tests/programs/fib.rinha:5:9
+    10  call 1                  ; tests/programs/fib.rinha:5:5
+    11  current
+    12  load 0
+    13  const 2
+    14  sub                     ; tests/programs/fib.rinha:5:22
+    15  call 1                  ; tests/programs/fib.rinha:5:18
+    16  add                     ; tests/programs/fib.rinha:5:5
+    17  return
+code 1 <main> (arity 0, captures 0, locals 1):
+     0  closure 0
+     1  store 0
+     2  load 0
+     3  const 20
+     4  call 1                  ; tests/programs/fib.rinha:9:7
+     5  print                   ; tests/programs/fib.rinha:9:1
+     6  return
+";
+    assert_eq!(listing("tests/programs/fib.rinha"), expected);
+}
+
+/// The instructions of the patterns, the records and the builtins.
+#[test]
+fn the_listing_of_a_match() {
+    let expected = std::fs::read_to_string("tests/programs/bytecode/listing.bytecode").unwrap();
+    assert_eq!(listing("tests/programs/bytecode/listing.rinha"), expected);
+}
+
+/// A constant or a location used over and over is in the program once.
+#[test]
+fn the_constants_and_locations_are_interned() {
+    let file = touka::parse("let f = fn (n) => n + 1 + 1;\nprint(f(1) + f(1))").unwrap();
+    let program = touka::bytecode::Compiler::default().compile_file(&file).unwrap();
+
+    assert_eq!(program.constants.len(), 1);
+    let mut locations = program.locations.clone();
+    locations.sort();
+    locations.dedup();
+    assert_eq!(locations.len(), program.locations.len());
+}
@@ -0,0 +1,47 @@
+//! The C translation unit points back to the program: every statement of
+//! it has a `#line` directive with the line of its term, for the errors of
+//! the C compiler and for the debuggers.
+
+const PROGRAM: &str = "\
+let x = 1;
+
+print(x + 2);
+let f = fn (n) => {
+  n * 2
+};
+print(f(x))";
+
+/// The line of each statement of the program, from its `#line`.
+fn statements(c: &str) -> Vec<(usize, &str)> {
+    let program = &c[c.find("/* fn: ").expect("the program has no functions")..];
+    let lines: Vec<_> = program.lines().collect();
+
+    lines
+        .windows(2)
+        .filter_map(|pair| {
+            let directive = pair[0].strip_prefix("#line ")?;
+            let (line, file) = directive.split_once(' ').unwrap();
+            assert_eq!(file, "\"main.rinha\"", "{directive}");
+            Some((line.parse().unwrap(), pair[1]))
+        })
+        .collect()
+}
+
+#[test]
+fn the_statements_have_the_lines_of_their_terms() {
+    let file = touka::parse_named("main.rinha", PROGRAM).unwrap();
+    let c = touka::compile_to_c(&file).unwrap();
+    let statements = statements(&c);
+
+    let line = |needle: &str| {
+        let found = statements.iter().find(|(_, statement)| statement.contains(needle));
+        found.unwrap_or_else(|| panic!("there's no `{needle}` in:\n{c}")).0
+    };
+    assert_eq!(line("WrapAdd"), 3);
+    assert_eq!(line("Mul, \"main.rinha:5:3\""), 5);
+    assert_eq!(line("MakeClosure"), 4);
+    assert_eq!(line("\"main.rinha:7:7\""), 7);
+
+    let last = PROGRAM.lines().count();
+    assert!(statements.iter().all(|(line, _)| (1..=last).contains(line)), "{statements:?}");
+}
@@ -0,0 +1,65 @@
+//! The exit status of the command line: a program that doesn't compile
+//! fails, whatever it was compiled to, and writes nothing.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+const WRONG: &[(&str, &str)] = &[
+    ("syntax", "print(1 +"),
+    ("unbound", "let x = 1;\nprint(y)"),
+    ("annotation", "let x: Int = \"a\";\nprint(x)"),
+];
+
+/// A directory of a test, removed when it's done, even when it fails.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("touka-cli-{}-{test}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn a_program_that_doesnt_compile_fails() {
+    let dir = Scratch::new("wrong");
+
+    for (name, source) in WRONG {
+        let program = dir.join(format!("{name}.rinha"));
+        std::fs::write(&program, source).unwrap();
+
+        for args in [&["--backend", "vm"][..], &["--backend", "c"], &["--emit", "js"], &["--emit", "wat"]] {
+            let output_file = dir.join(format!("{name}.out"));
+            let _ = std::fs::remove_file(&output_file);
+
+            let output = common::run(common::touka().arg(&program).args(args).arg("-o").arg(&output_file));
+            assert_eq!(output.status.code(), Some(1), "{name} with {args:?}");
+            assert!(output.stdout.is_empty(), "{name} with {args:?}");
+            assert!(!output.stderr.is_empty(), "{name} with {args:?}");
+            assert!(!output_file.exists(), "{name} with {args:?} wrote its output");
+        }
+    }
+}
+
+#[test]
+fn a_program_that_compiles_succeeds() {
+    let dir = Scratch::new("fine");
+    let program = dir.join("fine.rinha");
+    std::fs::write(&program, "print(1)").unwrap();
+
+    let output = common::run(common::touka().arg(&program).args(["--backend", "vm"]));
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"1\n");
+}
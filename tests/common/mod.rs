@@ -0,0 +1,109 @@
+//! What the tests share: the programs of `tests/programs`, and running
+//! them on the compiler and on the tools of the other backends.
+
+// Every test uses some of these.
+#![allow(dead_code)]
+
+pub mod wasm;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// The programs of `tests/programs`, relative to the crate so the
+/// locations in their errors are the same everywhere. The ones in its
+/// directories don't run on every backend.
+pub fn programs() -> Vec<PathBuf> {
+    programs_in("tests/programs")
+}
+
+/// The programs of a directory, relative to the crate.
+pub fn programs_in(dir: &str) -> Vec<PathBuf> {
+    let mut programs: Vec<_> = std::fs::read_dir(dir)
+        .unwrap_or_else(|_| panic!("there are no test programs in `{dir}`"))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rinha"))
+        .collect();
+
+    programs.sort();
+    programs
+}
+
+/// Runs a command without a standard input.
+pub fn run(command: &mut Command) -> Output {
+    command.stdin(Stdio::null()).output().expect("the command doesn't start")
+}
+
+/// The compiler itself.
+pub fn touka() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_touka"))
+}
+
+/// Whether a tool is around. The tests that need one that isn't are
+/// skipped, they pass without checking anything.
+pub fn installed(tool: &str) -> bool {
+    let found = Command::new(tool).arg("--version").output().is_ok();
+    if !found {
+        eprintln!("skipping, `{tool}` isn't installed");
+    }
+
+    found
+}
+
+/// The C compiler, `$CC` or `cc`, if it's around.
+pub fn cc() -> Option<String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    installed(&cc).then_some(cc)
+}
+
+/// Compiles the C of a program next to it, the binary is the source
+/// without its extension. It's what the compiler prints when it doesn't
+/// compile.
+pub fn compile_c(cc: &str, source: &Path) -> Result<PathBuf, String> {
+    let binary = source.with_extension("");
+    let compiled = run(Command::new(cc).arg("-w").arg("-o").arg(&binary).arg(source).arg("-lpthread"));
+    if !compiled.status.success() {
+        return Err(String::from_utf8_lossy(&compiled.stderr).into_owned());
+    }
+
+    Ok(binary)
+}
+
+/// An error as miette reports it, with the source around it.
+pub fn report(error: touka::Diagnostics) -> String {
+    format!("{:?}", miette::Report::new(error))
+}
+
+/// The reports of the backends that rejected a program when compiling,
+/// the name of each backend first. It fails if one of them compiled it,
+/// the assembly and the WAT are compiled from the same bytecode as the
+/// virtual machine.
+pub fn compile_everywhere_err(file: &touka::ast::File) -> Vec<(&'static str, String)> {
+    let c = touka::compile_to_c(file).expect_err("it compiled to C");
+    let js = touka::compile_to_js(file, Default::default()).expect_err("it compiled to JavaScript");
+    let Err(touka::InterpretError::Compile(vm)) = touka::interpret(file, std::io::sink()) else {
+        panic!("it compiled on the virtual machine");
+    };
+
+    vec![("C", report(c)), ("JavaScript", report(js)), ("the virtual machine", report(vm))]
+}
+
+/// What a program printed on the virtual machine.
+pub fn vm(program: &Path) -> Output {
+    run(touka().arg(program).args(["--backend", "vm"]))
+}
+
+/// Fails unless a program printed and exited like it did on the virtual
+/// machine.
+pub fn assert_same(name: &str, expected: &Output, found: &Output) {
+    assert_eq!(
+        String::from_utf8_lossy(&found.stdout),
+        String::from_utf8_lossy(&expected.stdout),
+        "{name}: the output differs"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&found.stderr),
+        String::from_utf8_lossy(&expected.stderr),
+        "{name}: the errors differ"
+    );
+    assert_eq!(found.status.code(), expected.status.code(), "{name}: the status differs");
+}
@@ -0,0 +1,122 @@
+//! A host for the modules of `--emit wat` that doesn't need a WASI
+//! runtime: it does what `tests/wasm/host.wat` does, on `wasmi`, with the
+//! input and the arguments it's given, and keeps what the module printed.
+
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+
+use wasmi::core::Trap;
+use wasmi::{Caller, Config, Engine, ExternType, Linker, Memory, MemoryType, Module, StackLimits, Store};
+
+#[derive(Default)]
+struct Host {
+    memory: Option<Memory>,
+    input: Vec<u8>,
+    read: usize,
+    args: Vec<Vec<u8>>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// The bytes the module gave to the host, each write is a line.
+fn line(caller: &Caller<'_, Host>, ptr: i32, len: i32) -> Vec<u8> {
+    let memory = caller.data().memory.expect("the memory is the host's");
+    let mut line = memory.data(caller)[ptr as usize..][..len as usize].to_vec();
+    line.push(b'\n');
+    line
+}
+
+/// Runs a module like `wasmtime run --preload touka=tests/wasm/host.wat`
+/// would, without a standard input or arguments.
+pub fn run(wat: &[u8]) -> Output {
+    run_with(wat, b"", &[])
+}
+
+/// Runs a module with a standard input and arguments.
+pub fn run_with(wat: &[u8], input: &[u8], args: &[&str]) -> Output {
+    let binary = wat::parse_bytes(wat).expect("the module doesn't assemble");
+    // Room for the calls the runtime counts, wasmi gives 1024 frames.
+    let mut config = Config::default();
+    config.set_stack_limits(StackLimits::new(1024, 16 * 1024 * 1024, 64 * 1024).unwrap());
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, &binary[..]).expect("the module doesn't compile");
+
+    // The first argument is the name of the program, like under WASI.
+    let args = std::iter::once("program").chain(args.iter().copied()).map(|arg| arg.as_bytes().to_vec());
+    let host = Host { input: input.to_vec(), args: args.collect(), ..Host::default() };
+    let mut store = Store::new(&engine, host);
+
+    // The memory is at least as big as the module asks for.
+    let ty = module
+        .imports()
+        .find_map(|import| match import.ty() {
+            ExternType::Memory(ty) => Some(*ty),
+            _ => None,
+        })
+        .unwrap_or_else(|| MemoryType::new(1, None).unwrap());
+    let memory = Memory::new(&mut store, ty).unwrap();
+    store.data_mut().memory = Some(memory);
+
+    let mut linker = Linker::new(&engine);
+    linker.define("touka", "memory", memory).unwrap();
+    linker
+        .func_wrap("touka", "print", |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let line = line(&caller, ptr, len);
+            caller.data_mut().stdout.extend(line);
+        })
+        .unwrap();
+    linker
+        .func_wrap("touka", "error", |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let line = line(&caller, ptr, len);
+            caller.data_mut().stderr.extend(line);
+            Err::<(), _>(Trap::i32_exit(1))
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("touka", "read", |mut caller: Caller<'_, Host>, ptr: i32, len: i32| {
+            let host = caller.data();
+            let bytes = host.input[host.read..].iter().take(len as usize).copied().collect::<Vec<_>>();
+            let memory = host.memory.unwrap();
+            memory.data_mut(&mut caller)[ptr as usize..][..bytes.len()].copy_from_slice(&bytes);
+            caller.data_mut().read += bytes.len();
+            bytes.len() as i32
+        })
+        .unwrap();
+    linker
+        .func_wrap("touka", "args_sizes", |mut caller: Caller<'_, Host>, argc: i32, size: i32| {
+            let args = &caller.data().args;
+            let sizes = [args.len(), args.iter().map(|arg| arg.len() + 1).sum()];
+            let memory = caller.data().memory.unwrap();
+            let data = memory.data_mut(&mut caller);
+            for (ptr, value) in [argc, size].into_iter().zip(sizes) {
+                data[ptr as usize..][..4].copy_from_slice(&(value as u32).to_le_bytes());
+            }
+        })
+        .unwrap();
+    linker
+        .func_wrap("touka", "args", |mut caller: Caller<'_, Host>, argv: i32, buf: i32| {
+            let args = caller.data().args.clone();
+            let memory = caller.data().memory.unwrap();
+            let data = memory.data_mut(&mut caller);
+            let mut at = buf as usize;
+            for (idx, arg) in args.iter().enumerate() {
+                data[argv as usize + idx * 4..][..4].copy_from_slice(&(at as u32).to_le_bytes());
+                data[at..][..arg.len()].copy_from_slice(arg);
+                data[at + arg.len()] = 0;
+                at += arg.len() + 1;
+            }
+        })
+        .unwrap();
+
+    let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+    let start = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+
+    let code = match start.call(&mut store, ()) {
+        Ok(()) => 0,
+        Err(trap) => trap.i32_exit_status().unwrap_or_else(|| panic!("the module trapped: {trap}")),
+    };
+
+    let host = store.into_data();
+    Output { status: ExitStatus::from_raw(code << 8), stdout: host.stdout, stderr: host.stderr }
+}
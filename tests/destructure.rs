@@ -0,0 +1,33 @@
+//! The patterns of a `let` can't fail to match. The grammar only has the
+//! ones that can't, but a JSON AST can have any, and every backend rejects
+//! them when it compiles.
+
+mod common;
+
+use touka::ast::{Element, Int, Pattern, Term};
+
+/// `let (1, b) = (1, 2); print(b)`, which can't be written.
+fn refutable() -> touka::ast::File {
+    let mut file = touka::parse("let (a, b) = (1, 2); print(b)").unwrap();
+
+    let Term::Destructure(destructure) = &mut file.expression else {
+        panic!("the program isn't a destructuring `let`");
+    };
+    let Pattern::Tuple(tuple) = &mut destructure.pattern else {
+        panic!("the pattern isn't a tuple");
+    };
+    let location = tuple.first.location().clone();
+    *tuple.first = Pattern::Int(Int { value: 1, location });
+
+    file
+}
+
+#[test]
+fn refutable_patterns_dont_compile() {
+    let file = refutable();
+    let message = "a `let` can only take apart tuples";
+
+    for (backend, report) in common::compile_everywhere_err(&file) {
+        assert!(report.contains(message), "on {backend}: {report}");
+    }
+}
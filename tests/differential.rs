@@ -0,0 +1,246 @@
+//! The interpreter against the C backend: every program of
+//! `tests/programs` has to print the same and exit with the same status on
+//! both. A program where they diverge is shrunk to a smaller one that still
+//! does, and that's the reproducer in the failure.
+//! It needs a C compiler, `$CC` or `cc`.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long a program can run, the ones shrunk can loop forever.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a program printed, and how it exited.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stdout: String,
+    status: Option<i32>,
+}
+
+/// Where the programs being compared are written.
+struct Scratch {
+    dir: PathBuf,
+    cc: String,
+}
+
+/// Waits for a program that writes to `stdout`, killing it after the
+/// timeout.
+fn wait(mut child: Child, stdout: &Path) -> Option<Outcome> {
+    let start = Instant::now();
+
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+
+        std::thread::sleep(Duration::from_millis(5));
+    };
+
+    Some(Outcome {
+        stdout: String::from_utf8_lossy(&std::fs::read(stdout).unwrap()).into_owned(),
+        status: status.code(),
+    })
+}
+
+fn run(command: &mut Command, stdout: &Path) -> Option<Outcome> {
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(std::fs::File::create(stdout).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    wait(child, stdout)
+}
+
+impl Scratch {
+    fn new(cc: String) -> Self {
+        let dir = std::env::temp_dir().join(format!("touka-differential-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir, cc }
+    }
+
+    /// The outcomes of a program on the interpreter and on the C backend,
+    /// if it compiles and finishes on both.
+    fn outcomes(&self, source: &str) -> Option<(Outcome, Outcome)> {
+        let program = self.dir.join("program.rinha");
+        std::fs::write(&program, source).unwrap();
+
+        let interpreted = run(
+            common::touka().arg(&program).args(["--backend", "vm"]),
+            &self.dir.join("vm.stdout"),
+        )?;
+
+        let c = self.dir.join("program.c");
+        let generated = common::touka().arg(&program).arg("-o").arg(&c).output().unwrap();
+        if !generated.status.success() {
+            return None;
+        }
+
+        let binary = common::compile_c(&self.cc, &c).ok()?;
+
+        let native = run(&mut Command::new(&binary), &self.dir.join("c.stdout"))?;
+        Some((interpreted, native))
+    }
+
+    fn diverges(&self, source: &str) -> bool {
+        self.outcomes(source).is_some_and(|(interpreted, native)| interpreted != native)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The tokens of a program, without the comments. Joined back with
+/// spaces they're the same program.
+fn tokens(source: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let end = match c {
+            c if c.is_whitespace() => continue,
+
+            '/' if source[start..].starts_with("//") => {
+                chars.find(|(_, c)| *c == '\n');
+                continue;
+            }
+
+            '/' if source[start..].starts_with("/*") => {
+                let end = source[start + 2..].find("*/").map_or(source.len(), |idx| start + idx + 4);
+                while chars.next_if(|(idx, _)| *idx < end).is_some() {}
+                continue;
+            }
+
+            '"' => {
+                let mut escaped = false;
+                let end = chars.find(|(_, c)| {
+                    let closes = *c == '"' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    closes
+                });
+                end.map_or(source.len(), |(idx, _)| idx + 1)
+            }
+
+            c if c.is_alphanumeric() || c == '_' => {
+                while chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '/').is_some() {}
+                chars.peek().map_or(source.len(), |(idx, _)| *idx)
+            }
+
+            _ => {
+                let two = ["=>", "==", "!=", "<=", ">=", "&&", "||"];
+                match two.iter().any(|op| source[start..].starts_with(op)) {
+                    true => chars.next().map_or(source.len(), |(idx, c)| idx + c.len_utf8()),
+                    false => start + c.len_utf8(),
+                }
+            }
+        };
+
+        tokens.push(source[start..end].to_string());
+    }
+
+    tokens
+}
+
+/// Delta debugging: removes chunks of `units`, smaller and smaller, as
+/// long as what's left still fails.
+fn ddmin(mut units: Vec<String>, separator: &str, fails: &impl Fn(&str) -> bool) -> Vec<String> {
+    let mut parts = 2;
+
+    while units.len() >= 2 {
+        let size = (units.len() + parts - 1) / parts;
+        let chunks: Vec<_> = (0..units.len()).step_by(size).map(|start| start..units.len().min(start + size)).collect();
+
+        let fails = |units: &[String]| fails(&units.join(separator));
+
+        if let Some(subset) = chunks.iter().map(|chunk| units[chunk.clone()].to_vec()).find(|s| fails(s)) {
+            units = subset;
+            parts = 2;
+            continue;
+        }
+
+        let complements = chunks.iter().map(|chunk| [&units[..chunk.start], &units[chunk.end..]].concat());
+        if let Some(complement) = complements.into_iter().find(|c| fails(c)) {
+            units = complement;
+            parts = (parts - 1).max(2);
+            continue;
+        }
+
+        if parts >= units.len() {
+            break;
+        }
+        parts = (parts * 2).min(units.len());
+    }
+
+    units
+}
+
+/// The smallest program that still fails, shrunk by lines and then by
+/// tokens.
+fn minimize(source: &str, fails: impl Fn(&str) -> bool) -> String {
+    let lines = source.lines().map(str::to_string).collect();
+    let source = ddmin(lines, "\n", &fails).join("\n");
+
+    let mut tokens = ddmin(tokens(&source), " ", &fails);
+
+    // What only goes away together is left, like a pair of parentheses.
+    'shrink: loop {
+        for width in 2..=3.min(tokens.len()) {
+            for start in 0..=tokens.len() - width {
+                let candidate = [&tokens[..start], &tokens[start + width..]].concat();
+                if fails(&candidate.join(" ")) {
+                    tokens = candidate;
+                    continue 'shrink;
+                }
+            }
+        }
+
+        break tokens.join(" ");
+    }
+}
+
+#[test]
+fn the_interpreter_and_c_agree() {
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    let scratch = Scratch::new(cc);
+
+    for program in common::programs() {
+        let source = std::fs::read_to_string(&program).unwrap();
+        let Some((interpreted, native)) = scratch.outcomes(&source) else {
+            panic!("{} doesn't compile or finish", program.display());
+        };
+
+        if interpreted != native {
+            let reproducer = minimize(&source, |source| scratch.diverges(source));
+            let (interpreted, native) = scratch.outcomes(&reproducer).unwrap();
+
+            panic!(
+                "{} diverges, reduced to:\n\n{reproducer}\n\ninterpreter: {interpreted:?}\nC:           {native:?}",
+                program.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn the_reproducer_still_fails() {
+    let source = "let a = 1;\nlet b = 2;\n// unused\nprint(a + b * 4);\nprint(b)";
+    let fails = |source: &str| source.contains('*') && source.contains("print");
+
+    assert_eq!(minimize(source, fails), "print *");
+}
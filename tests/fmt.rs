@@ -0,0 +1,135 @@
+//! The pretty-printer: what it writes parses back to the same program,
+//! it keeps the comments, and it's already formatted.
+
+mod common;
+
+use std::path::Path;
+use touka::generator::{entropy, Generator};
+
+/// A program with comments everywhere they can be.
+const COMMENTED: &str = r#"// The header.
+/* block
+   comment */
+let a = 1; // one
+let b = /* inline */ 2;
+
+// Functions.
+let f = fn (x) => {
+  // inside
+  let y = x + 1; // trailing
+  y * 2
+  // at the end
+};
+let g = "has // no comment";
+print(f(a) + b); /* after */
+if (a == 1) {
+  print(a)
+} else {
+  // else start
+  print(b)
+};
+let t = match (1, 2) {
+  // first arm
+  (1, _) => 0,
+  _ => 1
+};
+let h = fn (a, /* c1 */ b) => {
+  a + b // c2
+};
+let u = match (0, 1) {
+  (0, _) => "zero", // first
+  _ => "other" // second
+};
+let l = [1, /* two */ 2];
+let s = 1 + // wrapped
+  2;
+print(t)
+// the end
+"#;
+
+/// The shape of a program, without the locations.
+fn shape(expression: &touka::ast::Term) -> serde_json::Value {
+    fn strip(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(object) => {
+                object.remove("location");
+                object.values_mut().for_each(strip);
+            }
+            serde_json::Value::Array(array) => array.iter_mut().for_each(strip),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(expression).unwrap();
+    strip(&mut value);
+    value
+}
+
+/// Formats a program, and checks it parses back to the same one and
+/// that formatting it again doesn't change it.
+fn round_trip(name: &str, source: &str) -> String {
+    let file = touka::parse(source).unwrap_or_else(|e| panic!("{name}: {:?}", miette::Report::new(e)));
+    let formatted = touka::pretty::format(&file, source);
+
+    let again = touka::parse(&formatted)
+        .unwrap_or_else(|e| panic!("{name} formatted doesn't parse:\n{formatted}\n{:?}", miette::Report::new(e)));
+    assert_eq!(shape(&file.expression), shape(&again.expression), "{name} formatted:\n{formatted}");
+    assert_eq!(touka::format(&formatted).unwrap(), formatted, "{name} formatted twice is different");
+
+    formatted
+}
+
+#[test]
+fn the_programs_round_trip() {
+    for program in common::programs() {
+        let source = std::fs::read_to_string(&program).unwrap();
+        round_trip(&program.display().to_string(), &source);
+    }
+}
+
+#[test]
+fn the_comments_are_kept_in_order() {
+    let formatted = round_trip("the commented program", COMMENTED);
+
+    let comments = |text: &str| touka::pretty::comments(text).into_iter().map(|c| text[c].to_string()).collect();
+    let (before, after): (Vec<String>, Vec<String>) = (comments(COMMENTED), comments(&formatted));
+    assert_eq!(before, after);
+
+    assert!(formatted.contains("let a = 1; // one\n"));
+    assert!(formatted.contains("} else {\n  // else start\n  print(b)\n}"));
+    assert!(formatted.ends_with("print(t)\n// the end\n"));
+
+    // The comments stay in the line and the term they're in.
+    assert!(formatted.contains("let h = fn (a, /* c1 */ b) => {\n  a + b // c2\n};"));
+    assert!(formatted.contains("  (0, _) => \"zero\", // first\n  _ => \"other\" // second\n};"));
+    assert!(formatted.contains("let l = [1, /* two */ 2];"));
+    assert!(formatted.contains("let s = 1 + // wrapped\n  2;"));
+}
+
+#[test]
+fn generated_programs_round_trip() {
+    for seed in 0..200 {
+        let file = Generator::new(&entropy(seed, 256)).file();
+        let formatted = touka::pretty::format(&file, "");
+
+        let again = touka::parse(&formatted).unwrap_or_else(|e| panic!("{formatted}\n{:?}", miette::Report::new(e)));
+        assert_eq!(shape(&file.expression), shape(&again.expression), "seed {seed}:\n{formatted}");
+    }
+}
+
+#[test]
+fn fmt_checks_and_rewrites_files() {
+    let dir = std::env::temp_dir().join(format!("touka-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("messy.rinha");
+    std::fs::write(&path, "let x=1;print( x+2 )").unwrap();
+
+    let fmt = |args: &[&str]| common::run(common::touka().arg("fmt").args(args).arg(&path));
+    assert!(!fmt(&["--check"]).status.success());
+    assert!(fmt(&[]).status.success());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "let x = 1;\nprint(x + 2)\n");
+    assert!(fmt(&["--check"]).status.success());
+
+    std::fs::remove_dir_all(Path::new(&dir)).unwrap();
+}
@@ -0,0 +1,121 @@
+//! The fuzz targets of `fuzz/`, offline: the inputs come from seeds
+//! instead of libFuzzer, so they run with the rest of the tests.
+//!
+//! `FUZZ_ITERATIONS` says how many inputs each one tries. A generated
+//! program that fails is printed, and written as its JSON AST, which
+//! `touka` runs.
+
+mod common;
+
+use std::process::{Command, Stdio};
+use touka::generator::{entropy, Generator};
+
+fn iterations(default: u64) -> u64 {
+    std::env::var("FUZZ_ITERATIONS").ok().and_then(|n| n.parse().ok()).unwrap_or(default)
+}
+
+/// Parses some text, the errors are fine but a panic isn't.
+fn parse(text: &[u8]) {
+    let text = String::from_utf8_lossy(text);
+    let _ = touka::parser::parse_or_report("<fuzz>", &text);
+}
+
+/// Fails with the program, written where it can be run again.
+fn report(file: &touka::ast::File, seed: u64, what: &str) -> ! {
+    let path = std::env::temp_dir().join(format!("touka-fuzz-{seed}.json"));
+    std::fs::write(&path, serde_json::to_string_pretty(file).unwrap()).unwrap();
+
+    let program = touka::pretty::format(file, "");
+    panic!("seed {seed}: {what}, the program is at {}:\n\n{program}", path.display());
+}
+
+fn interpret(file: &touka::ast::File) -> Result<String, String> {
+    let mut stdout = vec![];
+    match touka::interpret(file, &mut stdout) {
+        Ok(()) => Ok(String::from_utf8_lossy(&stdout).into_owned()),
+        Err(touka::InterpretError::Compile(e)) => Err(format!("{:?}", miette::Report::new(e))),
+        Err(touka::InterpretError::Runtime(e)) => Err(e.to_string()),
+    }
+}
+
+#[test]
+fn the_parser_takes_random_bytes() {
+    for seed in 0..iterations(2000) {
+        let len = seed as usize % 200;
+        parse(&entropy(seed, len));
+    }
+}
+
+#[test]
+fn the_parser_takes_mangled_programs() {
+    let programs: Vec<_> = common::programs().iter().map(|path| std::fs::read(path).unwrap()).collect();
+
+    // The recovery from the errors is slow, so these are fewer.
+    for seed in 0..iterations(500) {
+        let mut program = programs[seed as usize % programs.len()].clone();
+        let bytes = entropy(seed, 16);
+
+        // Some bytes replaced, removed or repeated.
+        for chunk in bytes.chunks(4) {
+            let at = u16::from_le_bytes([chunk[0], chunk[1]]) as usize % (program.len() + 1);
+            match chunk[2] % 3 {
+                0 if at < program.len() => program[at] = chunk[3],
+                1 if at < program.len() => drop(program.remove(at)),
+                _ => program.insert(at, b"(){};,=>\"0a_ /*"[chunk[3] as usize % 15]),
+            }
+        }
+
+        parse(&program);
+    }
+}
+
+#[test]
+fn the_parser_takes_long_literals() {
+    for text in ["2147483647", "2147483648", "99999999999999999999999", "print(1 + 123456789012345)"] {
+        parse(text.as_bytes());
+    }
+
+    let error = touka::parse("print(9999999999)").unwrap_err();
+    assert!(format!("{:?}", miette::Report::new(error)).contains("too large"));
+}
+
+#[test]
+fn generated_programs_run() {
+    for seed in 0..iterations(500) {
+        let file = Generator::new(&entropy(seed, 256)).file();
+        if let Err(e) = interpret(&file) {
+            report(&file, seed, &e);
+        }
+    }
+}
+
+/// Compiling the C is slow, this one runs with `cargo test -- --ignored`.
+#[test]
+#[ignore]
+fn generated_programs_agree_on_c() {
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    let dir = std::env::temp_dir().join(format!("touka-fuzz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("program.c");
+
+    for seed in 0..iterations(50) {
+        let file = Generator::new(&entropy(seed, 256)).file();
+        let interpreted = interpret(&file).unwrap_or_else(|e| report(&file, seed, &e));
+
+        let c = touka::compile_to_c(&file).unwrap_or_else(|e| report(&file, seed, &format!("{e:?}")));
+        std::fs::write(&source, c).unwrap();
+
+        let binary = common::compile_c(&cc, &source).unwrap_or_else(|stderr| report(&file, seed, &stderr));
+
+        let output = Command::new(&binary).stdin(Stdio::null()).output().unwrap();
+        let native = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || native != interpreted {
+            report(&file, seed, &format!("the interpreter printed {interpreted:?}, C printed {native:?}"));
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
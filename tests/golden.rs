@@ -0,0 +1,164 @@
+//! The programs of `tests/programs` against what they're expected to
+//! print, `name.stdout`, and the error of the ones that fail at run time,
+//! `name.stderr`. They run on the interpreter, and on the C backend when
+//! there's a C compiler around, `$CC` or `cc`. The ones of
+//! `tests/programs/c` call C functions, they run on the C backend only,
+//! and the ones of `tests/programs/deep` recurse deeper than the other
+//! backends go.
+//!
+//! `BLESS=1 cargo test --test golden` writes the expectations from what
+//! the interpreter prints, or the C backend for those.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// What a program did.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stdout: String,
+    stderr: String,
+    success: bool,
+}
+
+impl Outcome {
+    /// The expectations of a program, it fails when it has a `.stderr`.
+    fn expected(program: &Path) -> Outcome {
+        let stdout = std::fs::read_to_string(program.with_extension("stdout"));
+        let stderr = std::fs::read_to_string(program.with_extension("stderr"));
+
+        Outcome {
+            stdout: stdout.unwrap_or_else(|_| panic!("{}: there's no `.stdout`, bless it", program.display())),
+            success: stderr.is_err(),
+            stderr: stderr.unwrap_or_default(),
+        }
+    }
+
+    fn bless(&self, program: &Path) {
+        std::fs::write(program.with_extension("stdout"), &self.stdout).unwrap();
+
+        let stderr = program.with_extension("stderr");
+        match self.success {
+            true => drop(std::fs::remove_file(stderr)),
+            false => std::fs::write(stderr, &self.stderr).unwrap(),
+        }
+    }
+}
+
+/// Parses a program and its imports, the locations are relative to the
+/// crate so the expectations are the same everywhere.
+fn load(program: &Path) -> touka::ast::File {
+    let text = std::fs::read_to_string(program).unwrap();
+    let file = touka::parser::parse_or_report(&program.to_string_lossy(), &text)
+        .unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    touka::loader::link(file, program).unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)))
+}
+
+fn interpret(program: &Path) -> Outcome {
+    let mut stdout = vec![];
+    let result = touka::interpret(&load(program), &mut stdout);
+
+    let stderr = match result {
+        Ok(()) => String::new(),
+        Err(touka::InterpretError::Runtime(e)) => format!("{e}\n"),
+        Err(touka::InterpretError::Compile(e)) => panic!("{:?}", miette::Report::new(e)),
+    };
+
+    Outcome {
+        stdout: String::from_utf8(stdout).unwrap(),
+        success: stderr.is_empty(),
+        stderr,
+    }
+}
+
+fn compile_and_run(cc: &str, program: &Path) -> Outcome {
+    let c = touka::compile_to_c(&load(program)).unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+
+    let dir = std::env::temp_dir().join("touka-golden");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let name = program.file_stem().unwrap();
+    let source = dir.join(name).with_extension("c");
+    std::fs::write(&source, c).unwrap();
+
+    let binary = common::compile_c(cc, &source).unwrap_or_else(|stderr| panic!("{stderr}"));
+
+    let output = Command::new(&binary).stdin(Stdio::null()).output().unwrap();
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.status.success(),
+    }
+}
+
+/// Runs every program, and fails with all the ones that didn't do what
+/// they were expected to.
+fn check(backend: &str, programs: &[PathBuf], run: impl Fn(&Path) -> Outcome) {
+    let mut failures = vec![];
+
+    for program in programs {
+        let (expected, found) = (Outcome::expected(program), run(program));
+        if expected != found {
+            failures.push(format!(
+                "{} on {backend}:\n  expected: {expected:?}\n  found:    {found:?}",
+                program.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n\n"));
+}
+
+#[test]
+fn interpreter_matches_the_goldens() {
+    if std::env::var_os("BLESS").is_some() {
+        for program in common::programs() {
+            interpret(&program).bless(&program);
+        }
+    }
+
+    check("the interpreter", &common::programs(), interpret);
+}
+
+#[test]
+fn c_matches_the_goldens() {
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    check("the C backend", &common::programs(), |program| compile_and_run(&cc, program));
+}
+
+#[test]
+fn c_externals_match_the_goldens() {
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    let programs = common::programs_in("tests/programs/c");
+    if std::env::var_os("BLESS").is_some() {
+        for program in &programs {
+            compile_and_run(&cc, program).bless(program);
+        }
+    }
+
+    check("the C backend", &programs, |program| compile_and_run(&cc, program));
+}
+
+#[test]
+fn deep_recursions_match_the_goldens() {
+    let programs = common::programs_in("tests/programs/deep");
+    if std::env::var_os("BLESS").is_some() {
+        for program in &programs {
+            interpret(program).bless(program);
+        }
+    }
+
+    check("the interpreter", &programs, interpret);
+
+    if let Some(cc) = common::cc() {
+        check("the C backend", &programs, |program| compile_and_run(&cc, program));
+    }
+}
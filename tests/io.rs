@@ -0,0 +1,150 @@
+//! `read_line` and `argv` on every backend that's around, with the lines
+//! given on the standard input and the arguments after `--`, and on the
+//! virtual machine of the library with its own input.
+
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+const PROGRAM: &str = "tests/programs/io/echo.rinha";
+const INPUT: &str = "hello\nwörld\r\n";
+const ARGUMENTS: [&str; 2] = ["one", "two words"];
+
+const STDOUT: &str = "hello\nwörld!\n[one, two words]\n2\n";
+const STDERR: &str =
+    "ToukaRT: error[end-of-input] at tests/programs/io/echo.rinha:7:7: there are no more lines to read\n";
+
+/// Runs a command with the input, it's the end of the input after it.
+fn feed(command: &mut Command) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the command doesn't start");
+
+    child.stdin.take().unwrap().write_all(INPUT.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn check(backend: &str, output: &Output) {
+    assert_eq!(String::from_utf8_lossy(&output.stdout), STDOUT, "on {backend}");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), STDERR, "on {backend}");
+    assert_eq!(output.status.code(), Some(1), "on {backend}");
+}
+
+/// Writes what the compiler emits for the program, with some arguments.
+fn emit(args: &[&str], path: &Path) {
+    let output = common::run(common::touka().arg(PROGRAM).args(args).arg("-o").arg(path));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn dir() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("touka-io-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn vm_reads_lines_and_arguments() {
+    let source = std::fs::read_to_string(PROGRAM).unwrap();
+    let file = touka::parse_named(PROGRAM, &source).unwrap();
+
+    let options = touka::RunOptions {
+        args: ARGUMENTS.map(String::from).to_vec(),
+        input: Some(Box::new(INPUT.as_bytes())),
+    };
+    let mut out = vec![];
+    let error = touka::interpret_with(&file, &mut out, options).unwrap_err();
+
+    assert_eq!(String::from_utf8_lossy(&out), STDOUT);
+    assert_eq!(format!("{error}\n"), STDERR);
+}
+
+/// The same, from the command line.
+#[test]
+fn the_command_line_reads_lines_and_arguments() {
+    let vm = feed(common::touka().arg(PROGRAM).args(["--backend", "vm", "--"]).args(ARGUMENTS));
+    check("the virtual machine", &vm);
+}
+
+#[test]
+fn c_reads_lines_and_arguments() {
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    let source = dir().join("echo.c");
+    emit(&["--backend", "c"], &source);
+
+    let binary = common::compile_c(&cc, &source).unwrap_or_else(|stderr| panic!("{stderr}"));
+
+    check("the C backend", &feed(Command::new(&binary).args(ARGUMENTS)));
+}
+
+#[test]
+fn js_reads_lines_and_arguments() {
+    if !common::installed("node") {
+        return;
+    }
+
+    let script = dir().join("echo.js");
+    emit(&["--emit", "js"], &script);
+    check("JavaScript", &feed(Command::new("node").arg(&script).args(ARGUMENTS)));
+}
+
+#[test]
+fn asm_reads_lines_and_arguments() {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) || !common::installed("as") {
+        return;
+    }
+
+    let (source, object, binary) = (dir().join("echo.s"), dir().join("echo.o"), dir().join("echo-asm"));
+    emit(&["--backend", "asm"], &source);
+
+    assert!(Command::new("as").arg("-o").arg(&object).arg(&source).status().unwrap().success());
+    assert!(Command::new("ld").arg("-o").arg(&binary).arg(&object).status().unwrap().success());
+    check("the assembly backend", &feed(Command::new(&binary).args(ARGUMENTS)));
+}
+
+#[test]
+fn wat_reads_lines_and_arguments() {
+    let module = dir().join("echo.wat");
+    emit(&["--emit", "wat"], &module);
+
+    let wat = std::fs::read(&module).unwrap();
+    check("WebAssembly", &common::wasm::run_with(&wat, INPUT.as_bytes(), &ARGUMENTS));
+}
+
+/// A print that can't be written is a fault where it is, on the virtual
+/// machine and on C alike. `/dev/full` fails every write.
+#[test]
+fn a_print_that_cannot_be_written_is_a_fault() {
+    let full = || std::fs::OpenOptions::new().write(true).open("/dev/full");
+    if full().is_err() {
+        return;
+    }
+
+    let program = "tests/programs/fib.rinha";
+    let vm = common::run(common::touka().arg(program).args(["--backend", "vm"]).stdout(full().unwrap()));
+    assert!(
+        String::from_utf8_lossy(&vm.stderr).starts_with("ToukaRT: error[write-error] at tests/programs/fib.rinha:"),
+        "{}",
+        String::from_utf8_lossy(&vm.stderr)
+    );
+    assert_eq!(vm.status.code(), Some(1));
+
+    let Some(cc) = common::cc() else {
+        return;
+    };
+
+    let source = dir().join("full.c");
+    let output = common::run(common::touka().arg(program).arg("-o").arg(&source));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let binary = common::compile_c(&cc, &source).unwrap_or_else(|stderr| panic!("{stderr}"));
+    let c = common::run(Command::new(binary).stdout(full().unwrap()));
+    common::assert_same("the C backend", &vm, &c);
+}
@@ -0,0 +1,26 @@
+//! The scripts of `--emit js` against the virtual machine, they run under
+//! `node`.
+
+mod common;
+
+use std::process::Command;
+
+#[test]
+fn js_matches_the_vm() {
+    if !common::installed("node") {
+        return;
+    }
+
+    for program in common::programs() {
+        let name = program.file_stem().unwrap().to_string_lossy();
+
+        let js = common::run(common::touka().arg(&program).args(["--emit", "js"]));
+        assert!(js.status.success(), "{name}: {}", String::from_utf8_lossy(&js.stderr));
+
+        let script = std::env::temp_dir().join(format!("touka-{name}.js"));
+        std::fs::write(&script, &js.stdout).unwrap();
+
+        let found = common::run(Command::new("node").arg(&script));
+        common::assert_same(&name, &common::vm(&program), &found);
+    }
+}
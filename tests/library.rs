@@ -0,0 +1,17 @@
+//! The compiler as a library, the way an embedder uses it.
+
+#[test]
+fn a_parse_doesnt_change_the_locations_of_another() {
+    let file = touka::parse("let x = 1;\nlet z = 2;\nprint(y)").unwrap();
+    touka::parse("print(1)").unwrap();
+
+    let error = touka::compile_to_c(&file).unwrap_err();
+    let report = format!("{:?}", miette::Report::new(error));
+    assert!(report.contains("3 │ print(y)"), "{report}");
+}
+
+#[test]
+fn the_files_are_named() {
+    let error = touka::parse_named("main.rinha", "print(").unwrap_err();
+    assert!(format!("{:?}", miette::Report::new(error)).contains("main.rinha"));
+}
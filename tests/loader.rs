@@ -0,0 +1,86 @@
+//! The imports of `tests/programs/imports`: the paths are relative to the
+//! file that has them, every file is a single module however many import
+//! it, and a cycle is an error.
+
+use std::path::Path;
+
+use touka::ast::{Element, Term};
+use touka::loader::LoadError;
+
+fn link(program: &str) -> Result<touka::ast::File, LoadError> {
+    let path = Path::new("tests/programs/imports").join(program);
+    let text = std::fs::read_to_string(&path).unwrap();
+    let file = touka::parser::parse_or_report(&path.to_string_lossy(), &text)?;
+    touka::loader::link(file, &path)
+}
+
+/// The names of the modules bound before the program.
+fn modules(mut term: &Term) -> Vec<String> {
+    let mut names = vec![];
+    while let Term::Let(binding) = term {
+        if binding.name.text.starts_with('#') {
+            names.push(binding.name.text.clone());
+        }
+        term = &binding.next;
+    }
+
+    names
+}
+
+#[test]
+fn imports_are_relative_and_loaded_once() {
+    let file = link("main.rinha").unwrap_or_else(|e| panic!("{:?}", miette::Report::new(e)));
+    assert_eq!(modules(&file.expression), ["#module0", "#module1"]);
+
+    let mut stdout = vec![];
+    touka::interpret(&file, &mut stdout).unwrap();
+    assert_eq!(String::from_utf8(stdout).unwrap(), "loading shared\n42\n8\n");
+}
+
+#[test]
+fn import_cycles_are_errors() {
+    let Err(error) = link("cycle/a.rinha") else {
+        panic!("the cycle was linked");
+    };
+
+    let LoadError::Cycle { chain, .. } = &error else {
+        panic!("{:?}", miette::Report::new(error));
+    };
+
+    // The paths are as the imports wrote them, not absolute.
+    let files: Vec<_> = chain.split(" -> ").collect();
+    assert_eq!(
+        files,
+        ["tests/programs/imports/cycle/a.rinha", "tests/programs/imports/cycle/b.rinha", "tests/programs/imports/cycle/a.rinha"]
+    );
+}
+
+#[test]
+fn an_imported_tail_that_does_something_is_an_error() {
+    let Err(error) = link("tail/main.rinha") else {
+        panic!("the file was linked");
+    };
+
+    assert!(matches!(error, LoadError::UnusedTail { .. }), "{:?}", miette::Report::new(error));
+    let report = format!("{:?}", miette::Report::new(error));
+    assert!(report.contains("[tests/programs/imports/tail/effect.rinha:"), "{report}");
+}
+
+#[test]
+fn the_files_are_named_from_the_imports() {
+    let file = link("main.rinha").unwrap();
+    let Term::Let(shared) = &file.expression else {
+        panic!("there are no modules");
+    };
+
+    // `lib/helper.rinha` imports it again as `../shared.rinha`.
+    assert_eq!(shared.value.location().filename(), "tests/programs/imports/shared.rinha");
+}
+
+#[test]
+fn missing_imports_are_errors() {
+    let text = r#"import "nowhere.rinha" as nowhere; 0"#;
+    let file = touka::parser::parse_or_report("tests/programs/imports/missing.rinha", text).unwrap();
+    let error = touka::loader::link(file, Path::new("tests/programs/imports/missing.rinha")).unwrap_err();
+    assert!(matches!(error, LoadError::Unreadable { ref path, .. } if path == "nowhere.rinha"));
+}
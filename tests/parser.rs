@@ -0,0 +1,115 @@
+//! What the parser reports, and what it makes of the names.
+
+use miette::Diagnostic;
+
+/// The codes and the messages of the errors of a source that doesn't
+/// parse.
+fn errors(source: &str) -> Vec<(String, String)> {
+    let error = touka::parse(source).unwrap_err();
+    error
+        .related()
+        .unwrap()
+        .map(|error| (error.code().unwrap().to_string(), error.to_string()))
+        .collect()
+}
+
+/// The codes of the errors of a source that doesn't parse, with where
+/// they point.
+fn spans(source: &str) -> Vec<(String, usize)> {
+    let error = touka::parse(source).unwrap_err();
+    error
+        .related()
+        .unwrap()
+        .map(|error| {
+            let span = error.labels().unwrap().next().unwrap();
+            (error.code().unwrap().to_string(), span.offset())
+        })
+        .collect()
+}
+
+fn run(source: &str) -> String {
+    let file = touka::parse(source).unwrap();
+    let mut out = vec![];
+    touka::interpret(&file, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn the_builtins_are_names() {
+    assert_eq!(run("let len = 3; print(len)"), "3\n");
+    assert_eq!(run("let head = fn (x) => { x }; print(head(1))"), "1\n");
+    assert_eq!(run("let f = fn (second) => { second + 1 }; print(f(1))"), "2\n");
+    assert_eq!(run("let xs = [1, 2]; print(len(xs))"), "2\n");
+}
+
+/// The body of a function can be `let`s without braces, they end at the
+/// first term that isn't one.
+#[test]
+fn a_function_body_can_be_lets() {
+    assert_eq!(run("let f = fn (x) => let y = x + 1; y; print(f(1))"), "2\n");
+    assert_eq!(run("let f = fn (p) => let (a, b) = p; let c = a * b; c + 1;\nprint(f((2, 3)))"), "7\n");
+}
+
+#[test]
+fn a_builtin_takes_its_arguments() {
+    assert_eq!(
+        errors("print(head([1], 2))"),
+        [("zu::builtin_arguments".to_string(), "`head` takes 1 argument, not 2".to_string())]
+    );
+    assert_eq!(
+        errors("print(argv(1))"),
+        [("zu::builtin_arguments".to_string(), "`argv` takes 0 arguments, not 1".to_string())]
+    );
+}
+
+#[test]
+fn the_keywords_are_reserved() {
+    let errors = errors("let match = 1;\nprint(2)");
+    assert_eq!(errors[0], ("zu::reserved_word".to_string(), "`match` is a reserved word".to_string()));
+}
+
+/// A `let` where a term goes is misplaced, it isn't used as a name.
+#[test]
+fn a_let_in_an_expression_is_not_a_name() {
+    let errors = errors("print(1 + let x = 1; x)");
+    assert_eq!(errors[0].0, "zu::unrecognized_token");
+}
+
+/// The error that stops the parser comes with the ones it got past, all
+/// of them in the order of the source.
+#[test]
+fn the_fatal_error_is_reported_with_the_others() {
+    assert_eq!(
+        spans("print({ a: 1, a: 99999999999 }"),
+        [
+            ("zu::duplicate_field".to_string(), 14),
+            ("zu::integer_too_large".to_string(), 17),
+            ("zu::expected_token".to_string(), 30),
+        ]
+    );
+    assert_eq!(
+        spans("let f = fn (x) => ;\nprint("),
+        [("zu::expected_function_body".to_string(), 8), ("zu::expected_token".to_string(), 26)]
+    );
+}
+
+/// Every error is reported once, however the parser got to it.
+#[test]
+fn the_errors_are_reported_once() {
+    let source = std::fs::read_to_string("tests/programs/data.rinha").unwrap();
+    for end in (1..source.len()).filter(|end| source.is_char_boundary(*end)) {
+        let Err(error) = touka::parse(&source[..end]) else {
+            continue;
+        };
+
+        let mut errors: Vec<_> = error
+            .related()
+            .unwrap()
+            .map(|error| (error.labels().unwrap().next().unwrap().offset(), error.to_string()))
+            .collect();
+        let reported = errors.len();
+        errors.sort();
+        errors.dedup();
+        assert_eq!(errors.len(), reported, "{:?}", &source[..end]);
+    }
+}
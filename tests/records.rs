@@ -0,0 +1,41 @@
+//! The records that are known to be wrong before the program runs: a
+//! field written twice doesn't parse, and a field that a record doesn't
+//! have doesn't compile on any backend, where the shape of the record is
+//! known.
+
+mod common;
+
+use miette::Diagnostic;
+
+#[test]
+fn a_field_is_written_once() {
+    let error = touka::parse("let p = { x: 1, y: 2, x: 3 };\nprint(p.x)").unwrap_err();
+    let errors: Vec<_> = error.related().unwrap().collect();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code().unwrap().to_string(), "zu::duplicate_field");
+    assert_eq!(errors[0].labels().unwrap().next().unwrap().offset(), 22);
+}
+
+#[test]
+fn a_record_has_the_fields_it_is_accessed_by() {
+    let file = touka::parse("let p = { x: 1, y: 2 };\nprint(p.z)").unwrap();
+
+    for (backend, report) in common::compile_everywhere_err(&file) {
+        assert!(report.contains("the record has no field `z`"), "on {backend}: {report}");
+        assert!(report.contains("the fields are: x, y"), "on {backend}: {report}");
+        assert!(report.contains("2 │ print(p.z)"), "on {backend}: {report}");
+    }
+}
+
+/// The shape of a parameter isn't known, the field is looked up when it
+/// runs.
+#[test]
+fn the_virtual_machine_reports_a_missing_field() {
+    let file = touka::parse("let get = fn (p) => { p.z };\nprint(get({ x: 1, y: 2 }))").unwrap();
+
+    let Err(touka::InterpretError::Runtime(error)) = touka::interpret(&file, std::io::sink()) else {
+        panic!("the field was found");
+    };
+    assert!(error.to_string().contains("error[no-such-field] at <input>:1:23"), "{error}");
+}
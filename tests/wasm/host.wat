@@ -0,0 +1,48 @@
+;; The host of the modules of `--emit wat` under a WASI runtime, it's
+;; preloaded as `touka`:
+;;
+;;     wasmtime run --preload touka=tests/wasm/host.wat program.wat
+;;
+;; It keeps the vectors of its reads and writes in the first 32 bytes of
+;; the memory, which the modules leave alone.
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_sizes_get"
+    (func $args_sizes_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+
+  (memory (export "memory") 1)
+
+  ;; Writes the bytes and a line break.
+  (func $line (param $fd i32) (param $ptr i32) (param $len i32)
+    (i32.store8 (i32.const 24) (i32.const 10))
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (i32.store (i32.const 8) (i32.const 24))
+    (i32.store (i32.const 12) (i32.const 1))
+    (drop (call $fd_write (local.get $fd) (i32.const 0) (i32.const 2) (i32.const 16))))
+
+  (func (export "print") (param $ptr i32) (param $len i32)
+    (call $line (i32.const 1) (local.get $ptr) (local.get $len)))
+
+  (func (export "error") (param $ptr i32) (param $len i32)
+    (call $line (i32.const 2) (local.get $ptr) (local.get $len))
+    (call $proc_exit (i32.const 1)))
+
+  ;; How many bytes of the standard input it read, 0 at its end.
+  (func (export "read") (param $ptr i32) (param $len i32) (result i32)
+    (i32.store (i32.const 0) (local.get $ptr))
+    (i32.store (i32.const 4) (local.get $len))
+    (if (result i32) (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8))
+      (then (i32.const 0))
+      (else (i32.load (i32.const 8)))))
+
+  (func (export "args_sizes") (param $argc i32) (param $size i32)
+    (drop (call $args_sizes_get (local.get $argc) (local.get $size))))
+
+  (func (export "args") (param $argv i32) (param $buf i32)
+    (drop (call $args_get (local.get $argv) (local.get $buf)))))
@@ -0,0 +1,60 @@
+//! The modules of `--emit wat`: they must assemble and validate, and run
+//! like on the virtual machine, on the host in `tests/common/wasm.rs` and
+//! under `wasmtime` with the host in `tests/wasm/host.wat`.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+fn emit(program: &Path) -> Vec<u8> {
+    let wat = common::run(common::touka().arg(program).args(["--emit", "wat"]));
+    assert!(wat.status.success(), "{}: {}", program.display(), String::from_utf8_lossy(&wat.stderr));
+    wat.stdout
+}
+
+#[test]
+fn wat_is_valid() {
+    let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasm/host.wat");
+    let modules = common::programs().into_iter().map(|program| (program.display().to_string(), emit(&program)));
+
+    for (name, wat) in modules.chain([(host.display().to_string(), std::fs::read(&host).unwrap())]) {
+        let binary = wat::parse_bytes(&wat).unwrap_or_else(|e| panic!("{name}: {e}"));
+        if let Err(e) = wasmparser::Validator::new().validate_all(&binary) {
+            panic!("{name}: {e}");
+        }
+    }
+}
+
+#[test]
+fn wat_matches_the_vm() {
+    for program in common::programs() {
+        let name = program.file_stem().unwrap().to_string_lossy();
+        common::assert_same(&name, &common::vm(&program), &common::wasm::run(&emit(&program)));
+    }
+}
+
+/// The same, under a WASI runtime. Run it with `cargo test -- --ignored`
+/// where `wasmtime` is installed.
+#[test]
+#[ignore = "needs wasmtime"]
+fn wat_matches_the_vm_on_wasmtime() {
+    let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/wasm/host.wat");
+
+    for program in common::programs() {
+        let name = program.file_stem().unwrap().to_string_lossy();
+
+        let module = std::env::temp_dir().join(format!("touka-{name}.wat"));
+        std::fs::write(&module, emit(&program)).unwrap();
+
+        let found = Command::new("wasmtime")
+            .arg("run")
+            .arg("--preload")
+            .arg(format!("touka={}", host.display()))
+            .arg(&module)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("`wasmtime` isn't installed");
+        common::assert_same(&name, &common::vm(&program), &found);
+    }
+}